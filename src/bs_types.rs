@@ -31,11 +31,21 @@ pub enum DataType {
 
     Tuple(Box<Vec<DataType>>), // Mixed types (fixed size)
 
+    Channel(Box<DataType>), // Type of the values sent through the channel
+
     Union(Box<Vec<DataType>>), // Union of types
 
+    // Type parameter of a generic function, worked out from the arguments at each call
+    Generic(String, Box<DataType>), // Name, Constraint (Inferred if it can be any type)
+
     None, // The None result of an option
 }
 
+// Numbers can be either Ints or Floats
+pub fn number_type() -> DataType {
+    DataType::Union(Box::new(vec![DataType::Int, DataType::Float]))
+}
+
 pub fn return_datatype(node: &AstNode) -> DataType {
     match node {
         AstNode::RuntimeExpression(_, datatype) => datatype.clone(),
@@ -52,8 +62,16 @@ pub fn return_datatype(node: &AstNode) -> DataType {
             }
             _ => DataType::Inferred,
         },
-        AstNode::VarReference(_, datatype) | AstNode::ConstReference(_, datatype) => {
-            datatype.clone()
+        AstNode::VarReference(_, datatype)
+        | AstNode::ConstReference(_, datatype)
+        | AstNode::ChannelReceive(_, datatype)
+        | AstNode::FunctionCall(_, _, datatype)
+        | AstNode::TupleAccess(_, _, datatype)
+        | AstNode::CollectionAccess(_, _, datatype) => datatype.clone(),
+        AstNode::TypeCheck(..) => DataType::Bool,
+        AstNode::Copy(value) => return_datatype(value),
+        AstNode::AnonymousFunction(args, _, return_type) => {
+            DataType::Function(Box::new(args.to_owned()), Box::new(return_type.to_owned()))
         }
         AstNode::Tuple(nodes, _) => {
             let mut types: Vec<DataType> = Vec::new();
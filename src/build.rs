@@ -3,26 +3,36 @@ use crate::html_output::dom_hooks::{generate_dom_update_js, DOMUpdate};
 use crate::html_output::generate_html::create_html_boilerplate;
 use crate::html_output::web_parser;
 use crate::parsers::ast_nodes::{AstNode, Reference};
+use crate::parsers::dead_code::remove_dead_code;
+use crate::parsers::scope::ScopeStack;
 use crate::settings::{get_default_config, get_html_config, Config};
 use crate::tokenizer;
 use crate::tokens::Token;
+use crate::wasm_output::js_glue::{glue_import, new_js_glue};
+use crate::wasm_output::wasi::new_wasi_module;
+use crate::wasm_output::wasm_module::WasmModule;
+use crate::wasm_output::wasm_optimiser::optimise_module;
+use crate::wasm_output::wat_parser::new_page_module;
 use crate::{parsers, settings};
 
-use colour::{blue_ln, dark_cyan_ln, dark_yellow_ln, green_ln, print_bold, print_ln_bold, red_ln};
+use colour::{
+    blue_ln, dark_cyan_ln, dark_yellow_ln, green_ln, print_bold, print_ln_bold, red_ln, yellow_ln,
+};
 use std::error::Error;
 use std::ffi::OsStr;
 use std::fs;
 use std::path::PathBuf;
 use std::time::Instant;
-use wat::parse_str;
 
 pub struct OutputFile {
-    source_code: String,
-    file: PathBuf,
-    compiled_code: String,
-    wasm: Vec<u8>,
-    imports: Vec<PathBuf>,
-    global: bool,
+    pub source_code: String,
+    pub file: PathBuf,
+    pub compiled_code: String,
+    pub wasm: Vec<u8>,
+    pub js_glue: String, // JS module that loads the WASM module
+    pub wat: String,     // Text format of the WASM module, only written in dev builds
+    pub imports: Vec<PathBuf>,
+    pub global: bool,
 }
 pub struct ExportedJS {
     pub js: String,
@@ -32,8 +42,22 @@ pub struct ExportedJS {
     pub data_type: DataType,
 }
 
+// Where the compiled project runs
+#[derive(Clone, Copy, PartialEq)]
+pub enum Target {
+    Web,  // HTML pages that each have a WASM module
+    Wasi, // A WASM module for each file that runs on its own in a WASI runtime
+}
+
+// The page HTML, its WASM module, the JS glue for the module, and the paths of its imports
+pub type CompiledPage = (String, WasmModule, String, Vec<PathBuf>);
+
 #[allow(unused_variables)]
-pub fn build(entry_path: String, release_build: bool) -> Result<(), Box<dyn Error>> {
+pub fn build(
+    entry_path: String,
+    release_build: bool,
+    target: Target,
+) -> Result<(), Box<dyn Error>> {
     // Change default output directory to dev if release_build is true
     let project_config = get_default_config();
     let output_dir_folder = if release_build {
@@ -105,6 +129,8 @@ pub fn build(entry_path: String, release_build: bool) -> Result<(), Box<dyn Erro
                 file: file_path,
                 compiled_code: String::new(),
                 wasm: Vec::new(),
+                js_glue: String::new(),
+                wat: String::new(),
                 imports: Vec::new(),
                 global: false,
             });
@@ -139,12 +165,17 @@ pub fn build(entry_path: String, release_build: bool) -> Result<(), Box<dyn Erro
         }
     }
 
+    if target == Target::Wasi {
+        return build_wasi(&source_code_to_parse, release_build);
+    }
+
     let mut exported_js: Vec<ExportedJS> = Vec::new();
     let mut exported_css = String::new();
 
     // Compile all output files
     // And collect all exported functions and variables from the module
     // After compiling, collect all imported modules and add them to the list of exported modules
+    let mut failed_files = 0;
     for file in &mut source_code_to_parse {
         match compile(
             &file,
@@ -153,17 +184,29 @@ pub fn build(entry_path: String, release_build: bool) -> Result<(), Box<dyn Erro
             &mut exported_js,
             &mut exported_css,
         ) {
-            Ok((compiled_code, wasm, imports)) => {
+            Ok((compiled_code, wasm_module, js_glue, imports)) => {
+                // Dev builds keep the WAT so it can be debugged
+                if let Err(e) = add_page_module(file, &wasm_module, !release_build) {
+                    red_ln!("Error creating WASM module: {}", e);
+                    failed_files += 1;
+                    continue;
+                }
                 file.compiled_code = compiled_code;
-                file.wasm = wasm;
+                file.js_glue = js_glue;
                 file.imports.extend(imports);
             }
             Err(e) => {
                 red_ln!("Error compiling file: {:?}", e);
+                failed_files += 1;
             }
         }
     }
 
+    // Nothing is written if a file failed, so a page with a broken module is never served
+    if failed_files > 0 {
+        return Err(format!("{failed_files} file(s) failed to compile").into());
+    }
+
     // Add imports and globals to the compiled code of the files
     for file in &mut source_code_to_parse {
         // Add the imports to the files source code importing them after compiling all of them
@@ -275,6 +318,8 @@ pub fn add_bs_files_to_parse(
                         file: output_file_dir.join(file_name).with_extension("html"),
                         compiled_code: String::new(),
                         wasm: Vec::new(),
+                        js_glue: String::new(),
+                        wat: String::new(),
                         imports: Vec::new(),
                         global,
                     };
@@ -318,6 +363,8 @@ pub fn add_bs_files_to_parse(
                                     file: output_file_dir.join(file_name),
                                     compiled_code: String::new(),
                                     wasm: Vec::new(),
+                                    js_glue: String::new(),
+                                    wat: String::new(),
                                     imports: Vec::new(),
                                     global: false,
                                 });
@@ -337,13 +384,13 @@ pub fn add_bs_files_to_parse(
     Ok(())
 }
 
-fn compile(
+pub fn compile(
     output: &OutputFile,
     release_build: bool,
     config: &Config,
     exported_js: &mut Vec<ExportedJS>,
     exported_css: &mut String,
-) -> Result<(String, Vec<u8>, Vec<PathBuf>), Box<dyn Error>> {
+) -> Result<CompiledPage, Box<dyn Error>> {
     print_bold!("\nCompiling: ");
 
     let file_name = output
@@ -392,7 +439,7 @@ fn compile(
         tokens,
         &mut 0,
         &token_line_numbers,
-        globals,
+        &mut ScopeStack::new(globals),
         &DataType::None,
         true,
     );
@@ -401,6 +448,17 @@ fn compile(
     green_ln!("{:?}", time.elapsed());
     let time = Instant::now();
 
+    let (ast, imports, warnings) = match remove_dead_code(ast, imports) {
+        Ok(output) => output,
+        Err(e) => {
+            red_ln!("Failed to Compile due to Error: \n\n{:?}", e);
+            return Err(e.into());
+        }
+    };
+    for warning in warnings {
+        yellow_ln!("Warning: {}", warning);
+    }
+
     // find the imports
     let mut import_requests = Vec::new();
     for import in imports {
@@ -441,6 +499,7 @@ fn compile(
         release_build,
         file_name,
         output.global,
+        None,
         exported_css,
     ) {
         Ok(output) => output,
@@ -461,34 +520,134 @@ fn compile(
         .replace("@page-css", &parser_output.css)
         .replace("page-title", &parser_output.page_title)
         .replace("//js", &all_js)
-        .replace("wasm-module-name", file_name);
+        .replace("//glue", &glue_import(file_name));
 
     print!("HTML/CSS/WAT/JS generated in: ");
     green_ln!("{:?}", time.elapsed());
     let time = Instant::now();
 
-    let all_parsed_wasm = &format!(
-        "(module {}(func (export \"set_wasm_globals\"){}))",
-        &parser_output.wat, parser_output.wat_globals
+    let js_glue = new_js_glue(&parser_output.wasm_exports, file_name);
+
+    // Anything the page or the glue doesn't use can be removed from the module
+    let mut wasm_module = new_page_module(
+        parser_output.wasm,
+        parser_output.wasm_globals,
+        &module_output,
+    );
+    optimise_module(
+        &mut wasm_module,
+        "set_wasm_globals",
+        &format!("{module_output}\n{js_glue}"),
+        release_build,
     );
-    let wasm = match parse_str(all_parsed_wasm) {
-        Ok(wasm) => wasm,
-        Err(e) => {
-            red_ln!("Error parsing wat to wasm: {:?}", e);
-            Vec::new()
-        }
-    };
 
-    print!("WAT parsed to WASM in: ");
+    print!("WASM module created in: ");
     green_ln!("{:?}", time.elapsed());
 
     exported_js.extend(parser_output.exported_js);
     exported_css.push_str(&parser_output.exported_css);
 
-    Ok((module_output, wasm, import_requests))
+    Ok((module_output, wasm_module, js_glue, import_requests))
+}
+
+// Encodes the WASM module of a page
+// Keeping the WAT of the module writes it next to the page, so what the compiler created can be read
+pub fn add_page_module(
+    file: &mut OutputFile,
+    wasm_module: &WasmModule,
+    keep_wat: bool,
+) -> Result<(), String> {
+    file.wasm = wasm_module.encode()?;
+    if keep_wat {
+        file.wat = wasm_module.to_wat();
+    }
+    Ok(())
 }
 
-fn write_output_file(output: &OutputFile) -> Result<(), Box<dyn Error>> {
+// WASI programs have no pages, so only the WASM module of each Beanstalk file is created
+fn build_wasi(
+    source_code_to_parse: &[OutputFile],
+    release_build: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut modules = Vec::new();
+    let mut failed_files = 0;
+    for file in source_code_to_parse {
+        // Other files in the project are only used by pages
+        if file.file.extension() != Some("html".as_ref()) {
+            continue;
+        }
+        match compile_wasi(file, release_build) {
+            Ok(wasm) => modules.push((file.file.with_extension("wasm"), wasm)),
+            Err(e) => {
+                red_ln!("Error compiling file: {:?}", e);
+                failed_files += 1;
+            }
+        }
+    }
+
+    if failed_files > 0 {
+        return Err(format!("{failed_files} file(s) failed to compile").into());
+    }
+
+    for (path, wasm) in modules {
+        if let Some(parent_dir) = path.parent() {
+            fs::create_dir_all(parent_dir)?;
+        }
+        match fs::write(&path, wasm) {
+            Ok(_) => {}
+            Err(e) => {
+                red_ln!("Error writing WASM module file: {:?}", e);
+                return Err(e.into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn compile_wasi(output: &OutputFile, release_build: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+    print_bold!("\nCompiling: ");
+    let file_name = output
+        .file
+        .file_stem()
+        .unwrap_or(OsStr::new(""))
+        .to_str()
+        .unwrap_or("");
+    dark_yellow_ln!("{:?}", file_name);
+
+    let (tokens, token_line_numbers): (Vec<Token>, Vec<u32>) =
+        tokenizer::tokenize(&output.source_code, file_name);
+
+    let (ast, imports) = parsers::build_ast::new_ast(
+        tokens,
+        &mut 0,
+        &token_line_numbers,
+        &mut ScopeStack::new(Vec::new()),
+        &DataType::None,
+        true,
+    );
+
+    // Errors in the AST are returned before anything is compiled
+    let (ast, imports, warnings) = remove_dead_code(ast, imports)?;
+    for warning in warnings {
+        yellow_ln!("Warning: {}", warning);
+    }
+    if !imports.is_empty() {
+        return Err("Importing other modules can't be used with the WASI target yet".into());
+    }
+
+    let mut wasm_module = new_wasi_module(ast)?;
+    optimise_module(&mut wasm_module, "_start", "", release_build);
+    match wasm_module.encode() {
+        Ok(wasm) => Ok(wasm),
+        Err(e) => {
+            red_ln!("Error creating WASM module: {}", e);
+            Err(e.into())
+        }
+    }
+}
+
+pub fn write_output_file(output: &OutputFile) -> Result<(), Box<dyn Error>> {
     // If the output directory does not exist, create it
     let parent_dir = match output.file.parent() {
         Some(dir) => dir,
@@ -530,6 +689,26 @@ fn write_output_file(output: &OutputFile) -> Result<(), Box<dyn Error>> {
         }
     }
 
+    // Only dev builds have the WAT of the module
+    if !output.wat.is_empty() {
+        match fs::write(output.file.with_extension("wat"), &output.wat) {
+            Ok(_) => {}
+            Err(e) => {
+                red_ln!("Error writing WAT file: {:?}", e);
+                return Err(e.into());
+            }
+        }
+    }
+
+    // Write the JS that loads the wasm file
+    match fs::write(output.file.with_extension("bs.js"), &output.js_glue) {
+        Ok(_) => {}
+        Err(e) => {
+            red_ln!("Error writing JS glue file: {:?}", e);
+            return Err(e.into());
+        }
+    }
+
     Ok(())
 }
 
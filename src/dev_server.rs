@@ -180,7 +180,7 @@ fn handle_connection(
 fn build_project(build_path: &String, release: bool) {
     dark_cyan_ln!("Building project...");
     let start = Instant::now();
-    match build::build(build_path.to_string(), release) {
+    match build::build(build_path.to_string(), release, build::Target::Web) {
         Ok(_) => {
             let duration = start.elapsed();
             grey_ln!("------------------------------------");
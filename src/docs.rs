@@ -0,0 +1,350 @@
+use crate::bs_types::{number_type, DataType};
+use crate::build::{
+    add_bs_files_to_parse, add_page_module, compile, write_output_file, OutputFile,
+};
+use crate::parsers::ast_nodes::{AstNode, Reference};
+use crate::parsers::build_ast::new_ast;
+use crate::parsers::generics::type_parameters;
+use crate::parsers::scope::ScopeStack;
+use crate::settings::{self, get_default_config};
+use crate::tokenizer;
+use crate::tokens::Token;
+
+use colour::{dark_cyan_ln, dark_yellow_ln, print_ln_bold, red_ln};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// A declaration that is shown on the API page of a module
+struct ApiItem {
+    name: String,
+    signature: String,
+    doc: String,
+}
+
+// Creates an API page for every module in the project
+// Pages are written to an 'api' folder in the dev output folder, with an index page linking to each module
+pub fn build_docs(entry_path: String) -> Result<(), Box<dyn Error>> {
+    let project_config = get_default_config();
+    let entry_dir = PathBuf::from(entry_path.replace(|c: char| c.is_whitespace(), "-"));
+
+    print_ln_bold!("Project Directory: ");
+    dark_yellow_ln!("{:?}", &entry_dir);
+
+    let mut modules: Vec<OutputFile> = Vec::new();
+    let api_dir = if entry_dir.extension() == Some("bs".as_ref()) {
+        let api_dir = entry_dir.with_file_name("api");
+        modules.push(OutputFile {
+            source_code: fs::read_to_string(&entry_dir)?,
+            file: api_dir
+                .join(entry_dir.file_stem().unwrap_or_default())
+                .with_extension("html"),
+            compiled_code: String::new(),
+            wasm: Vec::new(),
+            js_glue: String::new(),
+            wat: String::new(),
+            imports: Vec::new(),
+            global: false,
+        });
+        api_dir
+    } else {
+        let api_dir = entry_dir.join(&project_config.dev_folder).join("api");
+        let src_dir = fs::read_dir(entry_dir.join(&project_config.src))?;
+        add_bs_files_to_parse(&mut modules, src_dir, api_dir.to_owned())?;
+        api_dir
+    };
+
+    // Exports from global modules can be used by every other module
+    // Global modules are always first in the list
+    let mut globals: Vec<Reference> = Vec::new();
+    let mut documented_modules = Vec::new();
+
+    for module in modules {
+        // Other files in the project are just copied over when building
+        if module.source_code.is_empty() {
+            continue;
+        }
+
+        // The index of the API pages links to every module, so the index page of the project is named after its source file
+        let module_path = module.file.strip_prefix(&api_dir).unwrap_or(&module.file);
+        let module_name = match module_path.with_extension("").to_string_lossy() {
+            name if name == settings::INDEX_PAGE_KEYWORD => settings::COMP_PAGE_KEYWORD.to_string(),
+            name => name.to_string(),
+        }
+        .replace('#', "");
+
+        let ast = module_ast(&module.source_code, &module_name, &globals);
+        if module.global {
+            globals.extend(exported_references(&ast));
+        }
+
+        let items = collect_api_items(ast);
+        if items.is_empty() {
+            continue;
+        }
+
+        let page = OutputFile {
+            source_code: api_page(&module_name, &items),
+            file: api_dir.join(&module_name).with_extension("html"),
+            compiled_code: String::new(),
+            wasm: Vec::new(),
+            js_glue: String::new(),
+            wat: String::new(),
+            imports: Vec::new(),
+            global: false,
+        };
+        write_api_page(page)?;
+        documented_modules.push(module_name);
+    }
+
+    let index = OutputFile {
+        source_code: api_index(&documented_modules),
+        file: api_dir
+            .join(settings::INDEX_PAGE_KEYWORD)
+            .with_extension("html"),
+        compiled_code: String::new(),
+        wasm: Vec::new(),
+        js_glue: String::new(),
+        wat: String::new(),
+        imports: Vec::new(),
+        global: false,
+    };
+    write_api_page(index)?;
+
+    dark_cyan_ln!("API docs written to {:?}", api_dir);
+    Ok(())
+}
+
+fn module_ast(source_code: &str, module_name: &str, globals: &[Reference]) -> Vec<AstNode> {
+    let (tokens, token_line_numbers) = tokenizer::tokenize(source_code, module_name);
+    let (ast, _) = new_ast(
+        tokens,
+        &mut 0,
+        &token_line_numbers,
+        &mut ScopeStack::new(globals.to_vec()),
+        &DataType::None,
+        true,
+    );
+
+    ast
+}
+
+fn exported_references(ast: &[AstNode]) -> Vec<Reference> {
+    ast.iter()
+        .filter_map(|node| match node {
+            AstNode::VarDeclaration(name, _, true, data_type, _) => Some(Reference {
+                name: name.to_owned(),
+                data_type: data_type.to_owned(),
+                default_value: None,
+            }),
+            AstNode::Function(name, args, _, true, return_type) => Some(Reference {
+                name: name.to_owned(),
+                data_type: DataType::Function(
+                    Box::new(args.to_owned()),
+                    Box::new(return_type.to_owned()),
+                ),
+                default_value: None,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+// Exported declarations and any declaration with a doc comment are part of the API
+fn collect_api_items(ast: Vec<AstNode>) -> Vec<ApiItem> {
+    let mut items = Vec::new();
+    let mut doc = String::new();
+
+    for node in ast {
+        match node {
+            AstNode::DocComment(text) => {
+                doc = text;
+                continue;
+            }
+
+            AstNode::Function(name, args, _, is_exported, return_type)
+                if is_exported || !doc.is_empty() =>
+            {
+                items.push(ApiItem {
+                    signature: format!(
+                        "{}{name} {}",
+                        if is_exported { "@" } else { "" },
+                        function_signature(&args, &return_type)
+                    ),
+                    name,
+                    doc: std::mem::take(&mut doc),
+                });
+            }
+
+            AstNode::VarDeclaration(name, value, is_exported, data_type, is_const)
+                if is_exported || !doc.is_empty() =>
+            {
+                let mut signature = format!(
+                    "{}{name} {}",
+                    if is_exported { "@" } else { "" },
+                    type_signature(&data_type)
+                );
+
+                // Only constant values are known ahead of time
+                if let (true, Some(value)) = (is_const, literal_signature(&value)) {
+                    signature.push_str(&format!(" = {value}"));
+                }
+
+                items.push(ApiItem {
+                    name,
+                    signature,
+                    doc: std::mem::take(&mut doc),
+                });
+            }
+
+            _ => {}
+        }
+
+        doc.clear();
+    }
+
+    items
+}
+
+fn function_signature(args: &[Reference], return_type: &DataType) -> String {
+    // Type parameters aren't passed in, but are declared in front of the arguments
+    let type_parameters =
+        type_parameters(args)
+            .into_iter()
+            .map(|(name, constraint)| match constraint {
+                constraint if constraint == number_type() => format!("{name} Number"),
+                _ => format!("{name} Type"),
+            });
+
+    let args = type_parameters
+        .chain(args.iter().map(|arg| {
+            let mut signature = format!("{} {}", arg.name, type_signature(&arg.data_type));
+            if let Some(value) = arg.default_value.as_deref().and_then(literal_signature) {
+                signature.push_str(&format!(" = {value}"));
+            }
+            signature
+        }))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    match return_type {
+        DataType::None => format!("fn({args})"),
+        _ => format!("fn({args}) -> {}", type_signature(return_type)),
+    }
+}
+
+// Types are written the same way they are in Beanstalk code
+fn type_signature(data_type: &DataType) -> String {
+    match data_type {
+        DataType::Bool | DataType::True | DataType::False => "Bool".to_string(),
+        DataType::String | DataType::CoerseToString => "String".to_string(),
+        DataType::Float => "Float".to_string(),
+        DataType::Int => "Int".to_string(),
+        DataType::Scene => "Scene".to_string(),
+        DataType::Style => "Style".to_string(),
+        DataType::Struct => "Struct".to_string(),
+        DataType::Choice => "Choice".to_string(),
+        DataType::Type => "Type".to_string(),
+        DataType::None => "None".to_string(),
+        DataType::Inferred => String::new(),
+        DataType::Collection(inner_type) => format!("{{{}}}", type_signature(inner_type)),
+        DataType::Channel(inner_type) => format!("<<{}>>", type_signature(inner_type)),
+        DataType::Tuple(types) => format!(
+            "({})",
+            types
+                .iter()
+                .map(type_signature)
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        DataType::Union(types) => types
+            .iter()
+            .map(type_signature)
+            .collect::<Vec<String>>()
+            .join(" | "),
+        DataType::Function(args, return_type) => function_signature(args, return_type),
+        DataType::Generic(name, _) => name.to_owned(),
+    }
+}
+
+fn literal_signature(value: &AstNode) -> Option<String> {
+    match value {
+        AstNode::Literal(Token::StringLiteral(value)) => Some(format!("\"{value}\"")),
+        AstNode::Literal(Token::FloatLiteral(value)) => Some(value.to_string()),
+        AstNode::Literal(Token::IntLiteral(value)) => Some(value.to_string()),
+        AstNode::Literal(Token::BoolLiteral(value)) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+// API pages are created as Beanstalk scenes, so they are styled the same way as the rest of the project
+fn api_page(module_name: &str, items: &[ApiItem]) -> String {
+    let mut page = format!(
+        "[header center: [title(1): {}]]\n[main:\n[link(\"./{}\"): All modules]\n",
+        module_name.to_uppercase(),
+        relative_root(module_name)
+    );
+
+    for item in items {
+        page.push_str(&format!(
+            "\n## {}\n[code:\n{}\n]\n{}\n",
+            item.name,
+            item.signature,
+            escape_scene_text(&item.doc)
+        ));
+    }
+
+    page.push_str("]\n");
+    page
+}
+
+// Doc comments are written as plain text, so brackets and braces in them are turned into HTML entities
+// Otherwise they would start or end scenes on the API page
+fn escape_scene_text(text: &str) -> String {
+    text.replace('[', "&#91;")
+        .replace(']', "&#93;")
+        .replace('{', "&#123;")
+        .replace('}', "&#125;")
+}
+
+fn api_index(module_names: &[String]) -> String {
+    let mut page = String::from("[header center: [title(1): API]]\n[main:\n");
+
+    if module_names.is_empty() {
+        page.push_str("Nothing in this project is exported or documented yet.\n");
+    }
+    for module_name in module_names {
+        page.push_str(&format!("- [link(\"./{module_name}\"): {module_name}]\n"));
+    }
+
+    page.push_str("]\n");
+    page
+}
+
+// Path back to the index page from a module page
+fn relative_root(module_name: &str) -> String {
+    let depth = Path::new(module_name).components().count();
+    format!(
+        "{}{}",
+        "../".repeat(depth - 1),
+        settings::INDEX_PAGE_KEYWORD
+    )
+}
+
+fn write_api_page(mut page: OutputFile) -> Result<(), Box<dyn Error>> {
+    let config = get_default_config();
+    match compile(&page, false, &config, &mut Vec::new(), &mut String::new()) {
+        Ok((compiled_code, wasm_module, js_glue, _)) => {
+            // API pages are never debugged, so their WAT isn't written
+            add_page_module(&mut page, &wasm_module, false)?;
+            page.compiled_code = compiled_code;
+            page.js_glue = js_glue;
+        }
+        Err(e) => {
+            red_ln!("Error creating API page: {:?}", e);
+            return Err(e);
+        }
+    }
+
+    write_output_file(&page)
+}
@@ -1,5 +1,5 @@
 // For loading WASM
-WebAssembly.instantiateStreaming(fetch("./wasm-module-name.wasm")).then((obj)=>{
+WebAssembly.instantiateStreaming(fetch("./wasm-module-name.wasm"), {Math}).then(async (obj)=>{
 let wsx = obj.instance.exports;//js
 ;});
 
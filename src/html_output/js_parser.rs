@@ -1,14 +1,50 @@
 use colour::red_ln;
 
-use crate::{bs_types::DataType, parsers::ast_nodes::AstNode, settings::BS_VAR_PREFIX, Token};
+use crate::{
+    bs_types::DataType,
+    parsers::ast_nodes::AstNode,
+    settings::BS_VAR_PREFIX,
+    stdlib::{math::math_function_from_call, string::string_function_from_call},
+    Token,
+};
+
+// Values are queued until they are received, so sending never has to wait
+// Receiving waits for the next value, so the module JS has to be running in an async function
+pub const CHANNEL_JS: &str = "class BsChannel{
+    constructor(name){this.name=name;this.values=[];this.waiting=[];this.closed=false}
+    send(value){
+        if(this.closed){throw new Error(`Can't send to '${this.name}' after it is closed`)}
+        const w=this.waiting.shift();
+        if(w){w.resolve(value)}else{this.values.push(value)}
+    }
+    receive(){
+        if(this.values.length>0){return Promise.resolve(this.values.shift())}
+        if(this.closed){return Promise.reject(new Error(`Can't receive from '${this.name}' after it is closed`))}
+        return new Promise((resolve,reject)=>{this.waiting.push({resolve,reject})})
+    }
+    close(){
+        this.closed=true;
+        for(const w of this.waiting){w.reject(new Error(`'${this.name}' was closed while waiting for a value`))}
+        this.waiting=[]
+    }
+}";
 
 // Create everything necissary in JS
 // Break out pieces in WASM calls
-pub fn expression_to_js(expr: &AstNode) -> String {
+// Locals are declared inside of a JS body, so numbers in them aren't WASM globals
+pub fn expression_to_js(expr: &AstNode, locals: &[String]) -> String {
     let mut js = String::new(); //Open the template string
 
     match expr {
         AstNode::RuntimeExpression(nodes, expression_type) => {
+            // Maths and logical expressions are stored in reverse polish notation
+            if matches!(
+                expression_type,
+                DataType::Float | DataType::Int | DataType::Bool | DataType::Generic(..)
+            ) {
+                return rpn_expression_to_js(nodes, expression_type, locals);
+            }
+
             for node in nodes {
                 match node {
                     AstNode::Literal(token) => match token {
@@ -19,7 +55,7 @@ pub fn expression_to_js(expr: &AstNode) -> String {
                             js.push_str(&value.to_string());
                         }
                         Token::StringLiteral(value) => {
-                            js.push_str(&format!("\"{}\"", value));
+                            js.push_str(&string_to_js(value));
                         }
                         _ => {
                             red_ln!("unknown literal found in expression: {:?}", token);
@@ -30,10 +66,11 @@ pub fn expression_to_js(expr: &AstNode) -> String {
                     | AstNode::ConstReference(name, data_type) => {
                         // If it's a string, it will just be pure JS, no WASM
                         match data_type {
-                            DataType::String | DataType::Scene => {
-                                js.push_str(&format!(" {BS_VAR_PREFIX}{name}"))
-                            }
-                            _ => js.push_str(&format!(" wsx.get_{BS_VAR_PREFIX}{name}()")),
+                            DataType::String
+                            | DataType::Scene
+                            | DataType::Bool
+                            | DataType::Union(_) => js.push_str(&format!(" {BS_VAR_PREFIX}{name}")),
+                            _ => js.push_str(&format!(" {}", js_value_of(name, data_type, locals))),
                         }
                     }
 
@@ -53,7 +90,20 @@ pub fn expression_to_js(expr: &AstNode) -> String {
                     },
 
                     AstNode::Tuple(values, _) => {
-                        js.push_str(&format!("[{}]", combine_vec_to_js(values)));
+                        js.push_str(&format!("[{}]", combine_vec_to_js(values, locals)));
+                    }
+
+                    AstNode::FunctionCall(name, arguments, _) => {
+                        js.push_str(&function_call_to_js(name, *arguments.to_owned(), locals));
+                    }
+
+                    // Expressions inserted into a string
+                    AstNode::RuntimeExpression(..) => {
+                        js.push_str(&format!("({})", expression_to_js(node, locals)));
+                    }
+
+                    AstNode::ChannelReceive(name, _) => {
+                        js.push_str(&channel_receive_to_js(name));
                     }
 
                     _ => {
@@ -85,7 +135,10 @@ pub fn expression_to_js(expr: &AstNode) -> String {
                 js.push_str(&value.to_string());
             }
             Token::StringLiteral(value) => {
-                js.push_str(&format!("\"{}\"", value));
+                js.push_str(&string_to_js(value));
+            }
+            Token::BoolLiteral(value) => {
+                js.push_str(&value.to_string());
             }
             _ => {
                 red_ln!("unknown literal found in expression: {:?}", token);
@@ -97,18 +150,49 @@ pub fn expression_to_js(expr: &AstNode) -> String {
                 DataType::String | DataType::Scene => {
                     js.push_str(&format!("`${{{BS_VAR_PREFIX}{name}}}`"))
                 }
-                _ => js.push_str(&format!("`${{wsx.get_{BS_VAR_PREFIX}{name}()}}`")),
+                DataType::Bool
+                | DataType::Function(..)
+                | DataType::Generic(..)
+                | DataType::Union(_)
+                | DataType::Collection(_) => js.push_str(&format!("{BS_VAR_PREFIX}{name}")),
+
+                // Numbers stay numbers, so they can be returned or compared
+                DataType::Float | DataType::Int => {
+                    js.push_str(&js_value_of(name, data_type, locals))
+                }
+                _ => js.push_str(&format!("`${{{}}}`", js_value_of(name, data_type, locals))),
             }
         }
 
+        // Closures that have already been parsed into JS
+        AstNode::JS(code) => {
+            js.push_str(code);
+        }
+
         // If the expression is just a tuple,
         // then it should automatically destructure into multiple arguments like this
         AstNode::Tuple(values, _) => {
-            js.push_str(&format!("[{}]", combine_vec_to_js(values)));
+            js.push_str(&format!("[{}]", combine_vec_to_js(values, locals)));
         }
 
         AstNode::FunctionCall(name, arguments, _) => {
-            js.push_str(&function_call_to_js(name, *arguments.to_owned()));
+            js.push_str(&function_call_to_js(name, *arguments.to_owned(), locals));
+        }
+
+        AstNode::ChannelReceive(name, _) => {
+            js.push_str(&channel_receive_to_js(name));
+        }
+
+        AstNode::CollectionAccess(name, index, _) | AstNode::TupleAccess(name, index, _) => {
+            js.push_str(&format!("{BS_VAR_PREFIX}{name}[{index}]"));
+        }
+
+        AstNode::TypeCheck(name, data_type) => {
+            js.push_str(&type_check_to_js(name, data_type));
+        }
+
+        AstNode::Copy(value) => {
+            js.push_str(&copy_to_js(value, locals));
         }
 
         _ => {
@@ -119,69 +203,296 @@ pub fn expression_to_js(expr: &AstNode) -> String {
     js
 }
 
-pub fn create_reference_in_js(name: &String, data_type: &DataType) -> String {
+fn rpn_expression_to_js(
+    nodes: &Vec<AstNode>,
+    expression_type: &DataType,
+    locals: &[String],
+) -> String {
+    let mut stack: Vec<String> = Vec::new();
+
+    for node in nodes {
+        match node {
+            AstNode::BinaryOperator(op, _) | AstNode::LogicalOperator(op, _) => {
+                let right = stack.pop().unwrap_or_default();
+                let left = stack.pop().unwrap_or_default();
+                let js_op = match op {
+                    Token::Add => "+",
+                    Token::Subtract => "-",
+                    Token::Multiply => "*",
+                    Token::Divide => "/",
+                    Token::Modulus => "%",
+                    Token::Exponent => "**",
+                    Token::Equal => "===",
+                    Token::LessThan => "<",
+                    Token::LessThanOrEqual => "<=",
+                    Token::GreaterThan => ">",
+                    Token::GreaterThanOrEqual => ">=",
+                    Token::And => "&&",
+                    Token::Or => "||",
+                    _ => {
+                        red_ln!("Unsupported operator found in operator stack when parsing an expression into JS: {:?}", op);
+                        continue;
+                    }
+                };
+                stack.push(format!("({left} {js_op} {right})"));
+            }
+
+            AstNode::UnaryOperator(Token::Negative, _) => {
+                let value = stack.pop().unwrap_or_default();
+                stack.push(format!("(-{value})"));
+            }
+
+            // Ints are i64 in WASM, so they are BigInts in JS
+            // Ints in a Bool expression are being compared with other Ints
+            AstNode::Literal(Token::IntLiteral(value))
+                if matches!(expression_type, DataType::Int | DataType::Bool) =>
+            {
+                stack.push(format!("{value}n"));
+            }
+
+            // The values being compared in a logical expression
+            AstNode::RuntimeExpression(_, data_type) => {
+                let value = expression_to_js(node, locals);
+                stack.push(js_number_as(value, data_type, expression_type));
+            }
+
+            AstNode::Literal(token) => match token {
+                Token::FloatLiteral(value) => stack.push(value.to_string()),
+                Token::IntLiteral(value) => stack.push(value.to_string()),
+                Token::BoolLiteral(value) => stack.push(value.to_string()),
+                Token::StringLiteral(value) => stack.push(string_to_js(value)),
+                _ => {
+                    red_ln!("unknown literal found in expression: {:?}", token);
+                }
+            },
+
+            AstNode::VarReference(name, data_type) | AstNode::ConstReference(name, data_type) => {
+                let value = js_value_of(name, data_type, locals);
+                stack.push(js_number_as(value, data_type, expression_type));
+            }
+
+            AstNode::CollectionAccess(name, index, data_type)
+            | AstNode::TupleAccess(name, index, data_type) => {
+                let value = format!("{BS_VAR_PREFIX}{name}[{index}]");
+                stack.push(js_number_as(value, data_type, expression_type));
+            }
+
+            AstNode::FunctionCall(name, arguments, data_type) => {
+                let value = function_call_to_js(name, *arguments.to_owned(), locals);
+                stack.push(js_number_as(value, data_type, expression_type));
+            }
+
+            AstNode::ChannelReceive(name, data_type) => {
+                let value = channel_receive_to_js(name);
+                stack.push(js_number_as(value, data_type, expression_type));
+            }
+
+            _ => {
+                red_ln!(
+                    "unknown AST node found in expression when parsing an expression into JS: {:?}",
+                    node
+                );
+            }
+        }
+    }
+
+    stack.pop().unwrap_or_default()
+}
+
+// Ints are BigInts, so they are converted to Numbers when used in a Float expression
+fn js_number_as(value: String, value_type: &DataType, expression_type: &DataType) -> String {
+    match (value_type, expression_type) {
+        (DataType::Int, DataType::Float) => format!("Number({value})"),
+        _ => value,
+    }
+}
+
+// Escapes characters that would end or break the string in JS
+fn string_to_js(value: &str) -> String {
+    let mut js = String::from("\"");
+    for ch in value.chars() {
+        match ch {
+            '"' => js.push_str("\\\""),
+            '\\' => js.push_str("\\\\"),
+            '\n' => js.push_str("\\n"),
+            '\r' => js.push_str("\\r"),
+            _ => js.push(ch),
+        }
+    }
+    js.push('"');
+    js
+}
+
+// Numbers live in WASM globals, everything else is a JS variable
+fn js_value_of(name: &String, data_type: &DataType, locals: &[String]) -> String {
     match data_type {
-        DataType::String | DataType::Scene | DataType::Inferred | DataType::CoerseToString => {
-            format!("uInnerHTML(\"{name}\", {BS_VAR_PREFIX}{name});")
+        DataType::Float | DataType::Int if !locals.contains(name) => {
+            format!("wsx.get_{BS_VAR_PREFIX}{name}()")
         }
-        _ => {
-            format!("uInnerHTML(\"{name}\", wsx.get_{BS_VAR_PREFIX}{name}());")
+        _ => format!("{BS_VAR_PREFIX}{name}"),
+    }
+}
+
+pub fn assignment_to_js(
+    name: &String,
+    operator: &Token,
+    value: &AstNode,
+    data_type: &DataType,
+    locals: &[String],
+) -> String {
+    // Mutable WASM globals are updated through the exported global
+    let target = match data_type {
+        DataType::Float | DataType::Int if !locals.contains(name) => {
+            format!("wsx.{BS_VAR_PREFIX}{name}.value")
         }
+        _ => format!("{BS_VAR_PREFIX}{name}"),
+    };
+
+    let value = match data_type {
+        DataType::Float => format!("Number({})", expression_to_js(value, locals)),
+        DataType::Int => format!("BigInt({})", expression_to_js(value, locals)),
+        DataType::Union(_) => union_value_to_js(value, locals),
+        _ => expression_to_js(value, locals),
+    };
+
+    let new_value = match operator {
+        Token::AddAssign => format!("{target} + {value}"),
+        Token::SubtractAssign => format!("{target} - {value}"),
+        Token::MultiplyAssign => format!("{target} * {value}"),
+        Token::DivideAssign => format!("{target} / {value}"),
+        Token::ModulusAssign => format!("{target} % {value}"),
+        Token::ExponentAssign => format!("{target} ** {value}"),
+        Token::RootAssign => format!("{target} ** (1 / {value})"),
+
+        // Floored, so the result always has the same sign as the divisor
+        Token::RemainderAssign => format!("(({target} % {value}) + {value}) % {value}"),
+        _ => value,
+    };
+
+    // Anything on the page showing a module variable is updated
+    if locals.contains(name) {
+        return format!("{target} = {new_value};");
     }
+    format!("{target} = {new_value};bs_changed(\"{name}\");")
 }
 
-pub fn function_call_to_js(name: &String, argument: AstNode) -> String {
-    let mut js = format!("{BS_VAR_PREFIX}{name}(");
+// Shown on the page again whenever the variable changes
+pub fn create_reference_in_js(name: &String, data_type: &DataType) -> String {
+    let value = match data_type {
+        DataType::String
+        | DataType::Scene
+        | DataType::Bool
+        | DataType::Inferred
+        | DataType::CoerseToString
+        | DataType::Collection(_)
+        | DataType::Union(_) => format!("{BS_VAR_PREFIX}{name}"),
+        _ => format!("wsx.get_{BS_VAR_PREFIX}{name}()"),
+    };
+
+    format!("bs_render(\"{name}\",()=>uInnerHTML(\"{name}\", {value}));")
+}
+
+pub fn function_call_to_js(name: &String, argument: AstNode, locals: &[String]) -> String {
+    // Multiple arguments are passed in as a tuple
+    let args = match argument {
+        AstNode::Empty => Vec::new(),
+        AstNode::Tuple(values, _) => values
+            .iter()
+            .map(|value| argument_to_js(value, locals))
+            .collect(),
+        _ => vec![argument_to_js(&argument, locals)],
+    };
+
+    if let Some(function) = string_function_from_call(name) {
+        return format!("{} ", function.call_to_js(&args));
+    }
+
+    let args = args.join(",");
+    match math_function_from_call(name) {
+        Some(function) => format!("{}({args}) ", function.js),
+        None => format!("{BS_VAR_PREFIX}{name}({args}) "),
+    }
+}
 
+fn argument_to_js(argument: &AstNode, locals: &[String]) -> String {
     match argument {
-        AstNode::Empty => {}
         AstNode::Literal(token) => match token {
-            Token::StringLiteral(value) => {
-                js.push_str(&format!("\"{}\",", value));
-            }
-            Token::FloatLiteral(value) => {
-                js.push_str(&format!("{},", value));
-            }
-            Token::IntLiteral(value) => {
-                js.push_str(&format!("{},", value));
-            }
-            Token::BoolLiteral(value) => {
-                js.push_str(&format!("{},", value));
-            }
-            _ => {}
+            Token::StringLiteral(value) => string_to_js(value),
+            Token::FloatLiteral(value) => value.to_string(),
+            Token::IntLiteral(value) => format!("{value}n"),
+            Token::BoolLiteral(value) => value.to_string(),
+            _ => String::new(),
         },
         AstNode::CollectionAccess(collection_name, index_accessed, _)
         | AstNode::TupleAccess(collection_name, index_accessed, _) => {
-            js.push_str(&format!("{collection_name}[{index_accessed}],"));
+            format!("{BS_VAR_PREFIX}{collection_name}[{index_accessed}]")
         }
-        AstNode::RuntimeExpression(expr, data_type) => {
-            js.push_str(&format!(
-                "{},",
-                expression_to_js(&AstNode::RuntimeExpression(
-                    expr.clone(),
-                    data_type.to_owned(),
-                ))
-            ));
-        }
-        AstNode::VarReference(name, _) | AstNode::ConstReference(name, _) => {
-            js.push_str(&format!("{},", name));
+        AstNode::RuntimeExpression(..) | AstNode::JS(_) => expression_to_js(argument, locals),
+        AstNode::VarReference(name, data_type) | AstNode::ConstReference(name, data_type) => {
+            js_value_of(name, data_type, locals)
         }
         AstNode::FunctionCall(function_name, args, _) => {
-            js.push_str(&function_call_to_js(&function_name, *args));
+            function_call_to_js(function_name, *args.to_owned(), locals)
         }
+        AstNode::ChannelReceive(name, _) => channel_receive_to_js(name),
+        AstNode::Copy(value) => copy_to_js(value, locals),
         _ => {
             red_ln!(
                 "Web Parser Error: Invalid argument type for function call: {:?}",
                 argument
             );
+            String::new()
         }
     }
+}
 
-    js.push_str(") ");
-    js
+// Sent values are converted the same way as arguments, so Ints are still BigInts when received
+pub fn channel_send_to_js(name: &String, value: &AstNode, locals: &[String]) -> String {
+    format!(
+        "{BS_VAR_PREFIX}{name}.send({});",
+        argument_to_js(value, locals)
+    )
 }
 
-pub fn combine_vec_to_js(collection: &Vec<AstNode>) -> String {
+fn channel_receive_to_js(name: &String) -> String {
+    format!("(await {BS_VAR_PREFIX}{name}.receive())")
+}
+
+// Copies are deep, so changing the items in a copy never changes the original
+fn copy_to_js(value: &AstNode, locals: &[String]) -> String {
+    format!("structuredClone({})", argument_to_js(value, locals))
+}
+
+// Values in a union are converted the same way as arguments,
+// so the type of the value can be checked with typeof
+pub fn union_value_to_js(value: &AstNode, locals: &[String]) -> String {
+    argument_to_js(value, locals)
+}
+
+// Ints are BigInts in JS, so they can be told apart from Floats
+fn type_check_to_js(name: &String, data_type: &DataType) -> String {
+    let value = format!("{BS_VAR_PREFIX}{name}");
+    match data_type {
+        DataType::Int => format!("typeof {value} === \"bigint\""),
+        DataType::Float => format!("typeof {value} === \"number\""),
+        DataType::String => format!("typeof {value} === \"string\""),
+        DataType::Bool => format!("typeof {value} === \"boolean\""),
+        DataType::Union(types) => format!(
+            "({})",
+            types
+                .iter()
+                .map(|data_type| type_check_to_js(name, data_type))
+                .collect::<Vec<String>>()
+                .join(" || ")
+        ),
+        _ => {
+            red_ln!("Can't check for type {:?} in JS", data_type);
+            String::from("false")
+        }
+    }
+}
+
+pub fn combine_vec_to_js(collection: &Vec<AstNode>, locals: &[String]) -> String {
     let mut js = String::new();
 
     let mut i: usize = 0;
@@ -189,7 +500,7 @@ pub fn combine_vec_to_js(collection: &Vec<AstNode>) -> String {
         // Make sure correct commas at end of each element but not last one
         js.push_str(&format!(
             "{}{}",
-            expression_to_js(&node),
+            expression_to_js(node, locals),
             if i < collection.len() - 1 { "," } else { "" }
         ));
         i += 1;
@@ -200,12 +511,10 @@ pub fn combine_vec_to_js(collection: &Vec<AstNode>) -> String {
 
 pub fn collection_to_js(collection: &AstNode) -> String {
     match collection {
-        AstNode::Tuple(nodes, _) => {
-            return combine_vec_to_js(nodes);
-        }
+        AstNode::Tuple(nodes, _) => combine_vec_to_js(nodes, &[]),
         _ => {
             red_ln!("Non-tuple AST node given to collection_to_js");
-            return "".to_string();
+            "".to_string()
         }
     }
 }
@@ -216,7 +525,7 @@ pub fn _collection_to_vec_of_js(collection: &AstNode) -> Vec<String> {
     match collection {
         AstNode::Tuple(nodes, _) => {
             for node in nodes {
-                js.push(expression_to_js(node));
+                js.push(expression_to_js(node, &[]));
             }
         }
         _ => {
@@ -1,19 +1,27 @@
 use std::path::Path;
 
 use super::{
-    code_block_highlighting::highlight_code_block, colors::get_color, js_parser::{collection_to_js, create_reference_in_js, expression_to_js, function_call_to_js}
+    code_block_highlighting::highlight_code_block, colors::get_color, js_parser::{assignment_to_js, channel_send_to_js, collection_to_js, create_reference_in_js, expression_to_js, function_call_to_js, union_value_to_js, CHANNEL_JS}
 };
 use crate::{
     bs_css::get_bs_css,
     bs_types::DataType,
     build::ExportedJS,
     parsers::{
-        ast_nodes::AstNode,
+        ast_nodes::{AstNode, Node, Reference},
+        generics::{is_instance, monomorphise},
         styles::{Action, Style, Tag},
         util::{count_newlines_at_end_of_string, count_newlines_at_start_of_string},
     },
     settings::{HTMLMeta, BS_VAR_PREFIX},
-    wasm_output::wat_parser::new_wat_var,
+    stdlib::math::math_function_from_call,
+    wasm_output::{
+        js_glue::WasmExport,
+        wasm_module::{Instruction, WasmModule},
+        wat_parser::{
+            assignment_to_wat, new_wasm_string, new_wat_function, new_wat_var, wat_number_type,
+        },
+    },
     Token,
 };
 use colour::red_ln;
@@ -25,22 +33,30 @@ pub struct ParserOutput {
     pub page_title: String,
     pub exported_js: Vec<ExportedJS>,
     pub exported_css: String,
-    pub wat: String,
-    pub wat_globals: String,
+    pub wasm: WasmModule,
+    pub wasm_globals: Vec<Instruction>,
+    pub wasm_exports: Vec<WasmExport>, // Used to create the JS glue for the page
 }
 
 // Parse ast into valid JS, HTML and CSS
+// Locals are the names declared in the JS bodies this body is inside of,
+// there are none at the top level of the module
 pub fn parse<'a>(
     ast: Vec<AstNode>,
     config: &'a HTMLMeta,
     release_build: bool,
     module_path: &'a str,
     is_global: bool,
+    locals: Option<&[String]>,
     imported_css: &'a String,
 ) -> Result<ParserOutput, String> {
+    let module_scope = locals.is_none();
+    let mut locals = locals.unwrap_or_default().to_vec();
+
     let mut js = String::new();
-    let mut wat = String::new();
-    let mut wat_global_initilisation = String::new();
+    let mut wasm = WasmModule::new();
+    let mut wasm_global_initilisation = Vec::new();
+    let mut wasm_exports = Vec::new();
     let mut html = String::new();
     let mut css = imported_css.to_owned();
     let mut page_title = String::new();
@@ -56,8 +72,66 @@ pub fn parse<'a>(
 
     let mut class_id: usize = 0;
 
+    // Channels can only be created at the top level of a module,
+    // so the class is only needed once for each module
+    let mut uses_channels = false;
+
+    // Functions that have been compiled to WASM, so can be called from other WASM code
+    let mut wasm_functions: Vec<String> = Vec::new();
+
+    // Number globals that the JS of the page changes
+    // WASM gives every global its starting value before any JS runs,
+    // so declarations that use them are worked out in JS after them instead
+    let mut js_globals: Vec<String> = Vec::new();
+
+    // Number globals that JS has already read,
+    // so changing them in WASM first would give that JS the wrong value
+    let mut read_by_js: Vec<String> = Vec::new();
+
+    // The number globals each function can change when it is called
+    let mut function_assignments: Vec<(String, Vec<String>)> = Vec::new();
+
+    // Line the current statement starts on, so errors in the WASM can be traced back to it
+    let mut line_number = 0;
+
+    // Generic functions called with numbers get a copy for WASM
+    let ast = match module_scope {
+        true => monomorphise(ast),
+        false => ast,
+    };
+
     // Parse HTML
     for node in ast {
+        let node = closures_to_js(
+            node,
+            config,
+            release_build,
+            module_path,
+            imported_css,
+            &mut wasm,
+            &mut wasm_global_initilisation,
+        )?;
+
+        if module_scope
+            && matches!(
+                node,
+                AstNode::If(..) | AstNode::For(..) | AstNode::FunctionCall(..)
+            )
+        {
+            js_globals.extend(changed_by(&node, &function_assignments));
+        }
+
+        // Numbers declared or assigned at the top level are only read in JS if they can't go in WASM
+        if module_scope
+            && !matches!(
+                node,
+                AstNode::VarDeclaration(_, _, _, DataType::Float | DataType::Int, _)
+                    | AstNode::Assignment(_, _, _, DataType::Float | DataType::Int)
+            )
+        {
+            read_by_js.extend(read_by(&node, &number_globals(&module_references)));
+        }
+
         match node {
             // SCENES (HTML)
             AstNode::Scene(scene, scene_tags, scene_styles, scene_actions) => {
@@ -73,7 +147,6 @@ pub fn parse<'a>(
                     &mut class_id,
                     &mut exp_id,
                     &mut Vec::new(),
-                    &mut wat,
                     config,
                 ));
             }
@@ -88,14 +161,87 @@ pub fn parse<'a>(
             AstNode::VarDeclaration(ref id, ref expr, is_exported, ref data_type, is_const) => {
                 let assignment_keyword = if is_const { "const" } else { "let" };
                 match data_type {
+                    // Numbers declared inside of a body are just JS values
+                    DataType::Float | DataType::Int if !module_scope => {
+                        let value = match data_type {
+                            DataType::Float => {
+                                format!("Number({})", expression_to_js(expr, &locals))
+                            }
+                            _ => format!("BigInt({})", expression_to_js(expr, &locals)),
+                        };
+                        js.push_str(&format!(
+                            "{assignment_keyword} {BS_VAR_PREFIX}{id} = {value};"
+                        ));
+                    }
                     DataType::Float | DataType::Int => {
-                        new_wat_var(id, expr, data_type, &mut wat, &mut wat_global_initilisation);
+                        // Function calls run in JS, so the result is given to the global afterwards
+                        // Anything using a global JS has changed is also worked out in JS, in order
+                        if calls_function(expr, &wasm_functions) || uses_any(expr, &js_globals) {
+                            let zero_value = match data_type {
+                                DataType::Float => AstNode::Literal(Token::FloatLiteral(0.0)),
+                                _ => AstNode::Literal(Token::IntLiteral(0)),
+                            };
+                            new_wat_var(
+                                id,
+                                &zero_value,
+                                data_type,
+                                line_number,
+                                &mut wasm,
+                                &mut wasm_global_initilisation,
+                            )?;
+                            js.push_str(&assignment_to_js(
+                                id,
+                                &Token::Assign,
+                                expr,
+                                data_type,
+                                &locals,
+                            ));
+                            js_globals.push(id.to_owned());
+                            js_globals.extend(changed_by(expr, &function_assignments));
+                            read_by_js.extend(read_by(expr, &number_globals(&module_references)));
+                        } else {
+                            new_wat_var(
+                                id,
+                                expr,
+                                data_type,
+                                line_number,
+                                &mut wasm,
+                                &mut wasm_global_initilisation,
+                            )?;
+                        }
+                        wasm_exports.push(WasmExport {
+                            name: id.to_owned(),
+                            data_type: data_type.to_owned(),
+                            mutable: !is_const,
+                        });
+                    }
+                    DataType::String if module_scope && !is_exported => {
+                        js.push_str(&format!("bs_wasm_string(\"{BS_VAR_PREFIX}{id}\");"));
+                        wasm_exports.push(WasmExport {
+                            name: id.to_owned(),
+                            data_type: data_type.to_owned(),
+                            mutable: !is_const,
+                        });
+
+                        // Constant strings go straight into the data section
+                        match &**expr {
+                            AstNode::Literal(Token::StringLiteral(value)) => {
+                                new_wasm_string(id, Some(value), &mut wasm);
+                            }
+                            _ => {
+                                new_wasm_string(id, None, &mut wasm);
+                                js.push_str(&format!(
+                                    "{BS_VAR_PREFIX}{id} = {};",
+                                    expression_to_js(expr, &locals)
+                                ));
+                            }
+                        }
                     }
                     DataType::String => {
                         let var_dec = format!(
                             "{} {BS_VAR_PREFIX}{id} = {};",
                             assignment_keyword,
-                            expression_to_js(&expr)
+                            expression_to_js(expr, &locals)
                         );
                         js.push_str(&var_dec);
                         if is_exported {
@@ -125,7 +271,6 @@ pub fn parse<'a>(
                                     &mut class_id,
                                     &mut exp_id,
                                     &mut Vec::new(),
-                                    &mut wat,
                                     config,
                                 );
                                 css.push_str(&created_css);
@@ -135,18 +280,31 @@ pub fn parse<'a>(
                                     exported_css.push_str(&created_css);
                                 }
 
-                                let var_dec = format!(
-                                    "{} {BS_VAR_PREFIX}{id} = `{}`;",
-                                    assignment_keyword, scene_to_js_string
-                                );
-                                js.push_str(&var_dec);
-                                if is_exported {
-                                    exported_js.push(ExportedJS {
-                                        js: var_dec,
-                                        module_path: Path::new(module_path).join(id),
-                                        global: is_global,
+                                // Scenes are only known once the page runs, so JS writes them into memory
+                                if module_scope && !is_exported {
+                                    new_wasm_string(id, None, &mut wasm);
+                                    js.push_str(&format!(
+                                        "bs_wasm_string(\"{BS_VAR_PREFIX}{id}\");{BS_VAR_PREFIX}{id} = `{scene_to_js_string}`;"
+                                    ));
+                                    wasm_exports.push(WasmExport {
+                                        name: id.to_owned(),
                                         data_type: data_type.to_owned(),
+                                        mutable: !is_const,
                                     });
+                                } else {
+                                    let var_dec = format!(
+                                        "{} {BS_VAR_PREFIX}{id} = `{}`;",
+                                        assignment_keyword, scene_to_js_string
+                                    );
+                                    js.push_str(&var_dec);
+                                    if is_exported {
+                                        exported_js.push(ExportedJS {
+                                            js: var_dec,
+                                            module_path: Path::new(module_path).join(id),
+                                            global: is_global,
+                                            data_type: data_type.to_owned(),
+                                        });
+                                    }
                                 }
                             }
                             _ => {
@@ -181,9 +339,10 @@ pub fn parse<'a>(
                                         &format!("{id}_{index}"),
                                         current_tuple_item,
                                         datatype,
-                                        &mut wat,
-                                        &mut wat_global_initilisation,
-                                    );
+                                        line_number,
+                                        &mut wasm,
+                                        &mut wasm_global_initilisation,
+                                    )?;
                                     tuple_js.push_str(&format!(
                                         "{}: wsx.get_{BS_VAR_PREFIX}{id}_{index}(),",
                                         index,
@@ -193,7 +352,7 @@ pub fn parse<'a>(
                                     tuple_js.push_str(&format!(
                                         "{}: {},",
                                         index,
-                                        expression_to_js(current_tuple_item)
+                                        expression_to_js(current_tuple_item, &locals)
                                     ));
                                 }
                                 _ => {
@@ -207,62 +366,144 @@ pub fn parse<'a>(
                         }
 
                         tuple_js.push_str("}");
+                        wasm_exports.push(WasmExport {
+                            name: id.to_owned(),
+                            data_type: data_type.to_owned(),
+                            mutable: false,
+                        });
                         js.push_str(&format!(
                             "{} {BS_VAR_PREFIX}{id} = {};",
                             assignment_keyword, tuple_js
                         ));
                     }
+                    DataType::Union(_) => {
+                        js.push_str(&format!(
+                            "{} {BS_VAR_PREFIX}{id} = {};",
+                            assignment_keyword,
+                            union_value_to_js(expr, &locals)
+                        ));
+                    }
+                    DataType::Channel(_) => {
+                        if !uses_channels {
+                            js.push_str(CHANNEL_JS);
+                            uses_channels = true;
+                        }
+                        js.push_str(&format!(
+                            "const {BS_VAR_PREFIX}{id} = new BsChannel(\"{id}\");"
+                        ));
+                    }
                     _ => {
                         js.push_str(&format!(
                             "{} {BS_VAR_PREFIX}{id} = {};",
                             assignment_keyword,
-                            expression_to_js(&expr)
+                            expression_to_js(expr, &locals)
                         ));
                     }
                 };
 
+                if !module_scope {
+                    locals.push(id.to_owned());
+                }
                 module_references.push(node);
             }
 
-            AstNode::Function(name, args, body, is_exported, return_type) => {
-                let mut arg_names = String::new();
-                for arg in &args {
-                    let unboxed_default = match &arg.default_value {
-                        Some(ref boxed_value) => &**boxed_value,
-                        _ => &AstNode::Empty,
-                    };
+            AstNode::Assignment(ref name, ref operator, ref value, ref data_type) => {
+                // Top level numbers are changed by the WASM that gives globals their starting values,
+                // so declarations after the assignment see the new value
+                let in_wasm = module_scope
+                    && !js_globals.contains(name)
+                    && !read_by_js.contains(name)
+                    && !calls_function(value, &wasm_functions)
+                    && !uses_any(value, &js_globals);
+                let wat_assignment = match wat_number_type(data_type) {
+                    Some(_) if in_wasm => assignment_to_wat(
+                        name,
+                        operator,
+                        value,
+                        data_type,
+                        Instruction::GlobalSet(format!("{BS_VAR_PREFIX}{name}")),
+                        &[],
+                        line_number,
+                    )?,
+                    _ => None,
+                };
 
-                    let default_arg = match unboxed_default {
-                        AstNode::Literal(token) => match token {
-                            Token::StringLiteral(value)
-                            | Token::RawStringLiteral(value)
-                            | Token::PathLiteral(value) => &format!("=\"{value}\""),
-                            Token::IntLiteral(value) => &format!("={value}"),
-                            Token::FloatLiteral(value) => &format!("={value}"),
-                            Token::BoolLiteral(value) => &format!("={value}"),
-                            _ => {
-                                return Err("Error: invalid literal given as a default value".to_string());
-                            }
-                        },
-                        _ => "",
-                    };
+                match wat_assignment {
+                    Some(instructions) => {
+                        wasm_global_initilisation.push(Instruction::SourceLine(line_number));
+                        wasm_global_initilisation.extend(instructions);
+                    }
+                    None => {
+                        if module_scope && wat_number_type(data_type).is_some() {
+                            js_globals.push(name.to_owned());
+                            js_globals.extend(changed_by(value, &function_assignments));
+                            read_by_js.extend(read_by(value, &number_globals(&module_references)));
+                        }
+                        js.push_str(&assignment_to_js(name, operator, value, data_type, &locals));
+                    }
+                }
+            }
 
-                    arg_names.push_str(&format!("{BS_VAR_PREFIX}{}{default_arg},", arg.name));
+            AstNode::ChannelSend(ref name, ref value) => {
+                js.push_str(&channel_send_to_js(name, value, &locals));
+            }
+
+            AstNode::ChannelClose(ref name) => {
+                js.push_str(&format!("{BS_VAR_PREFIX}{name}.close();"));
+            }
+
+            AstNode::Function(name, args, body, is_exported, return_type) => {
+                let assigned = body
+                    .iter()
+                    .flat_map(|node| changed_by(node, &function_assignments))
+                    .collect();
+                function_assignments.push((name.to_owned(), assigned));
+
+                // Functions that only use numbers run in WASM,
+                // JS calls them through the WASM instance
+                let globals = number_globals(&module_references);
+                let wat_function = new_wat_function(
+                    &name,
+                    &args,
+                    &body,
+                    &return_type,
+                    &globals,
+                    &wasm_functions,
+                    line_number,
+                )?;
+                if let Some(ref wat_function) = wat_function {
+                    wasm.functions.push(wat_function.to_owned());
+                    js.push_str(&format!(
+                        "const {BS_VAR_PREFIX}{name} = wsx.{BS_VAR_PREFIX}{name};"
+                    ));
+                    wasm_functions.push(name.to_owned());
+
+                    // Copies of generic functions are only called by the page, so they aren't put on 'bs'
+                    if !is_instance(&name) {
+                        wasm_exports.push(WasmExport {
+                            name: name.to_owned(),
+                            data_type: DataType::Function(
+                                Box::new(args.to_owned()),
+                                Box::new(return_type.to_owned()),
+                            ),
+                            mutable: false,
+                        });
+                    }
+
+                    // Other modules import the JS version of the function
+                    if !is_exported {
+                        continue;
+                    }
                 }
 
-                let func_body = match parse(
+                let (arg_names, func_body) = function_to_js(
+                    &args,
                     body,
                     config,
                     release_build,
                     module_path,
-                    false,
                     imported_css,
-                ) {
-                    Ok(output) => output,
-                    Err(e) => {
-                        return Err(format!("Error parsing function body: {:?}", e));
-                    }
-                };
+                )?;
                 let func = format!(
                     "{}function {BS_VAR_PREFIX}{name}({arg_names}){{{}}}",
                     if is_exported { "export " } else { "" },
@@ -277,20 +518,119 @@ pub fn parse<'a>(
                         data_type: DataType::Function(Box::new(args), Box::new(return_type)),
                     });
                 }
-                js.push_str(&func);
-                wat.push_str(&func_body.wat);
-                wat_global_initilisation.push_str(&func_body.wat_globals);
+                if wat_function.is_none() {
+                    js.push_str(&func);
+                    wasm.append(func_body.wasm);
+                    wasm_global_initilisation.extend(func_body.wasm_globals);
+                }
             }
 
             AstNode::FunctionCall(name, arguments, _) => {
-                js.push_str(&function_call_to_js(&name, *arguments.to_owned()));
+                js.push_str(&format!(
+                    "{};",
+                    function_call_to_js(&name, *arguments.to_owned(), &locals)
+                ));
             }
 
             AstNode::Return(ref expr) => {
-                js.push_str(&format!("return {};", expression_to_js(&expr)));
+                js.push_str(&format!("return {};", expression_to_js(expr, &locals)));
             }
+
+            AstNode::If(condition, body, else_body) => {
+                // Unions are JS values, even after they are narrowed to a number
+                let mut body_locals = locals.to_owned();
+                if let AstNode::TypeCheck(ref name, _) = *condition {
+                    body_locals.push(name.to_owned());
+                }
+
+                let if_body = match parse(
+                    body,
+                    config,
+                    release_build,
+                    module_path,
+                    false,
+                    Some(&body_locals),
+                    imported_css,
+                ) {
+                    Ok(output) => output,
+                    Err(e) => {
+                        return Err(format!("Error parsing if statement body: {:?}", e));
+                    }
+                };
+                js.push_str(&format!(
+                    "if ({}) {{{}}}",
+                    expression_to_js(&condition, &locals),
+                    if_body.js
+                ));
+                wasm.append(if_body.wasm);
+                wasm_global_initilisation.extend(if_body.wasm_globals);
+                wasm_exports.extend(if_body.wasm_exports);
+
+                if !else_body.is_empty() {
+                    let else_output = match parse(
+                        else_body,
+                        config,
+                        release_build,
+                        module_path,
+                        false,
+                        Some(&body_locals),
+                        imported_css,
+                    ) {
+                        Ok(output) => output,
+                        Err(e) => {
+                            return Err(format!("Error parsing else body: {:?}", e));
+                        }
+                    };
+                    js.push_str(&format!("else {{{}}}", else_output.js));
+                    wasm.append(else_output.wasm);
+                    wasm_global_initilisation.extend(else_output.wasm_globals);
+                    wasm_exports.extend(else_output.wasm_exports);
+                }
+            }
+
+            AstNode::For(item_name, iterable, body) => {
+                let mut body_locals = locals.to_owned();
+                body_locals.push(item_name.to_owned());
+
+                let loop_body = match parse(
+                    body,
+                    config,
+                    release_build,
+                    module_path,
+                    false,
+                    Some(&body_locals),
+                    imported_css,
+                ) {
+                    Ok(output) => output,
+                    Err(e) => {
+                        return Err(format!("Error parsing for loop body: {:?}", e));
+                    }
+                };
+
+                let loop_head = match &*iterable {
+                    AstNode::VarReference(name, DataType::Collection(_))
+                    | AstNode::ConstReference(name, DataType::Collection(_)) => {
+                        format!("const {BS_VAR_PREFIX}{item_name} of {BS_VAR_PREFIX}{name}")
+                    }
+                    // Ints are BigInts in JS
+                    _ => format!(
+                        "let {BS_VAR_PREFIX}{item_name} = 0n; {BS_VAR_PREFIX}{item_name} < {}; {BS_VAR_PREFIX}{item_name}++",
+                        expression_to_js(&iterable, &locals)
+                    ),
+                };
+
+                js.push_str(&format!("for ({loop_head}) {{{}}}", loop_body.js));
+                wasm.append(loop_body.wasm);
+                wasm_global_initilisation.extend(loop_body.wasm_globals);
+                wasm_exports.extend(loop_body.wasm_exports);
+            }
+            // Ints are BigInts, which would be logged with an 'n' after them
             AstNode::Print(ref expr) => {
-                js.push_str(&format!("console.log({});", expression_to_js(&expr)));
+                let value = expression_to_js(expr, &locals);
+                match expr.get_type() {
+                    DataType::Int => js.push_str(&format!("console.log(String({value}));")),
+                    _ => js.push_str(&format!("console.log({value});")),
+                }
             }
 
             // DIRECT INSERTION OF JS / CSS / HTML into page
@@ -301,8 +641,12 @@ pub fn parse<'a>(
                 css.push_str(&css_string);
             }
 
+            AstNode::LineNumber(line) => {
+                line_number = line;
+            }
+
             // Ignored
-            AstNode::Comment(_) => {}
+            AstNode::Comment(_) | AstNode::DocComment(_) | AstNode::FoldedCall(_) => {}
 
             AstNode::Error(err, line_number) => {
                 return Err(format!("Error on Line {}: - {}", line_number, err));
@@ -324,8 +668,256 @@ pub fn parse<'a>(
         page_title,
         exported_js,
         exported_css,
-        wat,
-        wat_globals: wat_global_initilisation,
+        wasm,
+        wasm_globals: wasm_global_initilisation,
+        wasm_exports,
+    })
+}
+
+// Returns the JS arguments and the parsed body of a function
+fn function_to_js(
+    args: &Vec<Reference>,
+    body: Vec<AstNode>,
+    config: &HTMLMeta,
+    release_build: bool,
+    module_path: &str,
+    imported_css: &String,
+) -> Result<(String, ParserOutput), String> {
+    let mut arg_names = String::new();
+    for arg in args {
+        let unboxed_default = match &arg.default_value {
+            Some(ref boxed_value) => &**boxed_value,
+            _ => &AstNode::Empty,
+        };
+
+        let default_arg = match unboxed_default {
+            AstNode::Literal(token) => match token {
+                Token::StringLiteral(value)
+                | Token::RawStringLiteral(value)
+                | Token::PathLiteral(value) => &format!("=\"{value}\""),
+                Token::IntLiteral(value) => &format!("={value}n"),
+                Token::FloatLiteral(value) => &format!("={value}"),
+                Token::BoolLiteral(value) => &format!("={value}"),
+                _ => {
+                    return Err("Error: invalid literal given as a default value".to_string());
+                }
+            },
+            _ => "",
+        };
+
+        arg_names.push_str(&format!("{BS_VAR_PREFIX}{}{default_arg},", arg.name));
+    }
+
+    // Numbers are normally WASM globals, but arguments are just JS values
+    let locals: Vec<String> = args.iter().map(|arg| arg.name.to_owned()).collect();
+    let func_body = match parse(
+        body,
+        config,
+        release_build,
+        module_path,
+        false,
+        Some(&locals),
+        imported_css,
+    ) {
+        Ok(output) => output,
+        Err(e) => {
+            return Err(format!("Error parsing function body: {:?}", e));
+        }
+    };
+
+    Ok((arg_names, func_body))
+}
+
+fn calls_function(expr: &AstNode, wasm_functions: &[String]) -> bool {
+    match expr {
+        // Maths functions and functions compiled to WASM can run in WASM
+        AstNode::FunctionCall(name, args, DataType::Float | DataType::Int)
+            if math_function_from_call(name).is_some() || wasm_functions.contains(name) =>
+        {
+            calls_function(args, wasm_functions)
+        }
+        AstNode::FunctionCall(..) | AstNode::ChannelReceive(..) => true,
+        AstNode::RuntimeExpression(nodes, _) | AstNode::Tuple(nodes, _) => nodes
+            .iter()
+            .any(|node| calls_function(node, wasm_functions)),
+        _ => false,
+    }
+}
+
+// Whether the expression uses any of the variables
+fn uses_any(expr: &AstNode, names: &[String]) -> bool {
+    match expr {
+        AstNode::VarReference(name, _) | AstNode::ConstReference(name, _) => names.contains(name),
+        AstNode::FunctionCall(_, args, _) => uses_any(args, names),
+        AstNode::RuntimeExpression(nodes, _) | AstNode::Tuple(nodes, _) => {
+            nodes.iter().any(|node| uses_any(node, names))
+        }
+        _ => false,
+    }
+}
+
+// Names of the number globals declared so far
+fn number_globals(module_references: &[AstNode]) -> Vec<String> {
+    module_references
+        .iter()
+        .filter_map(|reference| match reference {
+            AstNode::VarDeclaration(id, _, _, DataType::Float | DataType::Int, _) => {
+                Some(id.to_owned())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Number globals that are read by running the node, or by calling any function it declares
+fn read_by(node: &AstNode, globals: &[String]) -> Vec<String> {
+    match node {
+        AstNode::VarReference(name, _)
+        | AstNode::ConstReference(name, _)
+        | AstNode::CollectionAccess(name, ..)
+        | AstNode::TupleAccess(name, ..) => match globals.contains(name) {
+            true => vec![name.to_owned()],
+            false => Vec::new(),
+        },
+        AstNode::Assignment(name, operator, value, _) => {
+            let mut read = read_by(value, globals);
+            if *operator != Token::Assign && globals.contains(name) {
+                read.push(name.to_owned());
+            }
+            read
+        }
+        AstNode::JS(code) => globals
+            .iter()
+            .filter(|name| code.contains(&format!("{BS_VAR_PREFIX}{name}")))
+            .cloned()
+            .collect(),
+        AstNode::If(condition, body, else_body) => read_by(condition, globals)
+            .into_iter()
+            .chain(
+                body.iter()
+                    .chain(else_body)
+                    .flat_map(|node| read_by(node, globals)),
+            )
+            .collect(),
+        AstNode::For(_, iterable, body) => read_by(iterable, globals)
+            .into_iter()
+            .chain(body.iter().flat_map(|node| read_by(node, globals)))
+            .collect(),
+        AstNode::VarDeclaration(_, value, ..)
+        | AstNode::Return(value)
+        | AstNode::Print(value)
+        | AstNode::ChannelSend(_, value)
+        | AstNode::Copy(value)
+        | AstNode::FunctionCall(_, value, _) => read_by(value, globals),
+        AstNode::Function(_, _, nodes, ..)
+        | AstNode::AnonymousFunction(_, nodes, _)
+        | AstNode::Scene(nodes, ..)
+        | AstNode::Expression(nodes, _)
+        | AstNode::RuntimeExpression(nodes, _)
+        | AstNode::Collection(nodes, _)
+        | AstNode::Tuple(nodes, _) => nodes
+            .iter()
+            .flat_map(|node| read_by(node, globals))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Variables that can be changed by running the node in JS,
+// including anything changed by the functions it calls
+fn changed_by(node: &AstNode, function_assignments: &[(String, Vec<String>)]) -> Vec<String> {
+    let mut changed = Vec::new();
+    match node {
+        AstNode::Assignment(name, _, value, _) => {
+            changed.push(name.to_owned());
+            changed.extend(changed_by(value, function_assignments));
+        }
+        AstNode::FunctionCall(name, args, _) => {
+            if let Some((_, assigned)) = function_assignments.iter().find(|(n, _)| n == name) {
+                changed.extend(assigned.iter().cloned());
+            }
+            changed.extend(changed_by(args, function_assignments));
+        }
+        AstNode::If(condition, body, else_body) => {
+            changed.extend(changed_by(condition, function_assignments));
+            for node in body.iter().chain(else_body) {
+                changed.extend(changed_by(node, function_assignments));
+            }
+        }
+        AstNode::For(_, iterable, body) => {
+            changed.extend(changed_by(iterable, function_assignments));
+            for node in body {
+                changed.extend(changed_by(node, function_assignments));
+            }
+        }
+        AstNode::VarDeclaration(_, value, ..)
+        | AstNode::Return(value)
+        | AstNode::Print(value)
+        | AstNode::ChannelSend(_, value) => {
+            changed.extend(changed_by(value, function_assignments));
+        }
+        AstNode::RuntimeExpression(nodes, _) | AstNode::Tuple(nodes, _) => {
+            for node in nodes {
+                changed.extend(changed_by(node, function_assignments));
+            }
+        }
+        _ => {}
+    }
+    changed
+}
+
+// Anonymous functions are turned into JS closures before the node using them is parsed
+fn closures_to_js(
+    node: AstNode,
+    config: &HTMLMeta,
+    release_build: bool,
+    module_path: &str,
+    imported_css: &String,
+    wasm: &mut WasmModule,
+    wasm_globals: &mut Vec<Instruction>,
+) -> Result<AstNode, String> {
+    let mut to_js = |node: AstNode| {
+        closures_to_js(
+            node,
+            config,
+            release_build,
+            module_path,
+            imported_css,
+            wasm,
+            wasm_globals,
+        )
+    };
+
+    Ok(match node {
+        AstNode::AnonymousFunction(args, body, _) => {
+            let (arg_names, func_body) =
+                function_to_js(&args, body, config, release_build, module_path, imported_css)?;
+            wasm.append(func_body.wasm);
+            wasm_globals.extend(func_body.wasm_globals);
+
+            AstNode::JS(format!("(function({arg_names}){{{}}})", func_body.js))
+        }
+        AstNode::VarDeclaration(id, value, is_exported, data_type, is_const) => {
+            AstNode::VarDeclaration(id, Box::new(to_js(*value)?), is_exported, data_type, is_const)
+        }
+        AstNode::Assignment(name, operator, value, data_type) => {
+            AstNode::Assignment(name, operator, Box::new(to_js(*value)?), data_type)
+        }
+        AstNode::FunctionCall(name, args, return_type) => {
+            AstNode::FunctionCall(name, Box::new(to_js(*args)?), return_type)
+        }
+        AstNode::ChannelSend(name, value) => AstNode::ChannelSend(name, Box::new(to_js(*value)?)),
+        AstNode::Return(value) => AstNode::Return(Box::new(to_js(*value)?)),
+        AstNode::Print(value) => AstNode::Print(Box::new(to_js(*value)?)),
+        AstNode::Tuple(items, line_number) => AstNode::Tuple(
+            items.into_iter().map(to_js).collect::<Result<_, _>>()?,
+            line_number,
+        ),
+        AstNode::RuntimeExpression(nodes, data_type) => AstNode::RuntimeExpression(
+            nodes.into_iter().map(to_js).collect::<Result<_, _>>()?,
+            data_type,
+        ),
+        _ => node,
     })
 }
 
@@ -351,7 +943,6 @@ pub fn parse_scene(
     class_id: &mut usize,
     exp_id: &mut usize,
     positions: &mut Vec<f64>,
-    wasm_module: &mut String,
     config: &HTMLMeta,
 ) -> String {
     let mut html = String::new();
@@ -424,7 +1015,7 @@ pub fn parse_scene(
             Style::Margin(arg) => {
                 scene_wrap
                     .style
-                    .push_str(&format!("margin:{}rem;", expression_to_js(&arg)));
+                    .push_str(&format!("margin:{}rem;", expression_to_js(&arg, &[])));
                 // Only switch to span if there is no tag
                 style_assigned = true;
             }
@@ -622,10 +1213,10 @@ pub fn parse_scene(
                     AstNode::Literal(Token::StringLiteral(value)) => value,
                     AstNode::RuntimeExpression(expr, data_type) => {
                         if *data_type == DataType::String {
-                            &expression_to_js(&AstNode::RuntimeExpression(
-                                expr.clone(),
-                                DataType::String,
-                            ))
+                            &expression_to_js(
+                                &AstNode::RuntimeExpression(expr.clone(), DataType::String),
+                                &[],
+                            )
                         } else {
                             red_ln!("Error: src attribute must be a string literal (Webparser - get src)");
                             continue;
@@ -689,7 +1280,7 @@ pub fn parse_scene(
                 // Should accept a function as an argument
                 scene_wrap
                     .properties
-                    .push_str(&format!(" onclick=\"{}\"", expression_to_js(&node)));
+                    .push_str(&format!(" onclick=\"{}\"", expression_to_js(&node, &[])));
             }
             Action::_Swap => {}
         }
@@ -888,7 +1479,6 @@ pub fn parse_scene(
                     class_id,
                     exp_id,
                     &mut Vec::new(),
-                    wasm_module,
                     config,
                 );
 
@@ -976,7 +1566,7 @@ pub fn parse_scene(
                     module_references.push(node.to_owned());
                     js.push_str(&format!(
                         "uInnerHTML(\"{name}\",{});",
-                        &function_call_to_js(name, *arguments.to_owned())
+                        &function_call_to_js(name, *arguments.to_owned(), &[])
                     ));
                 }
             }
@@ -998,7 +1588,9 @@ pub fn parse_scene(
                                 index += 1;
                             }
 
-                            js.push_str(&format!("uInnerHTML(\"{name}\", [{elements}]);"));
+                            js.push_str(&format!(
+                                "bs_render(\"{name}\",()=>uInnerHTML(\"{name}\", [{elements}]));"
+                            ));
                         }
                         _ => {
                             js.push_str(&create_reference_in_js(name, data_type));
@@ -1025,10 +1617,12 @@ pub fn parse_scene(
                                 idx += 1;
                             }
 
-                            js.push_str(&format!("uInnerHTML(\"{name}\",[{elements}]);"));
+                            js.push_str(&format!(
+                                "bs_render(\"{name}\",()=>uInnerHTML(\"{name}\",[{elements}]));"
+                            ));
                         }
                         _ => {
-                            js.push_str(&format!("uInnerHTML(\"{name}\",{BS_VAR_PREFIX}{name}[{index}]);"));
+                            js.push_str(&format!("bs_render(\"{name}\",()=>uInnerHTML(\"{name}\",{BS_VAR_PREFIX}{name}[{index}]));"));
                         }
                     }
                 }
@@ -1084,7 +1678,7 @@ pub fn parse_scene(
 
         match literal.0 {
             AstNode::RuntimeExpression(expr, expr_type) => {
-                js_string = expression_to_js(&AstNode::RuntimeExpression(expr, expr_type));
+                js_string = expression_to_js(&AstNode::RuntimeExpression(expr, expr_type), &[]);
             }
             AstNode::Literal(token) => match token {
                 Token::StringLiteral(value) | Token::RawStringLiteral(value) => {
@@ -1161,7 +1755,7 @@ pub fn parse_scene(
                 0,
                 &format!(
                     "<a href={} style=\"{}\" class=\"{}\" {}>",
-                    expression_to_js(&href),
+                    expression_to_js(&href, &[]),
                     scene_wrap.style,
                     scene_wrap.classes,
                     scene_wrap.properties
@@ -1174,7 +1768,7 @@ pub fn parse_scene(
                 0,
                 &format!(
                     "<button onclick=\"{}\" style=\"{}\" class=\"{}\" {}>",
-                    expression_to_js(&button),
+                    expression_to_js(&button, &[]),
                     scene_wrap.style,
                     scene_wrap.classes,
                     scene_wrap.properties
@@ -1223,7 +1817,7 @@ pub fn parse_scene(
                 0,
                 &format!(
                     "<video src=\"{}\" style=\"{}\" {} class=\"{}\" controls />",
-                    expression_to_js(&src),
+                    expression_to_js(&src, &[]),
                     scene_wrap.style,
                     scene_wrap.properties,
                     scene_wrap.classes
@@ -1242,7 +1836,7 @@ pub fn parse_scene(
                 0,
                 &format!(
                     "<audio src=\"{}\" style=\"{}\" {} class=\"{}\" controls />",
-                    expression_to_js(&src),
+                    expression_to_js(&src, &[]),
                     scene_wrap.style,
                     scene_wrap.properties,
                     scene_wrap.classes
@@ -1364,10 +1958,10 @@ fn get_src(value: &AstNode, config: &HTMLMeta) -> String {
         }
         AstNode::RuntimeExpression(expr, data_type) => {
             if *data_type == DataType::String || *data_type == DataType::CoerseToString {
-                src = expression_to_js(&AstNode::RuntimeExpression(
-                    expr.clone(),
-                    data_type.to_owned(),
-                ))
+                src = expression_to_js(
+                    &AstNode::RuntimeExpression(expr.clone(), data_type.to_owned()),
+                    &[],
+                )
             } else {
                 red_ln!("Error: src attribute must be a string literal (Webparser - get src)");
             }
@@ -1437,3 +2031,26 @@ fn sanitise_content(content: &mut String) -> String {
     *content = content.replace('<', "&lt;").replace('>', "&gt;");
     content.trim_start().to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::run_page;
+
+    #[test]
+    fn assignment_after_js_read_keeps_source_order() {
+        let output = run_page(
+            "assignment_after_js_read",
+            "x = 1\nx += 0\ns = \"v{x}\"\nx = 5\n[s]\n",
+        );
+        assert!(output.contains("s = v1"), "{output}");
+    }
+
+    #[test]
+    fn assignment_before_js_read_is_seen() {
+        let output = run_page(
+            "assignment_before_js_read",
+            "y = 2\ny = 3\ns = \"v{y}\"\n[s]\n",
+        );
+        assert!(output.contains("s = v3"), "{output}");
+    }
+}
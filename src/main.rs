@@ -11,24 +11,35 @@ pub mod bs_types;
 mod build;
 mod create_new_project;
 pub mod dev_server;
+mod docs;
 mod settings;
 mod test;
+#[cfg(test)]
+mod test_utils;
 mod tokenize_scene;
 mod tokenizer;
 mod tokens;
 mod parsers {
     pub mod ast_nodes;
     pub mod build_ast;
+    pub mod channels;
     pub mod collections;
+    pub mod control_flow;
     mod create_scene_node;
+    pub mod dead_code;
     pub mod functions;
+    pub mod generics;
     mod expressions {
+        pub mod comptime;
         pub mod constant_folding;
         pub mod eval_expression;
         pub mod parse_expression;
     }
+    pub mod ownership;
+    pub mod scope;
     pub mod styles;
     pub mod tuples;
+    pub mod unions;
     pub mod util;
     pub mod variables;
 }
@@ -40,19 +51,32 @@ mod html_output {
     pub mod web_parser;
     pub mod code_block_highlighting;
 }
+mod stdlib {
+    pub mod math;
+    pub mod string;
+}
 mod wasm_output {
+    pub mod js_glue;
+    pub mod wasi;
     pub mod wasm_generator;
+    pub mod wasm_inspector;
+    pub mod wasm_module;
+    pub mod wasm_optimiser;
+    pub mod wasm_validator;
     pub mod wat_parser;
 }
+use build::Target;
 use colour::{dark_cyan, green_ln_bold, grey_ln, red_ln};
 pub use tokens::Token;
 enum Command {
     NewHTMLProject(PathBuf),
-    Build(String),
-    Release(String),
+    Build(String, Target),
+    Release(String, Target),
     Test,
-    Dev(String),  // Runs local dev server
-    Wat(PathBuf), // Compiles a WAT file to WebAssembly
+    Dev(String),     // Runs local dev server
+    Wat(PathBuf),    // Compiles a WAT file to WebAssembly
+    Inspect(String), // Prints what is in the WASM module of a page
+    Docs(String),    // Creates API pages from the declarations and doc comments of each module
 }
 
 fn main() {
@@ -83,10 +107,10 @@ fn main() {
                 }
             }
         }
-        Command::Build(path) => {
+        Command::Build(path, target) => {
             dark_cyan!("Building project...");
             let start = Instant::now();
-            match build::build(path, true) {
+            match build::build(path, true, target) {
                 Ok(_) => {
                     let duration = start.elapsed();
                     grey_ln!("------------------------------------");
@@ -100,10 +124,10 @@ fn main() {
                 }
             }
         }
-        Command::Release(path) => {
+        Command::Release(path, target) => {
             dark_cyan!("Building project...");
             let start = Instant::now();
-            match build::build(path, true) {
+            match build::build(path, true, target) {
                 Ok(_) => {
                     let duration = start.elapsed();
                     grey_ln!("------------------------------------");
@@ -145,13 +169,49 @@ fn main() {
             let _ = wasm_output::wasm_generator::compile_wat_file(&path);
             main();
         }
+        Command::Inspect(path) => {
+            dark_cyan!("Inspecting page...");
+            match wasm_output::wasm_inspector::inspect_page(&path) {
+                Ok(_) => {
+                    main();
+                }
+                Err(e) => {
+                    red_ln!("Error inspecting page: {:?}", e);
+                }
+            }
+        }
+        Command::Docs(path) => {
+            dark_cyan!("Creating API docs...");
+            match docs::build_docs(path) {
+                Ok(_) => {
+                    main();
+                }
+                Err(e) => {
+                    red_ln!("Error creating API docs: {:?}", e);
+                }
+            }
+        }
     }
 }
 
 fn collect_user_input() -> Command {
-    let args = prompt_user_for_input("Enter compiler command: ".to_string());
+    let mut args = prompt_user_for_input("Enter compiler command: ".to_string());
+
+    // Builds are for web pages unless another target is given with --target
+    let mut target = Target::Web;
+    if let Some(index) = args.iter().position(|arg| arg == "--target") {
+        match args.get(index + 1).map(String::as_str) {
+            Some("web") => {}
+            Some("wasi") => target = Target::Wasi,
+            other => {
+                red_ln!("Invalid target: {:?}. Targets are 'web' or 'wasi'", other);
+                return collect_user_input();
+            }
+        }
+        args.drain(index..index + 2);
+    }
 
-    match args.get(0).map(String::as_str) {
+    match args.first().map(String::as_str) {
         Some("new") => {
             // Check type of project
             match args.get(1).map(String::as_str) {
@@ -184,11 +244,11 @@ fn collect_user_input() -> Command {
 
             match args.get(1).map(String::as_str) {
                 Some(string) => {
-                    return Command::Build(format!("{}/{}", entry_path, string));
+                    return Command::Build(format!("{}/{}", entry_path, string), target);
                 }
                 _ => {
                     // Return current working directory path
-                    return Command::Build(entry_path);
+                    return Command::Build(entry_path, target);
                 }
             }
         }
@@ -203,11 +263,47 @@ fn collect_user_input() -> Command {
 
             match args.get(1).map(String::as_str) {
                 Some(string) => {
-                    return Command::Release(format!("{}/{}", entry_path, string));
+                    return Command::Release(format!("{}/{}", entry_path, string), target);
                 }
                 _ => {
                     // Return current working directory path
-                    return Command::Release(entry_path);
+                    return Command::Release(entry_path, target);
+                }
+            }
+        }
+        Some("docs") => {
+            let entry_path = match std::env::current_dir() {
+                Ok(dir) => dir.to_str().unwrap().to_owned(),
+                Err(e) => {
+                    red_ln!("Error getting current directory: {:?}", e);
+                    "".to_owned()
+                }
+            };
+
+            match args.get(1).map(String::as_str) {
+                Some(string) => {
+                    return Command::Docs(format!("{}/{}", entry_path, string));
+                }
+                _ => {
+                    return Command::Docs(entry_path);
+                }
+            }
+        }
+        Some("inspect") => {
+            let entry_path = match std::env::current_dir() {
+                Ok(dir) => dir.to_str().unwrap().to_owned(),
+                Err(e) => {
+                    red_ln!("Error getting current directory: {:?}", e);
+                    "".to_owned()
+                }
+            };
+
+            match args.get(1).map(String::as_str) {
+                Some(string) => {
+                    return Command::Inspect(format!("{}/{}", entry_path, string));
+                }
+                _ => {
+                    red_ln!("Give the path of the .bs page to inspect");
                 }
             }
         }
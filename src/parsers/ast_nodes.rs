@@ -27,15 +27,28 @@ pub enum AstNode {
 
     // Control Flow
     Return(Box<AstNode>),
+    If(Box<AstNode>, Vec<AstNode>, Vec<AstNode>), // Condition, Body, Else body
+    For(String, Box<AstNode>, Vec<AstNode>),      // Item name, Iterable, Body
+    TypeCheck(String, DataType),                  // Variable name, Type checked for with 'is'
 
     // Basics
     Function(String, Vec<Reference>, Vec<AstNode>, bool, DataType), // Function name, Args, Body, Public, return types
+    AnonymousFunction(Vec<Reference>, Vec<AstNode>, DataType),      // Args, Body, return type
     Expression(Vec<AstNode>, u32), // Expression that can contain mixed types, line number
     RuntimeExpression(Vec<AstNode>, DataType), //Expression, Result type
 
     Error(String, u32), // Message, line number
     Comment(String),
+    DocComment(String), // Documents the declaration straight after it
+    LineNumber(u32),    // Line in the source code the next statement starts on
+    FoldedCall(String), // Function that was called at compile time, so it still counts as used
     VarDeclaration(String, Box<AstNode>, bool, DataType, bool), // Variable name, Value, Public, Type, is_const
+    Assignment(String, Token, Box<AstNode>, DataType), // Variable name, Assignment operator, Value, Type
+
+    // Channels
+    ChannelSend(String, Box<AstNode>), // Channel name, Value
+    ChannelReceive(String, DataType),  // Channel name, Type of the values
+    ChannelClose(String),              // Channel name
 
     // IO
     Print(Box<AstNode>),
@@ -45,6 +58,7 @@ pub enum AstNode {
     ConstReference(String, DataType),
     JSStringReference(String),
     FunctionCall(String, Box<AstNode>, DataType), // Function name, arguments (can be a tuple of arguments), return type
+    Copy(Box<AstNode>), // Deep copy of a value, so the original isn't moved
 
     // Accessing fields
     CollectionAccess(String, usize, DataType), // Name, Index, Type
@@ -1,7 +1,10 @@
 use super::{
     ast_nodes::{AstNode, Reference},
+    channels::{create_channel_send, find_deadlock, has_send},
+    control_flow::{always_returns, create_for_loop, create_if_statement},
     create_scene_node::new_scene,
     expressions::parse_expression::{create_expression, get_args},
+    scope::{ScopeKind, ScopeStack},
     variables::create_new_var_or_ref,
 };
 use crate::{bs_types::DataType, Token};
@@ -12,7 +15,7 @@ pub fn new_ast(
     tokens: Vec<Token>,
     i: &mut usize,
     token_line_numbers: &Vec<u32>,
-    mut variable_declarations: Vec<Reference>,
+    variable_declarations: &mut ScopeStack,
     return_type: &DataType,
     module_scope: bool,
     // AST         Imports
@@ -20,13 +23,35 @@ pub fn new_ast(
     let mut ast = Vec::new();
     let mut imports = Vec::new();
     let mut exported: bool = false;
-    let mut needs_to_return = return_type != &DataType::None;
+    let mut doc_comment = String::new();
+    // Only the top level of a function body has to return,
+    // blocks inside of the function (if / for bodies) can return early
+    let mut needs_to_return = return_type != &DataType::None
+        && variable_declarations.current_kind() == &ScopeKind::Function;
 
     while *i < tokens.len() {
+        // Doc comments only apply to a declaration straight after them
+        if !matches!(
+            &tokens[*i],
+            Token::DocComment(_)
+                | Token::Comment(_)
+                | Token::Newline
+                | Token::Export
+                | Token::Variable(_)
+        ) {
+            doc_comment.clear();
+        }
+
         match &tokens[*i] {
             Token::Comment(value) => {
                 ast.push(AstNode::Comment(value.clone()));
             }
+            Token::DocComment(value) => {
+                if !doc_comment.is_empty() {
+                    doc_comment.push('\n');
+                }
+                doc_comment.push_str(value);
+            }
             Token::Import => {
                 if !module_scope {
                     red_ln!("Error: Import found outside of module scope");
@@ -59,14 +84,15 @@ pub fn new_ast(
                     ));
                 }
 
-                let starting_line_number = &token_line_numbers[*i];
+                variable_declarations.push(ScopeKind::Scene);
                 ast.push(new_scene(
                     &tokens,
                     i,
                     &ast,
-                    starting_line_number,
-                    &variable_declarations,
+                    token_line_numbers,
+                    variable_declarations,
                 ));
+                variable_declarations.pop();
             }
 
             Token::ModuleStart(_) => {
@@ -75,19 +101,60 @@ pub fn new_ast(
 
             // New Function or Variable declaration
             Token::Variable(name) => {
-                ast.push(create_new_var_or_ref(
+                let line_number = token_line_numbers[*i];
+                let node = create_new_var_or_ref(
                     name,
-                    &mut variable_declarations,
+                    variable_declarations,
                     &tokens,
                     i,
                     exported,
                     &ast,
                     token_line_numbers,
-                ));
+                );
+
+                // So code generated from the statement can be traced back to it
+                if matches!(
+                    node,
+                    AstNode::VarDeclaration(..) | AstNode::Assignment(..) | AstNode::Function(..)
+                ) {
+                    ast.push(AstNode::LineNumber(line_number));
+                }
+
+                let doc_comment = std::mem::take(&mut doc_comment);
+                if !doc_comment.is_empty()
+                    && matches!(node, AstNode::VarDeclaration(..) | AstNode::Function(..))
+                {
+                    ast.push(AstNode::DocComment(doc_comment));
+                }
+                ast.push(node);
+
+                // Export only applies to the declaration straight after it
+                exported = false;
             }
             Token::Export => {
                 exported = true;
             }
+
+            // Values that don't start with a name can only be sent to a channel
+            Token::FloatLiteral(_)
+            | Token::IntLiteral(_)
+            | Token::StringLiteral(_)
+            | Token::StringTemplate(..)
+            | Token::BoolLiteral(_)
+            | Token::OpenParenthesis
+            | Token::Negative
+            | Token::Math
+            | Token::Receive
+                if has_send(&tokens, *i) =>
+            {
+                ast.push(create_channel_send(
+                    &tokens,
+                    i,
+                    &ast,
+                    token_line_numbers,
+                    variable_declarations,
+                ));
+            }
             Token::JS(value) => {
                 ast.push(AstNode::JS(value.clone()));
             }
@@ -126,23 +193,28 @@ pub fn new_ast(
             }
 
             Token::Print => {
+                // Any value can be printed
                 let required_args: Vec<Reference> = vec![Reference {
                     name: "src".to_string(),
-                    data_type: DataType::String,
+                    data_type: DataType::Inferred,
                     default_value: None,
                 }];
-                let line_number = token_line_numbers[*i];
 
                 // Move past the print keyword
+                ast.push(AstNode::LineNumber(token_line_numbers[*i]));
                 *i += 1;
                 let eval_arg = match get_args(
                     &tokens,
                     &mut *i,
                     &ast,
-                    &line_number,
-                    &variable_declarations,
+                    token_line_numbers,
+                    variable_declarations,
                     &required_args,
                 ) {
+                    Some(AstNode::Error(err, line)) => {
+                        ast.push(AstNode::Error(err, line));
+                        continue;
+                    }
                     Some(arg) => arg,
                     None => {
                         continue;
@@ -151,28 +223,53 @@ pub fn new_ast(
                 ast.push(AstNode::Print(Box::new(eval_arg)));
             }
 
-            Token::DeadVarible(name) => {
-                // Remove entire declaration or scope of variable declaration
-                // So don't put any dead code into the AST
-                skip_dead_code(&tokens, i);
+            Token::If => {
+                let if_statement = create_if_statement(
+                    &tokens,
+                    i,
+                    &ast,
+                    token_line_numbers,
+                    variable_declarations,
+                    return_type,
+                );
+                if always_returns(std::slice::from_ref(&if_statement)) {
+                    needs_to_return = false;
+                }
+                ast.push(if_statement);
+            }
+
+            Token::For => {
+                ast.push(create_for_loop(
+                    &tokens,
+                    i,
+                    &ast,
+                    token_line_numbers,
+                    variable_declarations,
+                    return_type,
+                ));
+            }
+
+            // Ends the body of an if statement without moving past the else keyword
+            Token::Else => {
+                if variable_declarations.current_kind() == &ScopeKind::If {
+                    break;
+                }
+
                 ast.push(AstNode::Error(
-                    format!(
-                        "Dead Variable Declaration. Variable is never used or declared: {}",
-                        name
-                    ),
-                    token_line_numbers[*i - 1],
+                    "Else used without an if statement".to_string(),
+                    token_line_numbers[*i],
                 ));
             }
 
             Token::Return => {
-                if module_scope {
+                if !variable_declarations.in_function() {
                     ast.push(AstNode::Error(
                         "Return statement used outside of function".to_string(),
                         token_line_numbers[*i],
                     ));
                 }
 
-                if !needs_to_return {
+                if return_type == &DataType::None {
                     ast.push(AstNode::Error(
                         "Return statement used in function that doesn't return a value".to_string(),
                         token_line_numbers[*i],
@@ -180,18 +277,18 @@ pub fn new_ast(
                 }
 
                 needs_to_return = false;
+                ast.push(AstNode::LineNumber(token_line_numbers[*i]));
                 *i += 1;
 
-                let starting_line_number = &token_line_numbers[*i];
                 let return_value = create_expression(
                     &tokens,
                     i,
                     false,
                     &ast,
-                    starting_line_number,
+                    token_line_numbers,
                     &mut return_type.to_owned(),
                     false,
-                    &variable_declarations,
+                    variable_declarations,
                 );
 
                 ast.push(AstNode::Return(Box::new(return_value)));
@@ -211,6 +308,10 @@ pub fn new_ast(
                 break;
             }
 
+            Token::Error(message, line_number) => {
+                ast.push(AstNode::Error(message.to_owned(), *line_number));
+            }
+
             // Or stuff that hasn't been implemented yet
             _ => {
                 ast.push(AstNode::Error(
@@ -230,61 +331,23 @@ pub fn new_ast(
         ));
     }
 
-    (ast, imports)
-}
-
-fn skip_dead_code(tokens: &Vec<Token>, i: &mut usize) {
-    // Check what type of dead code it is
-    // If it is a variable declaration, skip to the end of the declaration
-
-    *i += 1;
-    match tokens.get(*i).unwrap_or(&Token::EOF) {
-        Token::TypeKeyword(_) => {
-            *i += 1;
-            match tokens.get(*i).unwrap_or(&Token::EOF) {
-                Token::Assign => {
-                    *i += 1;
-                }
-                _ => {
-                    return;
-                }
-            }
-        }
-        Token::Assign => {
-            *i += 1;
-        }
-        Token::Newline => {
-            *i += 1;
-            return;
-        }
-        _ => {
-            return;
+    if module_scope {
+        if let Some(error) = find_deadlock(&ast) {
+            ast.push(error);
         }
     }
 
-    // Skip to end of variable declaration
-    let mut open_parenthesis = 0;
-    while let Some(token) = tokens.get(*i) {
-        match token {
-            Token::OpenParenthesis => {
-                open_parenthesis += 1;
-            }
-            Token::CloseParenthesis => {
-                open_parenthesis -= 1;
-            }
-            Token::Newline => {
-                if open_parenthesis < 1 {
-                    return;
-                }
-            }
-            Token::EOF | Token::End => {
-                break;
-            }
-            _ => {}
-        }
+    // Calls run at compile time have gone from the AST,
+    // but the functions they called are still used by this block.
+    // A return has to stay the last statement of a function
+    let end = match ast.last() {
+        Some(AstNode::Return(_)) => ast.len() - 1,
+        _ => ast.len(),
+    };
+    let folded_calls = variable_declarations.take_folded_calls();
+    ast.splice(end..end, folded_calls.into_iter().map(AstNode::FoldedCall));
 
-        *i += 1;
-    }
+    (ast, imports)
 }
 
 // pub fn get_var_declaration_type(var_name: String, ast: &Vec<AstNode>) -> DataType {
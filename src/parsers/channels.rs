@@ -0,0 +1,450 @@
+use std::collections::HashMap;
+
+use crate::{bs_types::DataType, settings::BS_VAR_PREFIX, Token};
+
+use super::{
+    ast_nodes::{AstNode, Reference},
+    expressions::parse_expression::create_expression,
+    ownership::move_if_owned,
+    scope::{undefined_name_error, ScopeKind, ScopeStack},
+};
+
+/*
+    Channels pass values between different parts of a program
+
+    ch <<Int>>      Creates a channel that Ints can be sent through
+    5 >> ch         Sends a value to the channel
+    x = << ch       Waits for the next value from the channel
+    ch close()      Nothing can be sent or received after the channel is closed
+*/
+
+// Creates a new channel, the current token should be the '<<' after the name
+pub fn create_channel(
+    name: &str,
+    tokens: &[Token],
+    i: &mut usize,
+    is_exported: bool,
+    token_line_numbers: &[u32],
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    let line_number = token_line_numbers[*i];
+
+    if variable_declarations.current_kind() != &ScopeKind::Module {
+        return AstNode::Error(
+            format!("Channel '{name}' must be created at the top level of a module"),
+            line_number,
+        );
+    }
+
+    if is_exported {
+        return AstNode::Error(format!("Channel '{name}' can't be exported"), line_number);
+    }
+
+    let inner_type = match (tokens.get(*i + 1), tokens.get(*i + 2)) {
+        (Some(Token::TypeKeyword(inner_type)), Some(Token::Send)) => inner_type.to_owned(),
+        _ => {
+            return AstNode::Error(
+                format!("Expected the type of the values sent through '{name}', like <<Int>>"),
+                line_number,
+            );
+        }
+    };
+    *i += 2;
+
+    if !matches!(
+        tokens.get(*i + 1),
+        Some(Token::Newline | Token::EOF | Token::End) | None
+    ) {
+        return AstNode::Error(
+            format!("Channel '{name}' can't be given a value when it is created"),
+            line_number,
+        );
+    }
+
+    let data_type = DataType::Channel(Box::new(inner_type));
+    variable_declarations.declare(Reference {
+        name: name.to_owned(),
+        data_type: data_type.to_owned(),
+        default_value: None,
+    });
+
+    AstNode::VarDeclaration(
+        name.to_owned(),
+        Box::new(AstNode::Empty),
+        false,
+        data_type,
+        false,
+    )
+}
+
+// Finds the '>>' in the rest of the line, ignoring anything inside of brackets
+fn find_send(tokens: &[Token], mut i: usize) -> Option<usize> {
+    let mut depth = 0;
+    while let Some(token) = tokens.get(i) {
+        match token {
+            Token::Send if depth == 0 => return Some(i),
+            Token::OpenParenthesis => depth += 1,
+            Token::CloseParenthesis => {
+                if depth == 0 {
+                    return None;
+                }
+                depth -= 1;
+            }
+            Token::Newline | Token::Colon if depth == 0 => return None,
+            Token::EOF
+            | Token::SceneHead
+            | Token::ParentScene
+            | Token::FunctionKeyword
+            | Token::Arrow
+            | Token::End => return None,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+pub fn has_send(tokens: &[Token], i: usize) -> bool {
+    find_send(tokens, i).is_some()
+}
+
+// Everything before the '>>' is the value sent to the channel after it
+// Current token should be the start of the value, this moves to the name of the channel
+pub fn create_channel_send(
+    tokens: &Vec<Token>,
+    i: &mut usize,
+    ast: &Vec<AstNode>,
+    token_line_numbers: &Vec<u32>,
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    let line_number = token_line_numbers[*i];
+    let send_index = match find_send(tokens, *i) {
+        Some(index) => index,
+        None => {
+            return AstNode::Error("Expected '>>' after the value".to_string(), line_number);
+        }
+    };
+
+    let (name, inner_type) =
+        match get_channel(tokens, send_index + 1, line_number, variable_declarations) {
+            Ok(channel) => channel,
+            Err(err) => return err,
+        };
+
+    if !matches!(
+        tokens.get(send_index + 2),
+        Some(Token::Newline | Token::EOF | Token::End) | None
+    ) {
+        return AstNode::Error(
+            format!("Nothing can come after the channel in a send to '{name}'"),
+            line_number,
+        );
+    }
+
+    if *i == send_index {
+        return AstNode::Error(format!("Expected a value to send to '{name}'"), line_number);
+    }
+
+    let value = create_expression(
+        tokens,
+        i,
+        false,
+        ast,
+        token_line_numbers,
+        &mut inner_type.to_owned(),
+        false,
+        variable_declarations,
+    );
+
+    if let AstNode::Error(err, line) = value {
+        return AstNode::Error(
+            format!(
+                "Can't send this value to '{name}' (type {:?}): {err}",
+                inner_type
+            ),
+            line,
+        );
+    }
+
+    // Collections sent to a channel are moved
+    if let Err(err) = move_if_owned(&value, line_number, variable_declarations) {
+        return AstNode::Error(err, line_number);
+    }
+
+    *i = send_index + 1;
+    AstNode::ChannelSend(name, Box::new(value))
+}
+
+// Current token should be the '<<', this moves to the name of the channel
+pub fn create_channel_receive(
+    tokens: &[Token],
+    i: &mut usize,
+    line_number: &u32,
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    let (name, inner_type) = match get_channel(
+        tokens,
+        *i + 1,
+        line_number.to_owned(),
+        variable_declarations,
+    ) {
+        Ok(channel) => channel,
+        Err(err) => return err,
+    };
+
+    // Functions are run straight away, so they can't wait for a value
+    if variable_declarations.in_function() {
+        return AstNode::Error(
+            format!("Can't receive from '{name}' inside a function"),
+            line_number.to_owned(),
+        );
+    }
+
+    *i += 1;
+    AstNode::ChannelReceive(name, inner_type)
+}
+
+// Current token should be the name of the channel, this moves to the closing parenthesis
+pub fn create_channel_close(
+    name: &str,
+    tokens: &[Token],
+    i: &mut usize,
+    line_number: u32,
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    match (tokens.get(*i + 2), tokens.get(*i + 3)) {
+        (Some(Token::OpenParenthesis), Some(Token::CloseParenthesis)) => {}
+        _ => {
+            return AstNode::Error(format!("Expected '()' after '{name} close'"), line_number);
+        }
+    }
+
+    if variable_declarations.is_channel_closed(name) {
+        return AstNode::Error(format!("Channel '{name}' is already closed"), line_number);
+    }
+
+    variable_declarations.close_channel(name);
+    *i += 3;
+    AstNode::ChannelClose(name.to_owned())
+}
+
+// Returns the name of the channel and the type of the values sent through it
+fn get_channel(
+    tokens: &[Token],
+    index: usize,
+    line_number: u32,
+    variable_declarations: &mut ScopeStack,
+) -> Result<(String, DataType), AstNode> {
+    let name = match tokens.get(index) {
+        Some(Token::Variable(name)) => name,
+        _ => {
+            return Err(AstNode::Error(
+                "Expected the name of a channel".to_string(),
+                line_number,
+            ));
+        }
+    };
+
+    let var = match variable_declarations.get(name) {
+        Some(var) => var,
+        None => return Err(undefined_name_error(name, tokens, index, line_number)),
+    };
+
+    let inner_type = match &var.data_type {
+        DataType::Channel(inner_type) => *inner_type.to_owned(),
+        _ => {
+            return Err(AstNode::Error(
+                format!("'{name}' is not a channel (type {:?})", var.data_type),
+                line_number,
+            ));
+        }
+    };
+
+    if variable_declarations.is_channel_closed(name) {
+        return Err(AstNode::Error(
+            format!("Can't use '{name}' after it is closed"),
+            line_number,
+        ));
+    }
+
+    Ok((name.to_owned(), inner_type))
+}
+
+// Receiving at the top level of a module waits for a value before running the rest of the module
+// Finds a receive that could never get one, because nothing was sent before it
+// and nothing else can send to the channel in the meantime
+pub fn find_deadlock(ast: &[AstNode]) -> Option<AstNode> {
+    // Functions and JS can send to a channel at any time, like when something on the page is clicked
+    let mut senders = Senders::default();
+    senders.collect(ast, false);
+
+    // Most values each channel could be holding, None if it can't be known
+    let mut pending = HashMap::new();
+    senders.check_block(ast, &mut pending, &mut 0).err()
+}
+
+#[derive(Default)]
+struct Senders {
+    any_time: Vec<String>,
+    js: Vec<String>,
+}
+
+type Pending = HashMap<String, Option<usize>>;
+
+impl Senders {
+    fn collect(&mut self, nodes: &[AstNode], in_function: bool) {
+        for node in nodes {
+            match node {
+                AstNode::ChannelSend(name, _) if in_function => self.any_time.push(name.to_owned()),
+                AstNode::JS(code) => self.js.push(code.to_owned()),
+                AstNode::Function(_, _, body, ..) | AstNode::AnonymousFunction(_, body, _) => {
+                    self.collect(body, true)
+                }
+                AstNode::For(_, _, body) => self.collect(body, in_function),
+                AstNode::If(_, body, else_body) => {
+                    self.collect(body, in_function);
+                    self.collect(else_body, in_function);
+                }
+                AstNode::VarDeclaration(_, value, ..) => {
+                    self.collect(std::slice::from_ref(value), in_function)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn can_send_any_time(&self, name: &str) -> bool {
+        self.any_time.iter().any(|sender| sender == name)
+            || self
+                .js
+                .iter()
+                .any(|code| code.contains(&format!("{BS_VAR_PREFIX}{name}")))
+    }
+
+    fn check_block(
+        &self,
+        nodes: &[AstNode],
+        pending: &mut Pending,
+        line_number: &mut u32,
+    ) -> Result<(), AstNode> {
+        for node in nodes {
+            match node {
+                AstNode::LineNumber(line) => *line_number = *line,
+
+                // Either body might run, so take the most values left by either of them
+                AstNode::If(condition, body, else_body) => {
+                    self.check_receives(condition, pending, *line_number)?;
+                    let mut after_body = pending.to_owned();
+                    self.check_block(body, &mut after_body, line_number)?;
+                    let mut after_else = pending.to_owned();
+                    self.check_block(else_body, &mut after_else, line_number)?;
+                    *pending = most_pending(after_body, after_else);
+                }
+
+                // The body might not run at all, or run any number of times
+                AstNode::For(_, iterable, body) => {
+                    self.check_receives(iterable, pending, *line_number)?;
+                    let mut after_body = pending.to_owned();
+                    self.check_block(body, &mut after_body, line_number)?;
+                    let mut sends = Vec::new();
+                    find_sends(body, &mut sends);
+                    *pending = most_pending(pending.to_owned(), after_body);
+                    for name in sends {
+                        pending.insert(name, None);
+                    }
+                }
+
+                AstNode::Function(..) | AstNode::AnonymousFunction(..) => {}
+
+                AstNode::ChannelSend(name, value) => {
+                    self.check_receives(value, pending, *line_number)?;
+                    let count = pending.entry(name.to_owned()).or_insert(Some(0));
+                    *count = count.map(|count| count + 1);
+                }
+
+                _ => self.check_receives(node, pending, *line_number)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn check_receives(
+        &self,
+        node: &AstNode,
+        pending: &mut Pending,
+        line_number: u32,
+    ) -> Result<(), AstNode> {
+        let mut receives = Vec::new();
+        find_receives(node, &mut receives);
+
+        for name in receives {
+            if self.can_send_any_time(name) {
+                continue;
+            }
+            match pending.entry(name.to_owned()).or_insert(Some(0)) {
+                Some(0) => {
+                    return Err(AstNode::Error(
+                        format!(
+                            "Receiving from '{name}' here would wait forever, as nothing is sent to it before this"
+                        ),
+                        line_number,
+                    ));
+                }
+                Some(count) => *count -= 1,
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn most_pending(first: Pending, second: Pending) -> Pending {
+    let mut most = first;
+    for (name, count) in second {
+        let existing = most.get(&name).copied().unwrap_or(Some(0));
+        let count = match (existing, count) {
+            (Some(existing), Some(count)) => Some(existing.max(count)),
+            _ => None,
+        };
+        most.insert(name, count);
+    }
+    most
+}
+
+fn find_sends(nodes: &[AstNode], sends: &mut Vec<String>) {
+    for node in nodes {
+        match node {
+            AstNode::ChannelSend(name, _) => sends.push(name.to_owned()),
+            AstNode::For(_, _, body) => find_sends(body, sends),
+            AstNode::If(_, body, else_body) => {
+                find_sends(body, sends);
+                find_sends(else_body, sends);
+            }
+            _ => {}
+        }
+    }
+}
+
+// Receives in the order they run in a statement or expression
+fn find_receives<'a>(node: &'a AstNode, receives: &mut Vec<&'a String>) {
+    match node {
+        AstNode::ChannelReceive(name, _) => receives.push(name),
+        AstNode::VarDeclaration(_, value, ..)
+        | AstNode::Assignment(_, _, value, _)
+        | AstNode::Print(value)
+        | AstNode::Return(value)
+        | AstNode::Copy(value)
+        | AstNode::ChannelSend(_, value)
+        | AstNode::FunctionCall(_, value, _) => find_receives(value, receives),
+        AstNode::Expression(nodes, _)
+        | AstNode::RuntimeExpression(nodes, _)
+        | AstNode::Collection(nodes, _)
+        | AstNode::Tuple(nodes, _) => {
+            for node in nodes {
+                find_receives(node, receives);
+            }
+        }
+        _ => {}
+    }
+}
@@ -1,12 +1,15 @@
-use super::{ast_nodes::AstNode, expressions::parse_expression::create_expression};
+use super::{
+    ast_nodes::AstNode, expressions::parse_expression::create_expression, scope::ScopeStack,
+};
 use crate::{bs_types::DataType, Token};
 
 pub fn new_collection(
     tokens: &Vec<Token>,
     i: &mut usize,
     ast: &Vec<AstNode>,
-    starting_line_number: &u32,
+    token_line_numbers: &Vec<u32>,
     collection_type: &mut DataType,
+    variable_declarations: &mut ScopeStack,
 ) -> AstNode {
     let mut items: Vec<AstNode> = Vec::new();
     if *collection_type == DataType::Inferred {
@@ -30,10 +33,10 @@ pub fn new_collection(
                     i,
                     true,
                     ast,
-                    starting_line_number,
+                    token_line_numbers,
                     collection_type,
                     tokens[*i] == Token::OpenParenthesis,
-                    &Vec::new(),
+                    variable_declarations,
                 ));
             }
         }
@@ -0,0 +1,297 @@
+use super::{
+    ast_nodes::{AstNode, Node, Reference},
+    build_ast::new_ast,
+    expressions::parse_expression::create_expression,
+    ownership::moved_value_error,
+    scope::{undefined_name_error, ScopeKind, ScopeStack},
+    unions::{create_type_check, is_type_check, union_of},
+};
+use crate::{bs_types::DataType, Token};
+
+pub fn create_if_statement(
+    tokens: &Vec<Token>,
+    i: &mut usize,
+    ast: &Vec<AstNode>,
+    token_line_numbers: &Vec<u32>,
+    variable_declarations: &mut ScopeStack,
+    return_type: &DataType,
+) -> AstNode {
+    /*
+        if condition:
+            -- Body
+        else if other_condition:
+            -- Body
+        else
+            -- Else body
+        end
+    */
+    let line_number = token_line_numbers[*i];
+
+    // Move past the if keyword
+    *i += 1;
+
+    // Checking the type of a union narrows it inside of the body
+    // Name, type inside the body, types left for the else body
+    let mut narrowed: Option<(String, DataType, Vec<DataType>)> = None;
+    let condition = if is_type_check(tokens, *i) {
+        match create_type_check(tokens, i, line_number, variable_declarations) {
+            Ok((condition, checked_type, remaining)) => {
+                if let AstNode::TypeCheck(ref name, _) = condition {
+                    narrowed = Some((name.to_owned(), checked_type, remaining));
+                }
+                condition
+            }
+            Err(err) => return err,
+        }
+    } else {
+        create_expression(
+            tokens,
+            i,
+            false,
+            ast,
+            token_line_numbers,
+            &mut DataType::Bool,
+            false,
+            variable_declarations,
+        )
+    };
+
+    if let AstNode::Error(..) = condition {
+        return condition;
+    }
+
+    if tokens.get(*i) != Some(&Token::Colon) {
+        return AstNode::Error(
+            "Expected ':' after the condition of an if statement".to_string(),
+            line_number,
+        );
+    }
+    *i += 1;
+
+    variable_declarations.push(ScopeKind::If);
+    if let Some((name, checked_type, _)) = &narrowed {
+        declare_narrowed(name, checked_type, variable_declarations);
+    }
+    let body = new_ast(
+        tokens.to_vec(),
+        i,
+        token_line_numbers,
+        variable_declarations,
+        return_type,
+        false,
+    )
+    .0;
+    variable_declarations.pop();
+
+    // The body stops at the else keyword without moving past it
+    let mut else_body = Vec::new();
+    if tokens.get(*i) == Some(&Token::Else) {
+        *i += 1;
+
+        // Else if shares the same 'end' keyword as the rest of the if statement
+        if tokens.get(*i) == Some(&Token::If) {
+            variable_declarations.push(ScopeKind::If);
+            if let Some((name, _, remaining)) = &narrowed {
+                if !remaining.is_empty() {
+                    declare_narrowed(name, &union_of(remaining.to_owned()), variable_declarations);
+                }
+            }
+            else_body.push(create_if_statement(
+                tokens,
+                i,
+                ast,
+                token_line_numbers,
+                variable_declarations,
+                return_type,
+            ));
+            variable_declarations.pop();
+            return AstNode::If(Box::new(condition), body, else_body);
+        }
+
+        if tokens.get(*i) == Some(&Token::Colon) {
+            *i += 1;
+        }
+
+        variable_declarations.push(ScopeKind::If);
+        if let Some((name, _, remaining)) = &narrowed {
+            if !remaining.is_empty() {
+                declare_narrowed(name, &union_of(remaining.to_owned()), variable_declarations);
+            }
+        }
+        else_body = new_ast(
+            tokens.to_vec(),
+            i,
+            token_line_numbers,
+            variable_declarations,
+            return_type,
+            false,
+        )
+        .0;
+        variable_declarations.pop();
+
+        if tokens.get(*i) == Some(&Token::Else) {
+            return AstNode::Error(
+                "If statement can only have one else block".to_string(),
+                token_line_numbers[*i],
+            );
+        }
+    } else if let Some((name, _, remaining)) = &narrowed {
+        // Without an else block, every type in the union has to be checked for
+        if !remaining.is_empty() {
+            return AstNode::Error(
+                format!(
+                    "'{name}' could also be {:?}, check for it or add an else block",
+                    union_of(remaining.to_owned())
+                ),
+                line_number,
+            );
+        }
+    }
+
+    AstNode::If(Box::new(condition), body, else_body)
+}
+
+// Whether every path through the body ends with a return
+// Checking a union with 'is' and no else block covers every type in the union,
+// so only the body of the if statement has to return
+pub fn always_returns(body: &[AstNode]) -> bool {
+    body.iter().any(|node| match node {
+        AstNode::Return(_) => true,
+        AstNode::If(condition, if_body, else_body) => {
+            always_returns(if_body)
+                && (always_returns(else_body)
+                    || **condition == AstNode::Literal(Token::BoolLiteral(true))
+                    || (else_body.is_empty() && matches!(**condition, AstNode::TypeCheck(..))))
+        }
+        _ => false,
+    })
+}
+
+// The variable has the narrowed type until the end of the current scope
+fn declare_narrowed(name: &str, data_type: &DataType, variable_declarations: &mut ScopeStack) {
+    let default_value = variable_declarations
+        .get(name)
+        .and_then(|var| var.default_value.to_owned());
+    variable_declarations.declare(Reference {
+        name: name.to_owned(),
+        data_type: data_type.to_owned(),
+        default_value,
+    });
+}
+
+pub fn create_for_loop(
+    tokens: &Vec<Token>,
+    i: &mut usize,
+    ast: &Vec<AstNode>,
+    token_line_numbers: &Vec<u32>,
+    variable_declarations: &mut ScopeStack,
+    return_type: &DataType,
+) -> AstNode {
+    /*
+        for item in collection:
+            -- Body
+        end
+
+        -- Counts from 0 up to (but not including) the integer
+        for n in 10:
+            -- Body
+        end
+    */
+    let line_number = token_line_numbers[*i];
+
+    // Move past the for keyword
+    *i += 1;
+
+    let item_name = match tokens.get(*i) {
+        Some(Token::Variable(name)) => name.to_owned(),
+        _ => {
+            return AstNode::Error(
+                "Expected a name for each item after the 'for' keyword".to_string(),
+                line_number,
+            );
+        }
+    };
+
+    *i += 1;
+    if tokens.get(*i) != Some(&Token::In) {
+        return AstNode::Error(
+            "Expected the 'in' keyword after the name of the loop item".to_string(),
+            line_number,
+        );
+    }
+    *i += 1;
+
+    let iterable = match (tokens.get(*i), tokens.get(*i + 1)) {
+        // A reference to a collection can't be used inside of an expression yet
+        (Some(Token::Variable(name)), Some(Token::Colon)) => {
+            if let Some(moved_on) = variable_declarations.moved_on(name) {
+                return moved_value_error(name, moved_on, line_number);
+            }
+            match variable_declarations.get(name) {
+                Some(var) => {
+                    *i += 1;
+                    AstNode::VarReference(var.name.to_owned(), var.data_type.to_owned())
+                }
+                None => return undefined_name_error(name, tokens, *i, line_number),
+            }
+        }
+        _ => create_expression(
+            tokens,
+            i,
+            false,
+            ast,
+            token_line_numbers,
+            &mut DataType::Int,
+            false,
+            variable_declarations,
+        ),
+    };
+
+    let item_type = match iterable.get_type() {
+        DataType::Collection(inner_type) => *inner_type,
+        DataType::Int => DataType::Int,
+        _ => {
+            if let AstNode::Error(..) = iterable {
+                return iterable;
+            }
+            return AstNode::Error(
+                format!(
+                    "Can only loop through collections or integers, not {:?}",
+                    iterable.get_type()
+                ),
+                line_number,
+            );
+        }
+    };
+
+    if tokens.get(*i) != Some(&Token::Colon) {
+        return AstNode::Error(
+            "Expected ':' to open the body of the for loop".to_string(),
+            line_number,
+        );
+    }
+    *i += 1;
+
+    variable_declarations.push(ScopeKind::For);
+    if let Err(err) = variable_declarations.check_shadowing(&item_name) {
+        variable_declarations.pop();
+        return AstNode::Error(err, line_number);
+    }
+    variable_declarations.declare(Reference {
+        name: item_name.to_owned(),
+        data_type: item_type,
+        default_value: None,
+    });
+    let body = new_ast(
+        tokens.to_vec(),
+        i,
+        token_line_numbers,
+        variable_declarations,
+        return_type,
+        false,
+    )
+    .0;
+    variable_declarations.pop();
+
+    AstNode::For(item_name, Box::new(iterable), body)
+}
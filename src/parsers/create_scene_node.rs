@@ -1,6 +1,7 @@
 use super::{
     ast_nodes::{AstNode, Reference},
     expressions::parse_expression::{create_expression, get_args},
+    scope::ScopeStack,
     styles::{Action, Style, Tag},
     util::{count_newlines_at_end_of_string, count_newlines_at_start_of_string},
 };
@@ -11,9 +12,10 @@ pub fn new_scene(
     tokens: &Vec<Token>,
     i: &mut usize,
     ast: &Vec<AstNode>,
-    token_line_number: &u32,
-    variable_declarations: &Vec<Reference>,
+    token_line_numbers: &Vec<u32>,
+    variable_declarations: &mut ScopeStack,
 ) -> AstNode {
+    let token_line_number = &token_line_numbers[*i];
     let mut scene = Vec::new();
     *i += 1;
 
@@ -50,7 +52,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -78,7 +80,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -128,7 +130,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -154,7 +156,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -181,7 +183,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -225,7 +227,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -271,7 +273,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -304,7 +306,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -339,7 +341,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -376,7 +378,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -418,7 +420,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -449,7 +451,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -476,7 +478,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -495,22 +497,33 @@ pub fn new_scene(
 
             // Expressions to Parse
             Token::Variable(_)
+            | Token::Math
+            | Token::TypeKeyword(DataType::String)
             | Token::FloatLiteral(_)
             | Token::BoolLiteral(_)
             | Token::IntLiteral(_)
             | Token::StringLiteral(_)
+            | Token::StringTemplate(..)
             | Token::RawStringLiteral(_) => {
                 *i -= 1;
-                scene.push(create_expression(
+                let value = create_expression(
                     tokens,
                     &mut *i,
                     false,
                     &ast,
-                    token_line_number,
+                    token_line_numbers,
                     &mut DataType::CoerseToString,
                     inside_brackets,
                     variable_declarations,
-                ));
+                );
+
+                // The expression stops at the token that caused the error,
+                // so the rest of the scene is skipped instead of being parsed again
+                if let AstNode::Error(..) = value {
+                    skip_to_scene_close(tokens, i);
+                    return value;
+                }
+                scene.push(value);
             }
 
             Token::Comma | Token::Newline | Token::Empty => {}
@@ -546,7 +559,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -588,7 +601,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -614,7 +627,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -653,7 +666,7 @@ pub fn new_scene(
                     tokens,
                     &mut *i,
                     ast,
-                    token_line_number,
+                    token_line_numbers,
                     variable_declarations,
                     &required_args,
                 ) {
@@ -702,7 +715,12 @@ pub fn new_scene(
 
             Token::SceneHead => {
                 let nested_scene =
-                    new_scene(tokens, i, ast, token_line_number, variable_declarations);
+                    new_scene(tokens, i, ast, token_line_numbers, variable_declarations);
+                if let AstNode::Error(..) = nested_scene {
+                    *i += 1;
+                    skip_to_scene_close(tokens, i);
+                    return nested_scene;
+                }
                 scene.push(nested_scene);
             }
 
@@ -752,13 +770,6 @@ pub fn new_scene(
 
             Token::Empty | Token::Colon => {}
 
-            Token::DeadVarible(name) => {
-                scene.push(AstNode::Error(
-                    format!("Dead Variable used in scene. '{}' was never defined", name),
-                    token_line_number.to_owned(),
-                ));
-            }
-
             _ => {
                 scene.push(AstNode::Error(
                     format!(
@@ -776,6 +787,21 @@ pub fn new_scene(
     AstNode::Scene(scene, scene_tags, scene_styles, scene_actions)
 }
 
+// Moves to the SceneClose of the current scene, skipping over any nested scenes
+fn skip_to_scene_close(tokens: &[Token], i: &mut usize) {
+    let mut depth = 0;
+    while let Some(token) = tokens.get(*i) {
+        match token {
+            Token::SceneHead => depth += 1,
+            Token::SceneClose(_) if depth == 0 => return,
+            Token::SceneClose(_) => depth -= 1,
+            Token::EOF => return,
+            _ => {}
+        }
+        *i += 1;
+    }
+}
+
 fn check_if_inline(tokens: &Vec<Token>, i: usize, merge_next_p_line: &mut bool) -> bool {
     // If the element itself starts with Newlines, it should not be inlined
     let current_element = &tokens[i];
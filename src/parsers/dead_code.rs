@@ -0,0 +1,474 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    ast_nodes::{AstNode, Reference},
+    styles::{Action, Style, Tag},
+};
+use crate::settings::BS_VAR_PREFIX;
+
+// The AST, the imports that are still needed and warnings for everything removed
+type Stripped = (Vec<AstNode>, Vec<AstNode>, Vec<String>);
+
+// Removes variables, functions and imports that are never used from the AST
+// Exported declarations are always kept, since other modules or the page can use them
+pub fn remove_dead_code(ast: Vec<AstNode>, imports: Vec<AstNode>) -> Result<Stripped, String> {
+    // Errors inside of unused code would be removed with it
+    if let Some(AstNode::Error(err, line_number)) = find_error(&ast) {
+        return Err(format!("Error on Line {}: - {}", line_number, err));
+    }
+
+    let mut warnings = Vec::new();
+    let mut ast = ast;
+
+    // Removing a declaration can make everything it referenced unused as well,
+    // so keep going until nothing else is removed
+    let usage = loop {
+        let mut usage = Usage::default();
+        usage.collect_block(&[], &ast);
+
+        let mut removed = 0;
+        ast = strip_unused(ast, &usage, &mut Vec::new(), &mut warnings, &mut removed);
+        if removed == 0 {
+            break usage;
+        }
+    };
+
+    let mut used_imports = Vec::new();
+    for import in imports {
+        let path = match &import {
+            AstNode::Use(path) => path.to_owned(),
+            _ => {
+                used_imports.push(import);
+                continue;
+            }
+        };
+
+        // Importing the same module twice does nothing
+        if used_imports.contains(&import) {
+            warnings.push(format!("Module {:?} is imported more than once", path));
+            continue;
+        }
+
+        // The last part of the path is the name of what the module exports
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default();
+        if !usage.uses_import(name) {
+            warnings.push(format!("Import {:?} is never used", path));
+            continue;
+        }
+
+        used_imports.push(import);
+    }
+
+    Ok((ast, used_imports, warnings))
+}
+
+// Where a node is in the AST, as the index of each node on the way to it
+// and which body of that node it's in
+type Location = Vec<usize>;
+
+#[derive(Default)]
+struct Usage {
+    // Declarations that are read, exported or have to be kept for another reason
+    used: HashSet<Location>,
+
+    // The declaration each assignment changes
+    assigned: HashMap<Location, Location>,
+
+    // Names that aren't declared in this module, like imports and globals
+    undeclared: HashSet<String>,
+
+    // Embedded JS can use imports through their prefixed names
+    raw_code: Vec<String>,
+
+    // Declarations in each open scope.
+    // Declarations with no location can't be removed (arguments and loop items)
+    scopes: Vec<HashMap<String, Option<Location>>>,
+    location: Location,
+
+    // Nothing is removed from inside anonymous functions
+    anonymous_functions: usize,
+}
+
+impl Usage {
+    fn uses_import(&self, name: &str) -> bool {
+        self.undeclared.contains(name)
+            || self
+                .raw_code
+                .iter()
+                .any(|code| code.contains(&format!("{BS_VAR_PREFIX}{name}")))
+    }
+
+    // Finds the declaration this name refers to from the current scope
+    fn resolve(&self, name: &str) -> Option<&Option<Location>> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn use_name(&mut self, name: &str) {
+        match self.resolve(name) {
+            Some(Some(location)) => {
+                self.used.insert(location.to_owned());
+            }
+            Some(None) => {}
+            None => {
+                self.undeclared.insert(name.to_owned());
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &str, is_exported: bool) {
+        let location = if self.anonymous_functions > 0 {
+            None
+        } else {
+            Some(self.location.to_owned())
+        };
+        if is_exported {
+            if let Some(location) = &location {
+                self.used.insert(location.to_owned());
+            }
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_owned(), location);
+        }
+    }
+
+    // Each body has its own scope and the nodes inside are located by their index
+    // Arguments and loop items are declared in the scope, but can't be removed
+    fn collect_block(&mut self, kept: &[&String], nodes: &[AstNode]) {
+        self.scopes
+            .push(kept.iter().map(|name| (name.to_string(), None)).collect());
+        for (index, node) in nodes.iter().enumerate() {
+            self.location.push(index);
+            self.collect(node);
+            self.location.pop();
+        }
+        self.scopes.pop();
+    }
+
+    fn collect_body(&mut self, branch: usize, kept: &[&String], body: &[AstNode]) {
+        self.location.push(branch);
+        self.collect_block(kept, body);
+        self.location.pop();
+    }
+
+    // Assigning a new value to a variable doesn't count as using it
+    fn collect(&mut self, node: &AstNode) {
+        match node {
+            AstNode::VarReference(name, _)
+            | AstNode::ConstReference(name, _)
+            | AstNode::JSStringReference(name)
+            | AstNode::CollectionAccess(name, ..)
+            | AstNode::TupleAccess(name, ..) => {
+                self.use_name(name);
+            }
+
+            AstNode::FunctionCall(name, args, _) | AstNode::ChannelSend(name, args) => {
+                self.use_name(name);
+                self.collect(args);
+            }
+
+            AstNode::ChannelReceive(name, _)
+            | AstNode::ChannelClose(name)
+            | AstNode::TypeCheck(name, _)
+            | AstNode::FoldedCall(name) => {
+                self.use_name(name);
+            }
+
+            // The value can still use a name from outside the scope that this declaration shadows
+            AstNode::VarDeclaration(name, value, is_exported, ..) => {
+                self.collect(value);
+                self.declare(name, *is_exported);
+            }
+
+            // Declared before the body, so it can call itself
+            AstNode::Function(name, args, body, is_exported, _) => {
+                self.declare(name, *is_exported);
+                self.collect_args(args);
+                let arg_names: Vec<&String> = args.iter().map(|arg| &arg.name).collect();
+                self.collect_body(0, &arg_names, body);
+            }
+
+            AstNode::AnonymousFunction(args, body, _) => {
+                self.anonymous_functions += 1;
+                self.collect_args(args);
+                let arg_names: Vec<&String> = args.iter().map(|arg| &arg.name).collect();
+                self.collect_body(0, &arg_names, body);
+                self.anonymous_functions -= 1;
+            }
+
+            // The variable has to be kept if the assignment calls a function
+            AstNode::Assignment(name, _, value, _) => {
+                self.collect(value);
+                if let Some(Some(declaration)) = self.resolve(name).cloned() {
+                    if has_side_effects(value) {
+                        self.used.insert(declaration.to_owned());
+                    }
+                    self.assigned.insert(self.location.to_owned(), declaration);
+                }
+            }
+
+            AstNode::Return(value)
+            | AstNode::Print(value)
+            | AstNode::Struct(_, value, _)
+            | AstNode::Copy(value) => {
+                self.collect(value);
+            }
+
+            AstNode::If(condition, body, else_body) => {
+                self.collect(condition);
+                self.collect_body(0, &[], body);
+                self.collect_body(1, &[], else_body);
+            }
+
+            AstNode::For(item, iterable, body) => {
+                self.collect(iterable);
+                self.collect_body(0, &[item], body);
+            }
+
+            AstNode::Expression(nodes, _)
+            | AstNode::RuntimeExpression(nodes, _)
+            | AstNode::Collection(nodes, _)
+            | AstNode::Tuple(nodes, _)
+            | AstNode::Settings(nodes) => {
+                self.collect_all(nodes);
+            }
+
+            // Scenes can use variables in their body, tags, styles or actions
+            AstNode::Scene(nodes, tags, styles, actions) => {
+                self.collect_all(nodes);
+                for tag in tags {
+                    self.collect_tag(tag);
+                }
+                for style in styles {
+                    self.collect_style(style);
+                }
+                for action in actions {
+                    if let Action::Click(value) = action {
+                        self.collect(value);
+                    }
+                }
+            }
+
+            // Embedded JS can use any variable in scope
+            AstNode::JS(code) => {
+                let used: Vec<Location> = self
+                    .scopes
+                    .iter()
+                    .flat_map(|scope| scope.iter())
+                    .filter(|(name, _)| used_in_js(code, name))
+                    .filter_map(|(_, location)| location.to_owned())
+                    .collect();
+                self.used.extend(used);
+                self.raw_code.push(code.to_owned());
+            }
+
+            _ => {}
+        }
+    }
+
+    fn collect_args(&mut self, args: &Vec<Reference>) {
+        for arg in args {
+            if let Some(default_value) = &arg.default_value {
+                self.collect(default_value);
+            }
+        }
+    }
+
+    fn collect_all(&mut self, nodes: &Vec<AstNode>) {
+        for node in nodes {
+            self.collect(node);
+        }
+    }
+
+    fn collect_tag(&mut self, tag: &Tag) {
+        match tag {
+            Tag::Redirect(value)
+            | Tag::A(value)
+            | Tag::Img(value)
+            | Tag::Video(value)
+            | Tag::Audio(value)
+            | Tag::Nav(value)
+            | Tag::Title(value)
+            | Tag::Button(value) => {
+                self.collect(value);
+            }
+            _ => {}
+        }
+    }
+
+    fn collect_style(&mut self, style: &Style) {
+        match style {
+            Style::Padding(value)
+            | Style::Margin(value)
+            | Style::Size(value)
+            | Style::TextColor(value, _)
+            | Style::BackgroundColor(value)
+            | Style::Order(value) => {
+                self.collect(value);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn strip_unused(
+    ast: Vec<AstNode>,
+    usage: &Usage,
+    location: &mut Location,
+    warnings: &mut Vec<String>,
+    removed: &mut usize,
+) -> Vec<AstNode> {
+    let mut new_ast = Vec::with_capacity(ast.len());
+
+    for (index, node) in ast.into_iter().enumerate() {
+        location.push(index);
+        let is_used = usage.used.contains(location);
+
+        match node {
+            AstNode::VarDeclaration(ref name, ref value, false, ..) if !is_used => {
+                warn_once(warnings, format!("Variable '{}' is never used", name));
+
+                // Function calls still have to run even if the result is never used
+                if has_side_effects(value) {
+                    new_ast.push(node);
+                } else {
+                    *removed += 1;
+                }
+            }
+
+            AstNode::Function(ref name, _, _, false, _) if !is_used => {
+                warn_once(warnings, format!("Function '{}' is never used", name));
+                *removed += 1;
+            }
+
+            // Nothing reads the new value, so the assignment can go with the declaration
+            AstNode::Assignment(..)
+                if usage
+                    .assigned
+                    .get(location)
+                    .is_some_and(|declaration| !usage.used.contains(declaration)) =>
+            {
+                *removed += 1;
+            }
+
+            AstNode::Function(name, args, body, is_exported, return_type) => {
+                new_ast.push(AstNode::Function(
+                    name,
+                    args,
+                    strip_body(body, 0, usage, location, warnings, removed),
+                    is_exported,
+                    return_type,
+                ));
+            }
+
+            AstNode::If(condition, body, else_body) => {
+                new_ast.push(AstNode::If(
+                    condition,
+                    strip_body(body, 0, usage, location, warnings, removed),
+                    strip_body(else_body, 1, usage, location, warnings, removed),
+                ));
+            }
+
+            AstNode::For(item, iterable, body) => {
+                new_ast.push(AstNode::For(
+                    item,
+                    iterable,
+                    strip_body(body, 0, usage, location, warnings, removed),
+                ));
+            }
+
+            _ => new_ast.push(node),
+        }
+
+        location.pop();
+    }
+
+    new_ast
+}
+
+fn strip_body(
+    body: Vec<AstNode>,
+    branch: usize,
+    usage: &Usage,
+    location: &mut Location,
+    warnings: &mut Vec<String>,
+    removed: &mut usize,
+) -> Vec<AstNode> {
+    location.push(branch);
+    let body = strip_unused(body, usage, location, warnings, removed);
+    location.pop();
+    body
+}
+
+// The first error anywhere in the AST, including inside of bodies
+fn find_error(ast: &[AstNode]) -> Option<&AstNode> {
+    ast.iter().find_map(|node| match node {
+        AstNode::Error(..) => Some(node),
+        AstNode::Function(_, _, body, ..)
+        | AstNode::AnonymousFunction(_, body, _)
+        | AstNode::For(_, _, body) => find_error(body),
+        AstNode::If(_, body, else_body) => find_error(body).or_else(|| find_error(else_body)),
+        AstNode::VarDeclaration(_, value, ..) | AstNode::Assignment(_, _, value, _) => {
+            find_error(std::slice::from_ref(value))
+        }
+        _ => None,
+    })
+}
+
+fn has_side_effects(node: &AstNode) -> bool {
+    match node {
+        AstNode::FunctionCall(..) | AstNode::ChannelReceive(..) => true,
+        AstNode::Expression(nodes, _)
+        | AstNode::RuntimeExpression(nodes, _)
+        | AstNode::Collection(nodes, _)
+        | AstNode::Tuple(nodes, _) => nodes.iter().any(has_side_effects),
+        _ => false,
+    }
+}
+
+// JS can use a variable through its prefixed name, or through the 'bs' object of the page
+fn used_in_js(code: &str, name: &str) -> bool {
+    [
+        format!("{BS_VAR_PREFIX}{name}"),
+        format!("bs.{name}"),
+        format!("bs[\"{name}\"]"),
+    ]
+    .iter()
+    .any(|pattern| code.contains(pattern))
+}
+
+fn warn_once(warnings: &mut Vec<String>, warning: String) {
+    if !warnings.contains(&warning) {
+        warnings.push(warning);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::dead_code_warnings;
+
+    const UNUSED_WARNING: &str = "Function 'double' is never used";
+
+    #[test]
+    fn unused_function_is_warned_about() {
+        let warnings = dead_code_warnings("double fn(x Int) -> Int: return x * 2\n@r = 3\n");
+        assert!(warnings.contains(&UNUSED_WARNING.to_string()));
+    }
+
+    #[test]
+    fn function_used_in_pipe_is_not_warned_about() {
+        let warnings =
+            dead_code_warnings("double fn(x Int) -> Int: return x * 2\n@r = 3 => double()\n");
+        assert!(!warnings.contains(&UNUSED_WARNING.to_string()));
+    }
+
+    #[test]
+    fn variables_used_through_bs_object_are_not_warned_about() {
+        let warnings = dead_code_warnings(
+            "y = 10\nx = \"5\"\n#JS:\n    console.log(bs.x + bs[\"y\"]);\nend\n",
+        );
+        assert!(warnings.is_empty(), "{warnings:?}");
+    }
+}
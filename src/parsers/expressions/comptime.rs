@@ -0,0 +1,457 @@
+use std::collections::HashMap;
+
+use crate::{
+    bs_types::DataType,
+    parsers::ast_nodes::{AstNode, Reference},
+    settings::{COMPTIME_CALL_DEPTH_LIMIT, COMPTIME_STEP_LIMIT},
+    stdlib::{math::math_function_from_call, string::string_function_from_call},
+    Token,
+};
+
+// Runs a function call at compile time and returns the result as a literal
+// Only works for pure functions that are called with constant arguments
+// The error explains why the call has to happen at runtime instead
+pub fn run_function_call(call: &AstNode, ast: &[AstNode]) -> Result<AstNode, String> {
+    let (name, args) = match call {
+        AstNode::FunctionCall(name, args, _) => (name, args),
+        _ => return Err("Only function calls can be run at compile time".to_string()),
+    };
+
+    let mut interpreter = Interpreter {
+        ast,
+        steps: 0,
+        depth: 0,
+    };
+
+    let args = interpreter.eval_args(args, &mut Vec::new())?;
+    let value = interpreter.call(name, args)?;
+    Ok(AstNode::Literal(value))
+}
+
+// Replaces the call with its result if it can be run at compile time
+pub fn fold_function_call(call: AstNode, ast: &[AstNode]) -> AstNode {
+    if !has_constant_args(&call) {
+        return call;
+    }
+
+    match run_function_call(&call, ast) {
+        Ok(value) => value,
+        Err(_) => call,
+    }
+}
+
+// Only calls with literal arguments are worth trying to run at compile time
+fn has_constant_args(call: &AstNode) -> bool {
+    match call {
+        AstNode::FunctionCall(_, args, _) => match &**args {
+            AstNode::Literal(_) | AstNode::Empty => true,
+            AstNode::Tuple(args, _) => args.iter().all(|arg| matches!(arg, AstNode::Literal(_))),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+// Tree walking interpreter over the AST of the module
+// Each block has its own scope of variables
+struct Interpreter<'a> {
+    ast: &'a [AstNode],
+    steps: usize,
+    depth: usize,
+}
+
+type Scopes = Vec<HashMap<String, Token>>;
+
+impl<'a> Interpreter<'a> {
+    fn step(&mut self) -> Result<(), String> {
+        self.steps += 1;
+        if self.steps > COMPTIME_STEP_LIMIT {
+            return Err(format!(
+                "it took more than {COMPTIME_STEP_LIMIT} steps to evaluate"
+            ));
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, name: &str, args: Vec<Token>) -> Result<Token, String> {
+        self.step()?;
+
+        if let Some(function) = math_function_from_call(name) {
+            return function
+                .fold(&args)
+                .ok_or_else(|| format!("it calls '{name}', which can only run at runtime"));
+        }
+        if let Some(function) = string_function_from_call(name) {
+            return function
+                .fold(&args)
+                .ok_or_else(|| format!("it calls '{name}', which can only run at runtime"));
+        }
+
+        let (arg_refs, body, return_type) = self.find_function(name)?;
+
+        if self.depth >= COMPTIME_CALL_DEPTH_LIMIT {
+            return Err(format!(
+                "'{name}' calls functions more than {COMPTIME_CALL_DEPTH_LIMIT} levels deep"
+            ));
+        }
+        if args.len() != arg_refs.len() {
+            return Err(format!(
+                "'{name}' takes {} arguments, but was given {}",
+                arg_refs.len(),
+                args.len()
+            ));
+        }
+
+        let mut scope = HashMap::new();
+        for (arg, value) in arg_refs.iter().zip(args) {
+            scope.insert(arg.name.to_owned(), value);
+        }
+        let mut scopes = vec![scope];
+
+        self.depth += 1;
+        let returned = self.run_block(body, &mut scopes)?;
+        self.depth -= 1;
+
+        match (returned, return_type) {
+            (Some(Token::IntLiteral(value)), DataType::Float) => {
+                Ok(Token::FloatLiteral(value as f64))
+            }
+            (Some(value), _) => Ok(value),
+            (None, _) => Err(format!("'{name}' doesn't return a value")),
+        }
+    }
+
+    // Named functions and variables holding anonymous functions can both be called
+    fn find_function(
+        &self,
+        name: &str,
+    ) -> Result<(&'a [Reference], &'a [AstNode], &'a DataType), String> {
+        let ast: &'a [AstNode] = self.ast;
+        for node in ast.iter().rev() {
+            match node {
+                AstNode::Function(function_name, args, body, _, return_type)
+                    if function_name == name =>
+                {
+                    return Ok((args, body, return_type));
+                }
+                AstNode::VarDeclaration(var_name, value, ..) if var_name == name => {
+                    if let AstNode::AnonymousFunction(args, body, return_type) = &**value {
+                        return Ok((args, body, return_type));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Err(format!("the body of '{name}' isn't known at this point"))
+    }
+
+    // Returns the value if the block hits a return statement
+    fn run_block(
+        &mut self,
+        body: &[AstNode],
+        scopes: &mut Scopes,
+    ) -> Result<Option<Token>, String> {
+        for node in body {
+            self.step()?;
+
+            match node {
+                AstNode::VarDeclaration(name, value, ..) => {
+                    let value = self.eval(value, scopes)?;
+                    if let Some(scope) = scopes.last_mut() {
+                        scope.insert(name.to_owned(), value);
+                    }
+                }
+
+                AstNode::Assignment(name, operator, value, _) => {
+                    let value = self.eval(value, scopes)?;
+                    let current = match scopes
+                        .iter_mut()
+                        .rev()
+                        .find_map(|scope| scope.get_mut(name))
+                    {
+                        Some(current) => current,
+                        None => {
+                            return Err(format!(
+                                "it changes '{name}', which is declared outside of the function"
+                            ));
+                        }
+                    };
+                    *current = match operator {
+                        Token::Assign => value,
+                        Token::AddAssign => binary_operation(&Token::Add, current, &value)?,
+                        Token::SubtractAssign => {
+                            binary_operation(&Token::Subtract, current, &value)?
+                        }
+                        Token::MultiplyAssign => {
+                            binary_operation(&Token::Multiply, current, &value)?
+                        }
+                        Token::DivideAssign => binary_operation(&Token::Divide, current, &value)?,
+                        Token::ModulusAssign => binary_operation(&Token::Modulus, current, &value)?,
+                        Token::RemainderAssign => {
+                            binary_operation(&Token::Remainder, current, &value)?
+                        }
+                        Token::ExponentAssign => {
+                            binary_operation(&Token::Exponent, current, &value)?
+                        }
+                        Token::RootAssign => match (&*current, value) {
+                            (Token::FloatLiteral(base), Token::FloatLiteral(root)) => {
+                                Token::FloatLiteral(base.powf(1.0 / root))
+                            }
+                            _ => return Err("roots can only be taken of floats".to_string()),
+                        },
+                        _ => return Err(format!("{:?} can't be used at compile time", operator)),
+                    };
+                }
+
+                AstNode::Return(value) => {
+                    return Ok(Some(self.eval(value, scopes)?));
+                }
+
+                AstNode::If(condition, body, else_body) => {
+                    let branch = match self.eval(condition, scopes)? {
+                        Token::BoolLiteral(true) => body,
+                        Token::BoolLiteral(false) => else_body,
+                        _ => return Err("if conditions must be booleans".to_string()),
+                    };
+
+                    scopes.push(HashMap::new());
+                    let returned = self.run_block(branch, scopes)?;
+                    scopes.pop();
+                    if returned.is_some() {
+                        return Ok(returned);
+                    }
+                }
+
+                AstNode::For(item, iterable, body) => {
+                    let count = match self.eval(iterable, scopes)? {
+                        Token::IntLiteral(count) => count,
+                        _ => {
+                            return Err(
+                                "only integers can be looped through at compile time".to_string()
+                            )
+                        }
+                    };
+
+                    for n in 0..count {
+                        self.step()?;
+                        scopes.push(HashMap::from([(item.to_owned(), Token::IntLiteral(n))]));
+                        let returned = self.run_block(body, scopes)?;
+                        scopes.pop();
+                        if returned.is_some() {
+                            return Ok(returned);
+                        }
+                    }
+                }
+
+                AstNode::FunctionCall(..) => {
+                    self.eval(node, scopes)?;
+                }
+
+                AstNode::Comment(_)
+                | AstNode::DocComment(_)
+                | AstNode::LineNumber(_)
+                | AstNode::FoldedCall(_)
+                | AstNode::Newline
+                | AstNode::Empty => {}
+
+                AstNode::Print(_) => return Err("it prints to the console".to_string()),
+                AstNode::JS(_) => return Err("it contains JavaScript".to_string()),
+                AstNode::Function(name, ..) => {
+                    return Err(format!("it declares the function '{name}' inside of it"));
+                }
+                AstNode::ChannelSend(name, _) | AstNode::ChannelClose(name) => {
+                    return Err(format!("it uses the channel '{name}'"));
+                }
+                _ => return Err(format!("{:?} can't be run at compile time", node)),
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn eval(&mut self, node: &AstNode, scopes: &mut Scopes) -> Result<Token, String> {
+        match node {
+            AstNode::Literal(value) => Ok(value.to_owned()),
+
+            AstNode::VarReference(name, _) | AstNode::ConstReference(name, _) => {
+                if let Some(value) = scopes.iter().rev().find_map(|scope| scope.get(name)) {
+                    return Ok(value.to_owned());
+                }
+                self.find_constant(name)
+            }
+
+            AstNode::FunctionCall(name, args, _) => {
+                let args = self.eval_args(args, scopes)?;
+                self.call(name, args)
+            }
+
+            // Strings are stored in order, everything else is in reverse polish notation
+            AstNode::RuntimeExpression(nodes, DataType::String | DataType::CoerseToString) => {
+                let mut string = String::new();
+                for node in nodes {
+                    if let AstNode::BinaryOperator(..) = node {
+                        continue;
+                    }
+                    string.push_str(&value_to_string(&self.eval(node, scopes)?));
+                }
+                Ok(Token::StringLiteral(string))
+            }
+
+            AstNode::RuntimeExpression(nodes, _) => {
+                let mut stack = Vec::new();
+                for node in nodes {
+                    match node {
+                        AstNode::BinaryOperator(op, _) | AstNode::LogicalOperator(op, _) => {
+                            let (right, left) = match (stack.pop(), stack.pop()) {
+                                (Some(right), Some(left)) => (right, left),
+                                _ => return Err("an expression is missing a value".to_string()),
+                            };
+                            stack.push(binary_operation(op, &left, &right)?);
+                        }
+                        AstNode::UnaryOperator(Token::Negative, _) => {
+                            let value = match stack.pop() {
+                                Some(Token::IntLiteral(value)) => Token::IntLiteral(
+                                    value.checked_neg().ok_or("an integer overflowed")?,
+                                ),
+                                Some(Token::FloatLiteral(value)) => Token::FloatLiteral(-value),
+                                _ => return Err("only numbers can be negated".to_string()),
+                            };
+                            stack.push(value);
+                        }
+                        _ => stack.push(self.eval(node, scopes)?),
+                    }
+                }
+
+                match (stack.pop(), stack.is_empty()) {
+                    (Some(value), true) => Ok(value),
+                    _ => Err("an expression didn't evaluate to a single value".to_string()),
+                }
+            }
+
+            AstNode::Tuple(..) => Err("tuples can't be created at compile time yet".to_string()),
+            AstNode::CollectionAccess(..) | AstNode::Collection(..) => {
+                Err("collections can't be used at compile time yet".to_string())
+            }
+            AstNode::JS(_) | AstNode::JSStringReference(_) => Err("it uses JavaScript".to_string()),
+            _ => Err(format!("{:?} can't be evaluated at compile time", node)),
+        }
+    }
+
+    fn eval_args(&mut self, args: &AstNode, scopes: &mut Scopes) -> Result<Vec<Token>, String> {
+        match args {
+            AstNode::Empty => Ok(Vec::new()),
+            AstNode::Tuple(args, _) => args.iter().map(|arg| self.eval(arg, scopes)).collect(),
+            _ => Ok(vec![self.eval(args, scopes)?]),
+        }
+    }
+
+    // Constants declared in the module can be used, but variables might change at runtime
+    fn find_constant(&self, name: &str) -> Result<Token, String> {
+        for node in self.ast.iter().rev() {
+            if let AstNode::VarDeclaration(var_name, value, _, _, is_const) = node {
+                if var_name != name {
+                    continue;
+                }
+                return match (&**value, is_const) {
+                    (AstNode::Literal(value), true) => Ok(value.to_owned()),
+                    _ => Err(format!("it uses '{name}', which can change at runtime")),
+                };
+            }
+        }
+
+        Err(format!(
+            "it uses '{name}', which isn't known at compile time"
+        ))
+    }
+}
+
+fn binary_operation(op: &Token, left: &Token, right: &Token) -> Result<Token, String> {
+    let overflow = || "an integer overflowed".to_string();
+
+    let result = match (left, right) {
+        (Token::IntLiteral(left), Token::IntLiteral(right)) => match op {
+            Token::Add => Token::IntLiteral(left.checked_add(*right).ok_or_else(overflow)?),
+            Token::Subtract => Token::IntLiteral(left.checked_sub(*right).ok_or_else(overflow)?),
+            Token::Multiply => Token::IntLiteral(left.checked_mul(*right).ok_or_else(overflow)?),
+            Token::Divide | Token::Modulus | Token::Remainder if *right == 0 => {
+                return Err("it divides by zero".to_string());
+            }
+            Token::Divide => Token::IntLiteral(left / right),
+            Token::Modulus => Token::IntLiteral(left % right),
+            Token::Remainder => Token::IntLiteral(left.rem_euclid(*right)),
+            Token::Exponent => match u32::try_from(*right) {
+                Ok(exponent) => Token::IntLiteral(left.checked_pow(exponent).ok_or_else(overflow)?),
+                Err(_) => return Err("integers can't have negative exponents".to_string()),
+            },
+            _ => compare(op, left, right)?,
+        },
+
+        // Ints mixed with floats become floats
+        (
+            Token::IntLiteral(_) | Token::FloatLiteral(_),
+            Token::IntLiteral(_) | Token::FloatLiteral(_),
+        ) => {
+            let (left, right) = (to_float(left), to_float(right));
+            match op {
+                Token::Add => Token::FloatLiteral(left + right),
+                Token::Subtract => Token::FloatLiteral(left - right),
+                Token::Multiply => Token::FloatLiteral(left * right),
+                Token::Divide => Token::FloatLiteral(left / right),
+                Token::Modulus => Token::FloatLiteral(left % right),
+                Token::Remainder => Token::FloatLiteral(left.rem_euclid(right)),
+                Token::Exponent => Token::FloatLiteral(left.powf(right)),
+                _ => compare(op, &left, &right)?,
+            }
+        }
+
+        (Token::StringLiteral(left), Token::StringLiteral(right)) => match op {
+            Token::Add => Token::StringLiteral(format!("{left}{right}")),
+            _ => compare(op, left, right)?,
+        },
+
+        (Token::BoolLiteral(left), Token::BoolLiteral(right)) => match op {
+            Token::And => Token::BoolLiteral(*left && *right),
+            Token::Or => Token::BoolLiteral(*left || *right),
+            _ => compare(op, left, right)?,
+        },
+
+        _ => {
+            return Err(format!(
+                "{:?} can't be used with {:?} and {:?}",
+                op, left, right
+            ));
+        }
+    };
+
+    Ok(result)
+}
+
+fn compare<T: PartialOrd>(op: &Token, left: T, right: T) -> Result<Token, String> {
+    Ok(Token::BoolLiteral(match op {
+        Token::Equal => left == right,
+        Token::LessThan => left < right,
+        Token::LessThanOrEqual => left <= right,
+        Token::GreaterThan => left > right,
+        Token::GreaterThanOrEqual => left >= right,
+        _ => return Err(format!("{:?} can't be used at compile time", op)),
+    }))
+}
+
+fn to_float(value: &Token) -> f64 {
+    match value {
+        Token::IntLiteral(value) => *value as f64,
+        Token::FloatLiteral(value) => *value,
+        _ => 0.0,
+    }
+}
+
+fn value_to_string(value: &Token) -> String {
+    match value {
+        Token::IntLiteral(value) => value.to_string(),
+        Token::FloatLiteral(value) => value.to_string(),
+        Token::StringLiteral(value) => value.to_owned(),
+        Token::BoolLiteral(value) => value.to_string(),
+        _ => String::new(),
+    }
+}
@@ -1,4 +1,8 @@
-use crate::{bs_types::DataType, parsers::ast_nodes::AstNode, Token};
+use crate::{
+    bs_types::DataType,
+    parsers::ast_nodes::{AstNode, Node},
+    Token,
+};
 
 // This will evaluate everything possible at compile time
 // returns either a literal or an evaluated runtime expression
@@ -68,6 +72,25 @@ pub fn math_constant_fold(output_stack: Vec<AstNode>, current_type: DataType) ->
                     }
                 })));
             }
+            AstNode::UnaryOperator(Token::Negative, _) => match stack.pop() {
+                Some(AstNode::Literal(Token::FloatLiteral(value))) => {
+                    stack.push(AstNode::Literal(Token::FloatLiteral(-value)));
+                }
+                Some(AstNode::Literal(Token::IntLiteral(value))) => {
+                    stack.push(AstNode::Literal(Token::IntLiteral(-value)));
+                }
+                Some(value) => {
+                    stack.push(value);
+                    stack.push(node.to_owned());
+                }
+                None => {
+                    return AstNode::Error(
+                        "Nothing to negate when parsing an expression".to_string(),
+                        0,
+                    );
+                }
+            },
+
             // Some runtime thing
             _ => {
                 stack.push(node.to_owned());
@@ -76,7 +99,16 @@ pub fn math_constant_fold(output_stack: Vec<AstNode>, current_type: DataType) ->
     }
 
     if stack.len() == 1 {
-        return stack.pop().unwrap();
+        // An Int value used on its own as a Float still has to be converted at runtime
+        match stack.pop().unwrap() {
+            AstNode::Literal(Token::IntLiteral(value)) if current_type == DataType::Float => {
+                return AstNode::Literal(Token::FloatLiteral(value as f64));
+            }
+            value if current_type == DataType::Float && value.get_type() == DataType::Int => {
+                return AstNode::RuntimeExpression(vec![value], current_type);
+            }
+            value => return value,
+        }
     }
 
     AstNode::RuntimeExpression(stack, current_type)
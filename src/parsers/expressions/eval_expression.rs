@@ -1,5 +1,9 @@
 use super::constant_folding::{logical_constant_fold, math_constant_fold};
-use crate::{bs_types::DataType, parsers::ast_nodes::AstNode, Token};
+use crate::{
+    bs_types::{number_type, DataType},
+    parsers::ast_nodes::{AstNode, Node},
+    Token,
+};
 use colour::red_ln;
 
 // This function takes in an Expression node or Collection of expressions that has a Vec of Nodes to evaluate
@@ -101,7 +105,8 @@ pub fn evaluate_expression(
                     AstNode::VarReference(_, data_type)
                     | AstNode::FunctionCall(_, _, data_type)
                     | AstNode::TupleAccess(_, _, data_type)
-                    | AstNode::CollectionAccess(_, _, data_type) => {
+                    | AstNode::CollectionAccess(_, _, data_type)
+                    | AstNode::ChannelReceive(_, data_type) => {
                         if current_type == DataType::Inferred {
                             current_type = data_type.to_owned();
                         }
@@ -113,6 +118,24 @@ pub fn evaluate_expression(
                             DataType::String | DataType::CoerseToString => {
                                 simplified_expression.push(node.to_owned());
                             }
+
+                            // Functions can be passed around as values
+                            // And collections can be returned from function calls
+                            // Union values can only be used on their own until their type is checked
+                            DataType::Function(..)
+                            | DataType::Collection(..)
+                            | DataType::Union(..) => {
+                                simplified_expression.push(node.to_owned());
+                            }
+
+                            // Type parameters that are numbers can be used in maths expressions
+                            DataType::Generic(_, ref constraint) => {
+                                if **constraint == number_type() {
+                                    output_stack.push(node.to_owned());
+                                } else {
+                                    simplified_expression.push(node.to_owned());
+                                }
+                            }
                             _ => {
                                 return AstNode::Error(
                                     format!(
@@ -127,6 +150,15 @@ pub fn evaluate_expression(
                         runtime_nodes += 1;
                     }
 
+                    // Copies are only made of collections, so they are used on their own
+                    AstNode::Copy(value) => {
+                        if current_type == DataType::Inferred {
+                            current_type = value.get_type();
+                        }
+                        simplified_expression.push(node.to_owned());
+                        runtime_nodes += 1;
+                    }
+
                     AstNode::BinaryOperator(op, precedence) => {
                         // If the current type is a string or scene, add operator is assumed.
                         if current_type == DataType::String || current_type == DataType::Scene {
@@ -144,6 +176,22 @@ pub fn evaluate_expression(
                             simplified_expression.push(node.to_owned());
                         }
 
+                        if let DataType::Generic(ref name, ref constraint) = current_type {
+                            if **constraint != number_type() {
+                                return AstNode::Error(
+                                    format!("Operators can only be used with '{name}' if it is a Number"),
+                                    line_number,
+                                );
+                            }
+                        }
+
+                        if let DataType::Union(_) = current_type {
+                            return AstNode::Error(
+                                "Operators can't be used with a union until its type is checked with 'is'".to_string(),
+                                line_number,
+                            );
+                        }
+
                         if current_type == DataType::Bool {
                             if *op != Token::Or || *op != Token::And {
                                 return AstNode::Error(
@@ -155,8 +203,9 @@ pub fn evaluate_expression(
                             operators_stack.push(node.to_owned());
                         }
 
-                        if operators_stack.last().is_some_and(|x| match x {
+                        while operators_stack.last().is_some_and(|x| match x {
                             AstNode::BinaryOperator(_, p) => p >= &precedence,
+                            AstNode::UnaryOperator(..) => true,
                             _ => false,
                         }) {
                             output_stack.push(operators_stack.pop().unwrap());
@@ -165,6 +214,38 @@ pub fn evaluate_expression(
                         operators_stack.push(node.to_owned());
                     }
 
+                    // Prefix operators only apply to the value after them,
+                    // so nothing is popped off of the operator stack yet
+                    AstNode::UnaryOperator(..) => {
+                        if !matches!(
+                            current_type,
+                            DataType::Inferred | DataType::Float | DataType::Int
+                        ) && current_type != number_type()
+                        {
+                            return AstNode::Error(
+                                format!("Can't negate a value of type {:?}", current_type),
+                                line_number,
+                            );
+                        }
+                        operators_stack.push(node.to_owned());
+                    }
+
+                    // Expressions inserted into a string are worked out at runtime
+                    AstNode::RuntimeExpression(..) => {
+                        if current_type == DataType::Inferred {
+                            current_type = DataType::String;
+                        }
+                        simplified_expression.push(node.to_owned());
+                        runtime_nodes += 1;
+                    }
+
+                    AstNode::AnonymousFunction(..) => {
+                        if current_type == DataType::Inferred {
+                            current_type = node.get_type();
+                        }
+                        simplified_expression.push(node.to_owned());
+                    }
+
                     AstNode::Scene(_, _, _, _) => {
                         if current_type == DataType::Inferred {
                             current_type = DataType::Scene;
@@ -222,7 +303,7 @@ pub fn evaluate_expression(
 
     // LOGICAL EXPRESSIONS
     if current_type == DataType::Bool {
-        for operator in operators_stack {
+        for operator in operators_stack.into_iter().rev() {
             output_stack.push(operator);
         }
 
@@ -241,6 +322,11 @@ pub fn evaluate_expression(
         return concat_strings(&mut simplified_expression);
     }
 
+    // Strings that use variables are joined together at runtime
+    if current_type == DataType::String {
+        return AstNode::RuntimeExpression(simplified_expression, current_type);
+    }
+
     // Scene Head Coerse to String
     if current_type == DataType::CoerseToString {
         return AstNode::RuntimeExpression(simplified_expression, current_type);
@@ -248,7 +334,7 @@ pub fn evaluate_expression(
 
     // MATHS EXPRESSIONS
     // Push everything into the stack, is now in RPN notation
-    for operator in operators_stack {
+    for operator in operators_stack.into_iter().rev() {
         output_stack.push(operator);
     }
 
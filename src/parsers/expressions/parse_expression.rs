@@ -1,16 +1,28 @@
 use colour::red_ln;
 
 use crate::{
-    bs_types::DataType,
+    bs_types::{number_type, DataType},
     parsers::{
-        ast_nodes::{AstNode, Reference},
+        ast_nodes::{AstNode, Node, Reference},
+        channels::create_channel_receive,
         create_scene_node::new_scene,
+        functions::create_anonymous_function,
+        generics::{bind, is_generic, substitute, TypeBindings},
+        ownership::{check_borrows, create_copy, moved_value_error},
+        scope::{undefined_name_error, ScopeStack},
         tuples::new_tuple,
     },
+    stdlib::{
+        math::{get_math_function, MathFunction},
+        string::{get_string_function, string_function_from_call, StringFunction},
+    },
     Token,
 };
 
-use super::eval_expression::evaluate_expression;
+use super::{
+    comptime::{fold_function_call, run_function_call},
+    eval_expression::evaluate_expression,
+};
 
 // If the datatype is a collection
 // The the expression must only contain references to collections
@@ -20,13 +32,17 @@ pub fn create_expression(
     i: &mut usize,
     inside_tuple: bool,
     ast: &Vec<AstNode>,
-    starting_line_number: &u32,
+    token_line_numbers: &Vec<u32>,
     data_type: &mut DataType,
     inside_brackets: bool,
-    variable_declarations: &Vec<Reference>,
+    variable_declarations: &mut ScopeStack,
 ) -> AstNode {
+    let starting_line_number = &token_line_numbers[*i];
     let mut expression = Vec::new();
-    let number_union = DataType::Union(Box::new(vec![DataType::Int, DataType::Float]));
+    let number_union = number_type();
+
+    // An expression that was only Int because of the values at its start becomes a Float if a Float is used in it
+    let type_inferred = *data_type == DataType::Inferred;
 
     if inside_brackets {
         *i += 1;
@@ -40,16 +56,52 @@ pub fn create_expression(
                 i,
                 data_type,
                 ast,
-                starting_line_number,
+                token_line_numbers,
                 variable_declarations,
             );
         }
     }
 
+    // Comparisons, 'and' and 'or' give a Bool from values that can be other types,
+    // so each side of them is parsed as its own expression
+    if let Some(end) = find_logical_end(tokens, *i, inside_brackets) {
+        let value = create_logical_expression(
+            &tokens[*i..end],
+            &token_line_numbers[*i..=end],
+            ast,
+            variable_declarations,
+        );
+        if let AstNode::Error(..) = value {
+            return value;
+        }
+        if !check_if_valid_type(&DataType::Bool, data_type) {
+            return AstNode::Error(
+                format!("Comparison used in an expression of type {:?}", data_type),
+                starting_line_number.to_owned(),
+            );
+        }
+
+        *i = end;
+        if inside_brackets {
+            *i += 1;
+        }
+        return value;
+    }
+
+    // Everything before a pipe is the value being piped, which has its own type
+    // The type of the whole expression comes from the last function in the pipe instead
+    let mut piped_type = DataType::Inferred;
+    let (data_type, pipe_result_type) = if has_pipe(tokens, *i) {
+        (&mut piped_type, Some(data_type))
+    } else {
+        (data_type, None)
+    };
+
     // Loop through the expression and create the AST nodes (increment i each time)
     // Figure out the type it should be from the data
     // DOES NOT MOVE TOKENS PAST THE CLOSING TOKEN
     let mut next_number_negative = false;
+    let mut next_call_comptime = false;
     while let Some(token) = tokens.get(*i) {
         match token {
             // Conditions that close the expression
@@ -79,7 +131,7 @@ pub fn create_expression(
                     i,
                     false,
                     ast,
-                    starting_line_number,
+                    token_line_numbers,
                     data_type,
                     true,
                     variable_declarations,
@@ -96,6 +148,11 @@ pub fn create_expression(
                 break;
             }
 
+            // The value before a send ends at the '>>'
+            Token::Pipe | Token::Send => {
+                break;
+            }
+
             Token::Newline => {
                 // Fine if inside of brackets (not closed yet)
                 // Otherwise break out of the expression
@@ -124,7 +181,7 @@ pub fn create_expression(
                         i,
                         data_type,
                         ast,
-                        starting_line_number,
+                        token_line_numbers,
                         variable_declarations,
                     );
                 }
@@ -137,11 +194,22 @@ pub fn create_expression(
 
             // Check if name is a reference to another variable or function call
             Token::Variable(name) => {
-                let var = variable_declarations.iter().find(|var| var.name == *name);
+                let var = variable_declarations.get(name).cloned();
                 match var {
                     Some(var) => {
+                        if let Some(moved_on) = variable_declarations.moved_on(name) {
+                            return moved_value_error(
+                                name,
+                                moved_on,
+                                starting_line_number.to_owned(),
+                            );
+                        }
+
                         // If this expression is inferring it's type from the expression
-                        if *data_type == DataType::Inferred {
+                        // Function calls will infer the type from what the function returns instead
+                        if *data_type == DataType::Inferred
+                            && !matches!(var.data_type, DataType::Function(..))
+                        {
                             *data_type = var.data_type.to_owned();
                         }
 
@@ -242,16 +310,29 @@ pub fn create_expression(
                                 // FUNCTION CALLS
                                 // move past the variable name
                                 *i += 1;
-                                match get_args(
+                                let mut type_bindings = TypeBindings::new();
+                                match get_function_args(
                                     tokens,
                                     i,
                                     ast,
-                                    starting_line_number,
+                                    token_line_numbers,
                                     variable_declarations,
-                                    argument_types,
+                                    FunctionCallContext {
+                                        name: &var.name,
+                                        argument_refs: argument_types,
+                                        type_bindings: &mut type_bindings,
+                                    },
                                 ) {
+                                    Some(AstNode::Error(err, line)) => {
+                                        return AstNode::Error(err, line);
+                                    }
                                     Some(args) => {
-                                        if !check_if_valid_type(&return_type, data_type) {
+                                        let return_type = substitute(return_type, &type_bindings);
+                                        if !check_number_type(
+                                            &return_type,
+                                            data_type,
+                                            type_inferred,
+                                        ) {
                                             return AstNode::Error(
                                                 format!(
                                                     "Function '{}' returns type {:?}, but used in an expression of type {:?}",
@@ -261,18 +342,33 @@ pub fn create_expression(
                                             );
                                         }
 
-                                        expression.push(AstNode::FunctionCall(
+                                        let call = AstNode::FunctionCall(
                                             var.name.to_owned(),
                                             Box::new(args),
-                                            *return_type.clone(),
-                                        ));
+                                            return_type,
+                                        );
 
-                                        *i += 1;
+                                        let value = fold_call(
+                                            call,
+                                            next_call_comptime,
+                                            ast,
+                                            starting_line_number,
+                                            variable_declarations,
+                                        );
+                                        if let AstNode::Error(..) = value {
+                                            return value;
+                                        }
+                                        expression.push(value);
+                                        next_call_comptime = false;
+
+                                        // get_args already moved past the closing parenthesis
                                         continue;
                                     }
 
                                     // Just a reference to a function
-                                    None => {}
+                                    None => {
+                                        *i -= 1;
+                                    }
                                 };
                             }
                             _ => {}
@@ -280,7 +376,7 @@ pub fn create_expression(
 
                         // If the variables type is known and not the same as the type of the expression
                         // Return a type error
-                        if !check_if_valid_type(&var.data_type, data_type) {
+                        if !check_number_type(&var.data_type, data_type, type_inferred) {
                             return AstNode::Error(
                                 format!(
                                     "Variable {} is of type {:?}, but used in an expression of type {:?}",
@@ -303,8 +399,10 @@ pub fn create_expression(
                         };
                     }
                     None => {
-                        return AstNode::Error(
-                            format!("Variable {} not found in scope", name),
+                        return undefined_name_error(
+                            name,
+                            tokens,
+                            *i,
                             starting_line_number.to_owned(),
                         );
                     }
@@ -313,7 +411,7 @@ pub fn create_expression(
 
             // Check if is a literal
             Token::FloatLiteral(mut float) => {
-                if !check_if_valid_type(&DataType::Float, data_type) {
+                if !check_number_type(&DataType::Float, data_type, type_inferred) {
                     return AstNode::Error(
                         "Float literal used in non-float expression".to_string(),
                         starting_line_number.to_owned(),
@@ -326,7 +424,7 @@ pub fn create_expression(
                 expression.push(AstNode::Literal(Token::FloatLiteral(float)));
             }
             Token::IntLiteral(int) => {
-                if !check_if_valid_type(&DataType::Int, data_type) {
+                if !check_number_type(&DataType::Int, data_type, type_inferred) {
                     return AstNode::Error(
                         "Int literal used in non-integer expression".to_string(),
                         starting_line_number.to_owned(),
@@ -348,6 +446,146 @@ pub fn create_expression(
                 }
                 expression.push(AstNode::Literal(Token::StringLiteral(string.clone())));
             }
+            Token::StringTemplate(parts, expressions) => {
+                if !check_if_valid_type(&DataType::String, data_type) {
+                    return AstNode::Error(
+                        "String literal used in non-string expression".to_string(),
+                        starting_line_number.to_owned(),
+                    );
+                }
+                let template = create_string_template(
+                    parts,
+                    expressions,
+                    ast,
+                    starting_line_number,
+                    variable_declarations,
+                );
+                if let AstNode::Error(..) = template {
+                    return template;
+                }
+                expression.push(template);
+            }
+            Token::BoolLiteral(value) => {
+                if !check_if_valid_type(&DataType::Bool, data_type) {
+                    return AstNode::Error(
+                        "Bool literal used in non-boolean expression".to_string(),
+                        starting_line_number.to_owned(),
+                    );
+                }
+                expression.push(AstNode::Literal(Token::BoolLiteral(*value)));
+            }
+
+            // Function literals
+            Token::FunctionKeyword => {
+                let function = create_anonymous_function(
+                    tokens,
+                    i,
+                    ast,
+                    token_line_numbers,
+                    variable_declarations,
+                );
+                if let AstNode::Error(..) = function {
+                    return function;
+                }
+
+                if !check_if_valid_type(&function.get_type(), data_type) {
+                    return AstNode::Error(
+                        "Function used in an expression of a different type".to_string(),
+                        starting_line_number.to_owned(),
+                    );
+                }
+                expression.push(function);
+
+                // Already moved past the end of the function
+                continue;
+            }
+
+            // Functions from the standard library modules
+            Token::Math | Token::TypeKeyword(DataType::String) => {
+                let function = match get_module_function_after(tokens, i, starting_line_number) {
+                    Ok(function) => function,
+                    Err(err) => return err,
+                };
+                let (argument_refs, return_type) = match &function.data_type {
+                    DataType::Function(argument_refs, return_type) => (argument_refs, return_type),
+                    _ => unreachable!(),
+                };
+
+                *i += 1;
+                let args = match get_function_args(
+                    tokens,
+                    i,
+                    ast,
+                    token_line_numbers,
+                    variable_declarations,
+                    FunctionCallContext {
+                        name: &function.name,
+                        argument_refs,
+                        type_bindings: &mut TypeBindings::new(),
+                    },
+                ) {
+                    Some(AstNode::Error(err, line)) => return AstNode::Error(err, line),
+                    Some(args) => args,
+                    None => {
+                        return AstNode::Error(
+                            format!("Expected '(' after '{}'", function.name),
+                            starting_line_number.to_owned(),
+                        );
+                    }
+                };
+
+                if !check_number_type(return_type, data_type, type_inferred) {
+                    return AstNode::Error(
+                        format!(
+                            "Function '{}' returns type {:?}, but used in an expression of type {:?}",
+                            function.name, return_type, data_type
+                        ),
+                        starting_line_number.to_owned(),
+                    );
+                }
+
+                let call = AstNode::FunctionCall(
+                    function.name.to_owned(),
+                    Box::new(args),
+                    *return_type.clone(),
+                );
+                let value = fold_call(
+                    call,
+                    next_call_comptime,
+                    ast,
+                    starting_line_number,
+                    variable_declarations,
+                );
+                if let AstNode::Error(..) = value {
+                    return value;
+                }
+                expression.push(value);
+                next_call_comptime = false;
+
+                // Already moved past the closing parenthesis
+                continue;
+            }
+
+            // Waits for the next value from a channel
+            Token::Receive => {
+                let value =
+                    create_channel_receive(tokens, i, starting_line_number, variable_declarations);
+                if let AstNode::Error(..) = value {
+                    return value;
+                }
+
+                if !check_if_valid_type(&value.get_type(), data_type) {
+                    return AstNode::Error(
+                        format!(
+                            "Channel receives type {:?}, but used in an expression of type {:?}",
+                            value.get_type(),
+                            data_type
+                        ),
+                        starting_line_number.to_owned(),
+                    );
+                }
+                expression.push(value);
+            }
 
             // Scenes - Create a new scene node
             // Maybe scenes can be added together like strings
@@ -358,13 +596,62 @@ pub fn create_expression(
                         starting_line_number.to_owned(),
                     );
                 }
-                return new_scene(tokens, i, &ast, starting_line_number, variable_declarations);
+                return new_scene(tokens, i, ast, token_line_numbers, variable_declarations);
+            }
+
+            // Deep copy, so the original can still be used
+            Token::Copy => {
+                let value = create_copy(
+                    tokens,
+                    i,
+                    starting_line_number.to_owned(),
+                    variable_declarations,
+                );
+                if let AstNode::Error(..) = value {
+                    return value;
+                }
+                if !check_if_valid_type(&value.get_type(), data_type) {
+                    return AstNode::Error(
+                        format!(
+                            "Copy is of type {:?}, but used in an expression of type {:?}",
+                            value.get_type(),
+                            data_type
+                        ),
+                        starting_line_number.to_owned(),
+                    );
+                }
+                expression.push(value);
             }
 
             // OPERATORS
             // Will push as a string so shunting yard can handle it later just as a string
             Token::Negative => {
-                next_number_negative = true;
+                // A negative number straight after a value is a subtraction
+                if expects_value(&expression) {
+                    next_number_negative = true;
+                } else {
+                    expression.push(AstNode::BinaryOperator(Token::Subtract, 1));
+                }
+            }
+
+            // Forces the next function call to be run at compile time
+            Token::Comptime => {
+                let is_call = match (tokens.get(*i + 1), tokens.get(*i + 2)) {
+                    (Some(Token::Math | Token::TypeKeyword(DataType::String)), _) => true,
+                    (Some(Token::Variable(name)), Some(Token::OpenParenthesis)) => {
+                        variable_declarations
+                            .get(name)
+                            .is_some_and(|var| matches!(var.data_type, DataType::Function(..)))
+                    }
+                    _ => false,
+                };
+                if !is_call {
+                    return AstNode::Error(
+                        "'comptime' must be followed by a function call".to_string(),
+                        starting_line_number.to_owned(),
+                    );
+                }
+                next_call_comptime = true;
             }
 
             // BINARY OPERATORS
@@ -372,16 +659,30 @@ pub fn create_expression(
                 expression.push(AstNode::BinaryOperator(token.to_owned(), 1));
             }
             Token::Subtract => {
-                if !check_if_valid_type(&number_union, data_type) {
-                    return AstNode::Error(
-                        "Subtraction used in non-numerical expression".to_string(),
-                        starting_line_number.to_owned(),
-                    );
+                // Nothing to subtract from, so this negates the value after it
+                // The type comes from that value, so an inferred type isn't set here
+                if expects_value(&expression) {
+                    if *data_type != DataType::Inferred
+                        && !check_if_numerical(&number_union, data_type)
+                    {
+                        return AstNode::Error(
+                            "Negation used in non-numerical expression".to_string(),
+                            starting_line_number.to_owned(),
+                        );
+                    }
+                    expression.push(AstNode::UnaryOperator(Token::Negative, false));
+                } else {
+                    if !check_if_numerical(&number_union, data_type) {
+                        return AstNode::Error(
+                            "Subtraction used in non-numerical expression".to_string(),
+                            starting_line_number.to_owned(),
+                        );
+                    }
+                    expression.push(AstNode::BinaryOperator(token.to_owned(), 1));
                 }
-                expression.push(AstNode::BinaryOperator(token.to_owned(), 1));
             }
             Token::Multiply => {
-                if !check_if_valid_type(&number_union,  data_type) {
+                if !check_if_numerical(&number_union, data_type) {
                     return AstNode::Error(
                         "Multiplication used in non-numerical expression".to_string(),
                         starting_line_number.to_owned(),
@@ -390,7 +691,7 @@ pub fn create_expression(
                 expression.push(AstNode::BinaryOperator(token.to_owned(), 2));
             }
             Token::Divide => {
-                if !check_if_valid_type(&number_union, data_type) {
+                if !check_if_numerical(&number_union, data_type) {
                     return AstNode::Error(
                         "Division used in non-numerical expression".to_string(),
                         starting_line_number.to_owned(),
@@ -399,7 +700,7 @@ pub fn create_expression(
                 expression.push(AstNode::BinaryOperator(token.to_owned(), 2));
             }
             Token::Modulus => {
-                if !check_if_valid_type(&number_union,  data_type) {
+                if !check_if_numerical(&number_union, data_type) {
                     return AstNode::Error(
                         "Modulus used in non-numerical expression".to_string(),
                         starting_line_number.to_owned(),
@@ -431,6 +732,11 @@ pub fn create_expression(
                 expression.push(AstNode::LogicalOperator(Token::Or, 3));
             }
 
+            // Invalid literals are found by the tokenizer
+            Token::Error(message, line_number) => {
+                return AstNode::Error(message.to_owned(), *line_number);
+            }
+
             _ => {
                 return AstNode::Error(
                     format!(
@@ -445,11 +751,660 @@ pub fn create_expression(
         *i += 1;
     }
 
-    return evaluate_expression(
+    let value = evaluate_expression(
         AstNode::Expression(expression, starting_line_number.to_owned()),
         data_type,
         ast,
     );
+
+    let value = match pipe_result_type {
+        Some(result_type) => create_pipe(
+            value,
+            tokens,
+            i,
+            ast,
+            token_line_numbers,
+            result_type,
+            variable_declarations,
+        ),
+        None => return value,
+    };
+    if let AstNode::Error(..) = value {
+        return value;
+    }
+
+    // Nothing else can come after the last function in a pipe
+    match tokens.get(*i) {
+        Some(Token::CloseParenthesis) if inside_brackets => {
+            *i += 1;
+        }
+        Some(Token::CloseParenthesis | Token::Comma | Token::Send) if !inside_brackets => {}
+        Some(
+            Token::Newline
+            | Token::EOF
+            | Token::SceneClose(_)
+            | Token::Colon
+            | Token::Arrow
+            | Token::End,
+        )
+        | None => {}
+        Some(token) => {
+            return AstNode::Error(
+                format!("Unexpected {:?} after the last function in a pipe", token),
+                starting_line_number.to_owned(),
+            );
+        }
+    }
+
+    value
+}
+
+// Expressions inside curly braces are inserted into the string
+// Constant values are folded into the string, everything else is joined at runtime
+fn create_string_template(
+    parts: &[String],
+    expressions: &[Vec<Token>],
+    ast: &Vec<AstNode>,
+    starting_line_number: &u32,
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    // Starts with a string, so everything after it is joined as a string
+    let mut template = vec![AstNode::Literal(Token::StringLiteral(String::new()))];
+    for (index, part) in parts.iter().enumerate() {
+        if !part.is_empty() {
+            template.push(AstNode::BinaryOperator(Token::Add, 1));
+            template.push(AstNode::Literal(Token::StringLiteral(part.to_owned())));
+        }
+
+        let Some(expression_tokens) = expressions.get(index) else {
+            continue;
+        };
+        if matches!(expression_tokens.as_slice(), [] | [Token::EOF]) {
+            return AstNode::Error(
+                "Empty curly braces inside a string, use '\\{' for a curly brace".to_string(),
+                starting_line_number.to_owned(),
+            );
+        }
+        // Everything inside of the braces is on the same line as the string
+        let line_numbers = vec![*starting_line_number; expression_tokens.len() + 1];
+        let value = create_expression(
+            expression_tokens,
+            &mut 0,
+            false,
+            ast,
+            &line_numbers,
+            &mut DataType::Inferred,
+            false,
+            variable_declarations,
+        );
+
+        let value = match value {
+            AstNode::Literal(Token::StringLiteral(_)) => value,
+            AstNode::Literal(Token::FloatLiteral(float)) => {
+                AstNode::Literal(Token::StringLiteral(float.to_string()))
+            }
+            AstNode::Literal(Token::IntLiteral(int)) => {
+                AstNode::Literal(Token::StringLiteral(int.to_string()))
+            }
+            AstNode::Literal(Token::BoolLiteral(value)) => {
+                AstNode::Literal(Token::StringLiteral(value.to_string()))
+            }
+            AstNode::VarReference(..)
+            | AstNode::ConstReference(..)
+            | AstNode::FunctionCall(..)
+            | AstNode::TupleAccess(..)
+            | AstNode::CollectionAccess(..)
+            | AstNode::RuntimeExpression(..) => value,
+            AstNode::Error(err, line) => {
+                return AstNode::Error(format!("{err} (inside a string)"), line);
+            }
+            _ => {
+                return AstNode::Error(
+                    format!("Can't insert {:?} into a string", value.get_type()),
+                    starting_line_number.to_owned(),
+                );
+            }
+        };
+
+        template.push(AstNode::BinaryOperator(Token::Add, 1));
+        template.push(value);
+    }
+
+    evaluate_expression(
+        AstNode::Expression(template, starting_line_number.to_owned()),
+        &DataType::String,
+        ast,
+    )
+}
+
+// Calls to pure functions with constant arguments are run at compile time
+// Using comptime makes it an error if the call can't be
+fn fold_call(
+    call: AstNode,
+    comptime: bool,
+    ast: &[AstNode],
+    line_number: &u32,
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    let name = match &call {
+        AstNode::FunctionCall(name, args, _) => {
+            if let Some(function) = string_function_from_call(name) {
+                if let Err(err) = function.check_args(args) {
+                    return AstNode::Error(err, line_number.to_owned());
+                }
+            }
+            name.to_owned()
+        }
+        _ => String::new(),
+    };
+
+    let value = if comptime {
+        match run_function_call(&call, ast) {
+            Ok(value) => value,
+            Err(err) => {
+                return AstNode::Error(
+                    format!("Can't run '{name}' at compile time because {err}"),
+                    line_number.to_owned(),
+                )
+            }
+        }
+    } else {
+        fold_function_call(call, ast)
+    };
+
+    if !matches!(value, AstNode::FunctionCall(..)) {
+        variable_declarations.fold_call(&name);
+    }
+    value
+}
+
+// Current token should be the module name (math or String), this moves to the name of the function
+fn get_module_function_after(
+    tokens: &[Token],
+    i: &mut usize,
+    line_number: &u32,
+) -> Result<Reference, AstNode> {
+    let module = match tokens.get(*i) {
+        Some(Token::Math) => "math",
+        _ => "String",
+    };
+
+    match (tokens.get(*i + 1), tokens.get(*i + 2)) {
+        (Some(Token::Dot), Some(Token::Variable(name))) => {
+            *i += 2;
+            let function = match module {
+                "math" => get_math_function(name).map(MathFunction::reference),
+                _ => get_string_function(name).map(StringFunction::reference),
+            };
+            function.ok_or_else(|| {
+                AstNode::Error(
+                    format!("The {module} module doesn't have a function called '{name}'"),
+                    line_number.to_owned(),
+                )
+            })
+        }
+        _ => Err(AstNode::Error(
+            format!("Expected the name of a function after '{module}.'"),
+            line_number.to_owned(),
+        )),
+    }
+}
+
+// Checks for a pipe in the rest of the expression, ignoring anything inside of brackets
+pub fn has_pipe(tokens: &[Token], mut i: usize) -> bool {
+    let mut depth = 0;
+    while let Some(token) = tokens.get(i) {
+        match token {
+            Token::Pipe if depth == 0 => return true,
+            Token::OpenParenthesis => depth += 1,
+            Token::CloseParenthesis => {
+                if depth == 0 {
+                    return false;
+                }
+                depth -= 1;
+            }
+            Token::Comma | Token::Colon | Token::Newline if depth == 0 => return false,
+            Token::EOF
+            | Token::SceneClose(_)
+            | Token::SceneHead
+            | Token::ParentScene
+            | Token::FunctionKeyword
+            | Token::Arrow
+            | Token::End => return false,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    false
+}
+
+// Returns where the expression ends if there is a comparison, 'and' or 'or' in it,
+// ignoring anything inside of brackets
+fn find_logical_end(tokens: &[Token], mut i: usize, inside_brackets: bool) -> Option<usize> {
+    let mut depth = 0;
+    let mut found = false;
+    while let Some(token) = tokens.get(i) {
+        match token {
+            Token::Equal
+            | Token::LessThan
+            | Token::LessThanOrEqual
+            | Token::GreaterThan
+            | Token::GreaterThanOrEqual
+            | Token::And
+            | Token::Or
+                if depth == 0 =>
+            {
+                found = true;
+            }
+            Token::OpenParenthesis => depth += 1,
+            Token::CloseParenthesis => {
+                if depth == 0 {
+                    return found.then_some(i);
+                }
+                depth -= 1;
+            }
+            Token::Newline if inside_brackets || depth > 0 => {}
+            Token::Comma if depth > 0 => {}
+            Token::Comma if !inside_brackets => return found.then_some(i),
+            Token::Comma
+            | Token::Newline
+            | Token::Colon
+            | Token::Send
+            | Token::EOF
+            | Token::SceneClose(_)
+            | Token::Arrow
+            | Token::End => return (found && !inside_brackets).then_some(i),
+            Token::SceneHead | Token::ParentScene | Token::FunctionKeyword => return None,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    None
+}
+
+// Splits the expression at the operator that is worked out last,
+// 'or' then 'and' then the comparison
+// There is one more line number than tokens, for the end of the expression
+fn create_logical_expression(
+    tokens: &[Token],
+    token_line_numbers: &[u32],
+    ast: &Vec<AstNode>,
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    let starting_line_number = &token_line_numbers[0];
+    let split = operator_positions(tokens, &[Token::Or])
+        .last()
+        .or(operator_positions(tokens, &[Token::And]).last())
+        .copied();
+    if let Some(index) = split {
+        let mut sides = Vec::new();
+        for (side, side_line_numbers) in [
+            (&tokens[..index], &token_line_numbers[..=index]),
+            (&tokens[index + 1..], &token_line_numbers[index + 1..]),
+        ] {
+            let value =
+                create_logical_expression(side, side_line_numbers, ast, variable_declarations);
+            if let AstNode::Error(..) = value {
+                return value;
+            }
+            if value.get_type() != DataType::Bool {
+                return AstNode::Error(
+                    format!(
+                        "'and' and 'or' can only be used with Bools, not {:?}",
+                        value.get_type()
+                    ),
+                    starting_line_number.to_owned(),
+                );
+            }
+            sides.push(value);
+        }
+
+        let precedence = if tokens[index] == Token::And { 4 } else { 3 };
+        let right = sides.pop().unwrap();
+        let left = sides.pop().unwrap();
+        return match (&left, &right) {
+            (
+                AstNode::Literal(Token::BoolLiteral(left)),
+                AstNode::Literal(Token::BoolLiteral(right)),
+            ) => AstNode::Literal(Token::BoolLiteral(match tokens[index] {
+                Token::And => *left && *right,
+                _ => *left || *right,
+            })),
+            _ => logical_runtime_expression(left, right, &tokens[index], precedence),
+        };
+    }
+
+    let comparisons = operator_positions(
+        tokens,
+        &[
+            Token::Equal,
+            Token::LessThan,
+            Token::LessThanOrEqual,
+            Token::GreaterThan,
+            Token::GreaterThanOrEqual,
+        ],
+    );
+    let index = match comparisons.as_slice() {
+        [] => {
+            return logical_operand(
+                tokens,
+                token_line_numbers,
+                &mut DataType::Bool,
+                ast,
+                variable_declarations,
+            );
+        }
+        [index] => *index,
+        _ => {
+            return AstNode::Error(
+                "Comparisons can't be chained together, join them with 'and' instead".to_string(),
+                starting_line_number.to_owned(),
+            );
+        }
+    };
+
+    let left = logical_operand(
+        &tokens[..index],
+        &token_line_numbers[..=index],
+        &mut DataType::Inferred,
+        ast,
+        variable_declarations,
+    );
+    if let AstNode::Error(..) = left {
+        return left;
+    }
+    let right = logical_operand(
+        &tokens[index + 1..],
+        &token_line_numbers[index + 1..],
+        &mut DataType::Inferred,
+        ast,
+        variable_declarations,
+    );
+    if let AstNode::Error(..) = right {
+        return right;
+    }
+
+    // Numbers can be compared with each other,
+    // other values can only be checked for being equal to a value of the same type
+    let op = &tokens[index];
+    let is_number = |data_type: &DataType| matches!(data_type, DataType::Int | DataType::Float);
+    let (left_type, right_type) = (left.get_type(), right.get_type());
+    let comparable = match op {
+        Token::Equal => {
+            (is_number(&left_type) && is_number(&right_type))
+                || (left_type == right_type
+                    && matches!(left_type, DataType::String | DataType::Bool))
+        }
+        _ => is_number(&left_type) && is_number(&right_type),
+    };
+    if !comparable {
+        return AstNode::Error(
+            format!("Can't compare {:?} with {:?}", left_type, right_type),
+            starting_line_number.to_owned(),
+        );
+    }
+
+    match (&left, &right) {
+        (AstNode::Literal(left_value), AstNode::Literal(right_value)) => {
+            match compare_literals(left_value, right_value, op) {
+                Some(value) => AstNode::Literal(Token::BoolLiteral(value)),
+                None => logical_runtime_expression(left, right, op, 5),
+            }
+        }
+        _ => logical_runtime_expression(left, right, op, 5),
+    }
+}
+
+// Positions of the operators that aren't inside of brackets
+fn operator_positions(tokens: &[Token], operators: &[Token]) -> Vec<usize> {
+    let mut depth = 0;
+    let mut positions = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::OpenParenthesis => depth += 1,
+            Token::CloseParenthesis => depth -= 1,
+            _ if depth == 0 && operators.contains(token) => positions.push(index),
+            _ => {}
+        }
+    }
+    positions
+}
+
+// One side of a comparison, 'and' or 'or'
+fn logical_operand(
+    tokens: &[Token],
+    token_line_numbers: &[u32],
+    data_type: &mut DataType,
+    ast: &Vec<AstNode>,
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    let starting_line_number = &token_line_numbers[0];
+    if tokens.iter().all(|token| token == &Token::Newline) {
+        return AstNode::Error(
+            "Expected a value on both sides of the comparison".to_string(),
+            starting_line_number.to_owned(),
+        );
+    }
+
+    let mut operand = tokens.to_vec();
+    operand.push(Token::EOF);
+    let mut index = 0;
+    let value = create_expression(
+        &operand,
+        &mut index,
+        false,
+        ast,
+        &token_line_numbers.to_vec(),
+        data_type,
+        false,
+        variable_declarations,
+    );
+
+    match operand.get(index) {
+        Some(Token::EOF) => value,
+        _ if matches!(value, AstNode::Error(..)) => value,
+        token => AstNode::Error(
+            format!("Unexpected {:?} in comparison", token),
+            starting_line_number.to_owned(),
+        ),
+    }
+}
+
+fn compare_literals(left: &Token, right: &Token, op: &Token) -> Option<bool> {
+    let number = |token: &Token| match token {
+        Token::IntLiteral(value) => Some(*value as f64),
+        Token::FloatLiteral(value) => Some(*value),
+        _ => None,
+    };
+
+    if let (Some(left), Some(right)) = (number(left), number(right)) {
+        return Some(match op {
+            Token::Equal => left == right,
+            Token::LessThan => left < right,
+            Token::LessThanOrEqual => left <= right,
+            Token::GreaterThan => left > right,
+            _ => left >= right,
+        });
+    }
+
+    match (left, right) {
+        (Token::StringLiteral(left), Token::StringLiteral(right)) => Some(left == right),
+        (Token::BoolLiteral(left), Token::BoolLiteral(right)) => Some(left == right),
+        _ => None,
+    }
+}
+
+// Logical expressions are in reverse polish notation like maths expressions,
+// the number values being compared keep their own expressions so their type isn't lost
+fn logical_runtime_expression(
+    left: AstNode,
+    right: AstNode,
+    op: &Token,
+    precedence: u8,
+) -> AstNode {
+    let mut nodes = Vec::new();
+    for side in [left, right] {
+        match side {
+            AstNode::RuntimeExpression(side_nodes, DataType::Bool) => nodes.extend(side_nodes),
+            side => nodes.push(side),
+        }
+    }
+    nodes.push(AstNode::LogicalOperator(op.to_owned(), precedence));
+
+    AstNode::RuntimeExpression(nodes, DataType::Bool)
+}
+
+// Passes the value before each '=>' into the first argument of the function after it
+// a => f(b) is the same as f(a, b)
+// Current token should be the first pipe, and this moves past the last function call
+fn create_pipe(
+    value: AstNode,
+    tokens: &Vec<Token>,
+    i: &mut usize,
+    ast: &Vec<AstNode>,
+    token_line_numbers: &Vec<u32>,
+    data_type: &mut DataType,
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    if let AstNode::Error(..) = value {
+        return value;
+    }
+    let starting_line_number = &token_line_numbers[*i];
+
+    // Bool literals have their value as their type
+    let mut value_type = match value.get_type() {
+        DataType::True | DataType::False => DataType::Bool,
+        value_type => value_type,
+    };
+    let mut value = value;
+    while tokens.get(*i) == Some(&Token::Pipe) {
+        *i += 1;
+
+        let var = match tokens.get(*i) {
+            Some(Token::Math | Token::TypeKeyword(DataType::String)) => {
+                match get_module_function_after(tokens, i, starting_line_number) {
+                    Ok(function) => function,
+                    Err(err) => return err,
+                }
+            }
+            Some(Token::Variable(name)) => match variable_declarations.get(name) {
+                Some(var) => var.to_owned(),
+                None => {
+                    return undefined_name_error(name, tokens, *i, starting_line_number.to_owned());
+                }
+            },
+            _ => {
+                return AstNode::Error(
+                    "Expected a function call after '=>'".to_string(),
+                    starting_line_number.to_owned(),
+                );
+            }
+        };
+
+        let (argument_refs, return_type) = match &var.data_type {
+            DataType::Function(argument_refs, return_type) => (argument_refs, return_type),
+            _ => {
+                return AstNode::Error(
+                    format!("Can't pipe into '{}' because it isn't a function", var.name),
+                    starting_line_number.to_owned(),
+                );
+            }
+        };
+
+        // The piped value is always the first argument
+        let mut type_bindings = TypeBindings::new();
+        match argument_refs.first() {
+            Some(first_arg) if is_generic(&first_arg.data_type) => {
+                if let Err(err) = bind(&first_arg.data_type, &value_type, &mut type_bindings) {
+                    return AstNode::Error(
+                        format!("Can't pipe this value into '{}': {err}", var.name),
+                        starting_line_number.to_owned(),
+                    );
+                }
+            }
+            Some(first_arg) => {
+                if !check_if_valid_type(&value_type, &mut first_arg.data_type.to_owned()) {
+                    return AstNode::Error(
+                        format!(
+                            "Can't pipe a value of type {:?} into '{}', argument '{}' is of type {:?}",
+                            value_type, var.name, first_arg.name, first_arg.data_type
+                        ),
+                        starting_line_number.to_owned(),
+                    );
+                }
+            }
+            None => {
+                return AstNode::Error(
+                    format!("Can't pipe into '{}' because it has no arguments", var.name),
+                    starting_line_number.to_owned(),
+                );
+            }
+        }
+
+        // The rest of the arguments are passed in normally
+        *i += 1;
+        let args = match get_function_args(
+            tokens,
+            i,
+            ast,
+            token_line_numbers,
+            variable_declarations,
+            FunctionCallContext {
+                name: &var.name,
+                argument_refs: &argument_refs[1..],
+                type_bindings: &mut type_bindings,
+            },
+        ) {
+            Some(AstNode::Error(err, line)) => {
+                return AstNode::Error(
+                    format!(
+                        "{err} ('{}' is passed in by the pipe)",
+                        argument_refs[0].name
+                    ),
+                    line,
+                );
+            }
+            Some(AstNode::Empty) => value,
+            Some(AstNode::Tuple(mut args, line)) => {
+                args.insert(0, value);
+                AstNode::Tuple(args, line)
+            }
+            Some(arg) => AstNode::Tuple(vec![value, arg], starting_line_number.to_owned()),
+            None => {
+                return AstNode::Error(
+                    format!("Expected '(' after '{}' in a pipe", var.name),
+                    starting_line_number.to_owned(),
+                );
+            }
+        };
+
+        if let Err(err) = check_borrows(&var.name, &args) {
+            return AstNode::Error(err, starting_line_number.to_owned());
+        }
+
+        value_type = substitute(return_type, &type_bindings);
+        value = fold_call(
+            AstNode::FunctionCall(var.name.to_owned(), Box::new(args), value_type.to_owned()),
+            false,
+            ast,
+            starting_line_number,
+            variable_declarations,
+        );
+    }
+
+    if !check_if_valid_type(&value_type, data_type) {
+        return AstNode::Error(
+            format!(
+                "Pipe returns type {:?}, but used in an expression of type {:?}",
+                value_type, data_type
+            ),
+            starting_line_number.to_owned(),
+        );
+    }
+
+    value
 }
 
 // RETURNING NONE MEANS NOT A FUNCTION CALL -> JUST A REFERENCE
@@ -457,8 +1412,8 @@ pub fn get_args(
     tokens: &Vec<Token>,
     i: &mut usize,
     ast: &Vec<AstNode>,
-    token_line_number: &u32,
-    variable_declarations: &Vec<Reference>,
+    token_line_numbers: &Vec<u32>,
+    variable_declarations: &mut ScopeStack,
     argument_refs: &Vec<Reference>,
 ) -> Option<AstNode> {
     if *i >= tokens.len() {
@@ -492,7 +1447,7 @@ pub fn get_args(
             &mut *i,
             false,
             ast,
-            token_line_number,
+            token_line_numbers,
             &mut data_type,
             true,
             variable_declarations,
@@ -501,6 +1456,202 @@ pub fn get_args(
     }
 }
 
+// The function that arguments are being parsed for
+pub struct FunctionCallContext<'a> {
+    pub name: &'a str,
+    pub argument_refs: &'a [Reference],
+
+    // Type parameters worked out from the arguments so far
+    pub type_bindings: &'a mut TypeBindings,
+}
+
+// Arguments for calling a function declared in Beanstalk
+// Can be passed in by position or by name (x: 1), and missing arguments use their default values
+// Current token should be the open parenthesis, and this moves past the closing parenthesis
+// RETURNING NONE MEANS NOT A FUNCTION CALL -> JUST A REFERENCE
+pub fn get_function_args(
+    tokens: &Vec<Token>,
+    i: &mut usize,
+    ast: &Vec<AstNode>,
+    token_line_numbers: &Vec<u32>,
+    variable_declarations: &mut ScopeStack,
+    function: FunctionCallContext,
+) -> Option<AstNode> {
+    let FunctionCallContext {
+        name: function_name,
+        argument_refs,
+        type_bindings,
+    } = function;
+    if tokens.get(*i) != Some(&Token::OpenParenthesis) {
+        return None;
+    }
+    let token_line_number = &token_line_numbers[*i];
+    *i += 1;
+
+    let mut args: Vec<Option<AstNode>> = vec![None; argument_refs.len()];
+    let mut next_position = 0;
+    let mut named_args_used = false;
+
+    while let Some(token) = tokens.get(*i) {
+        let index = match token {
+            Token::CloseParenthesis => {
+                *i += 1;
+                break;
+            }
+            Token::Comma | Token::Newline => {
+                *i += 1;
+                continue;
+            }
+            Token::EOF => {
+                return Some(AstNode::Error(
+                    format!("Missing ')' at the end of the call to '{function_name}'"),
+                    token_line_number.to_owned(),
+                ));
+            }
+
+            // Named argument
+            Token::Variable(name) if tokens.get(*i + 1) == Some(&Token::Colon) => {
+                named_args_used = true;
+                *i += 2;
+                match argument_refs.iter().position(|arg| arg.name == *name) {
+                    Some(index) => index,
+                    None => {
+                        return Some(AstNode::Error(
+                            format!("'{function_name}' doesn't have an argument called '{name}'"),
+                            token_line_number.to_owned(),
+                        ));
+                    }
+                }
+            }
+
+            _ => {
+                if named_args_used {
+                    return Some(AstNode::Error(
+                        format!("Positional arguments can't come after named arguments in the call to '{function_name}'"),
+                        token_line_number.to_owned(),
+                    ));
+                }
+                if next_position >= argument_refs.len() {
+                    return Some(AstNode::Error(
+                        format!(
+                            "Too many arguments passed into '{function_name}', it only takes {}",
+                            argument_refs.len()
+                        ),
+                        token_line_number.to_owned(),
+                    ));
+                }
+                next_position += 1;
+                next_position - 1
+            }
+        };
+
+        let arg = &argument_refs[index];
+        if args[index].is_some() {
+            return Some(AstNode::Error(
+                format!(
+                    "Argument '{}' was passed into '{function_name}' more than once",
+                    arg.name
+                ),
+                token_line_number.to_owned(),
+            ));
+        }
+
+        // Type parameters that haven't been worked out yet are taken from the value
+        let mut expected_type = substitute(&arg.data_type, type_bindings);
+        if is_generic(&expected_type) {
+            expected_type = DataType::Inferred;
+        }
+
+        let value = create_expression(
+            tokens,
+            i,
+            true,
+            ast,
+            token_line_numbers,
+            &mut expected_type,
+            false,
+            variable_declarations,
+        );
+        if let AstNode::Error(err, line) = value {
+            return Some(AstNode::Error(
+                format!("Argument '{}' of '{function_name}': {}", arg.name, err),
+                line,
+            ));
+        }
+        if is_generic(&arg.data_type) {
+            if let Err(err) = bind(&arg.data_type, &value.get_type(), type_bindings) {
+                return Some(AstNode::Error(
+                    format!("Argument '{}' of '{function_name}': {err}", arg.name),
+                    token_line_number.to_owned(),
+                ));
+            }
+        }
+        args[index] = Some(value);
+    }
+
+    // Fill in any missing arguments with their default values
+    let mut values = Vec::with_capacity(args.len());
+    for (arg, value) in argument_refs.iter().zip(args) {
+        match value.or_else(|| arg.default_value.as_ref().map(|value| *value.to_owned())) {
+            Some(value) => values.push(value),
+            None => {
+                return Some(AstNode::Error(
+                    format!("Missing argument '{}' for '{function_name}'", arg.name),
+                    token_line_number.to_owned(),
+                ));
+            }
+        }
+    }
+
+    let args = match values.len() {
+        0 => AstNode::Empty,
+        1 => values.remove(0),
+        _ => AstNode::Tuple(values, token_line_number.to_owned()),
+    };
+
+    if let Err(err) = check_borrows(function_name, &args) {
+        return Some(AstNode::Error(err, token_line_number.to_owned()));
+    }
+
+    Some(args)
+}
+
+// Whether the next part of an expression should be a value rather than an operator
+fn expects_value(expression: &[AstNode]) -> bool {
+    matches!(
+        expression.last(),
+        None | Some(
+            AstNode::BinaryOperator(..) | AstNode::LogicalOperator(..) | AstNode::UnaryOperator(..)
+        )
+    )
+}
+
+// Operators like '*' are valid in any expression that is already a number
+fn check_if_numerical(number_union: &DataType, accepted_type: &mut DataType) -> bool {
+    match accepted_type {
+        DataType::Int | DataType::Float | DataType::CoerseToString => true,
+        DataType::Generic(_, constraint) => constraint.as_ref() == number_union,
+        _ => check_if_valid_type(number_union, accepted_type),
+    }
+}
+
+// Ints can be used in a Float expression, as they are converted to Floats
+// An inferred Int expression becomes a Float expression when a Float is used in it
+fn check_number_type(
+    data_type: &DataType,
+    accepted_type: &mut DataType,
+    type_inferred: bool,
+) -> bool {
+    match (data_type, &accepted_type) {
+        (DataType::Int, DataType::Float) => true,
+        (DataType::Float, DataType::Int) if type_inferred => {
+            *accepted_type = DataType::Float;
+            true
+        }
+        _ => check_if_valid_type(data_type, accepted_type),
+    }
+}
+
 fn check_if_valid_type(data_type: &DataType, accepted_type: &mut DataType) -> bool {
     match accepted_type {
         DataType::Inferred => {
@@ -508,12 +1659,41 @@ fn check_if_valid_type(data_type: &DataType, accepted_type: &mut DataType) -> bo
             true
         }
         DataType::CoerseToString => true,
+
+        // Functions only need to have the same signature, the names of the arguments don't matter
+        DataType::Function(accepted_args, accepted_return) => match data_type {
+            DataType::Function(args, return_type)
+                if return_type == accepted_return
+                    && args.len() == accepted_args.len()
+                    && args
+                        .iter()
+                        .zip(accepted_args.iter())
+                        .all(|(arg, accepted)| arg.data_type == accepted.data_type) =>
+            {
+                true
+            }
+            _ => {
+                red_ln!(
+                    "Type Error: Expected type {:?}, but got type {:?}",
+                    accepted_type,
+                    data_type
+                );
+                false
+            }
+        },
         DataType::Union(types) => {
             for t in &**types {
                 if data_type == t {
                     return true;
                 }
             }
+
+            // Smaller unions can be used where a union has all of their types
+            if let DataType::Union(value_types) = data_type {
+                if value_types.iter().all(|t| types.contains(t)) {
+                    return true;
+                }
+            }
             red_ln!(
                 "Type Error: Expected type {:?}, but got type {:?}",
                 accepted_type,
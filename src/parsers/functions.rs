@@ -1,9 +1,15 @@
 use super::{
-    ast_nodes::{AstNode, Reference},
+    ast_nodes::{AstNode, Node, Reference},
     build_ast::new_ast,
     expressions::parse_expression::create_expression,
+    generics::{is_generic, type_parameters},
+    scope::{ScopeKind, ScopeStack},
+    unions::parse_union,
+};
+use crate::{
+    bs_types::{number_type, DataType},
+    Token,
 };
-use crate::{bs_types::DataType, Token};
 
 pub fn create_function(
     name: String,
@@ -12,13 +18,19 @@ pub fn create_function(
     is_exported: bool,
     ast: &Vec<AstNode>,
     token_line_numbers: &Vec<u32>,
-    variable_declarations: &mut Vec<Reference>,
+    variable_declarations: &mut ScopeStack,
 ) -> AstNode {
     /*
         funcName fn(arg type, arg2 type = default_value) -> returnType:
             -- Function body
         end
 
+        Generic function, T is worked out from the arguments when it is called
+
+        funcName fn(T Type, arg T) -> T:
+            -- Function body
+        end
+
         No return value
 
         func fn():
@@ -36,6 +48,90 @@ pub fn create_function(
 
     *i += 1;
 
+    match parse_return_type_and_body(
+        tokens,
+        i,
+        ast,
+        token_line_numbers,
+        variable_declarations,
+        &arg_refs,
+        Some(&name),
+    ) {
+        Ok((return_type, function_body)) => {
+            AstNode::Function(name, arg_refs, function_body, is_exported, return_type)
+        }
+        Err(err) => err,
+    }
+}
+
+// Function literals without a name that can be used as values
+// Current token should be the 'fn' keyword
+pub fn create_anonymous_function(
+    tokens: &Vec<Token>,
+    i: &mut usize,
+    ast: &Vec<AstNode>,
+    token_line_numbers: &Vec<u32>,
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    /*
+        fn(x Int) -> Int:
+            return x + 1
+        end
+
+        fn(x Int) -> Int: return x + 1
+
+        -- Shorthand that just returns a single expression
+        fn(x Int) -> x + 1
+    */
+
+    let starting_line_number = &token_line_numbers[*i];
+
+    // Anything declared inside of the function body doesn't escape it,
+    // so the body can work with a copy of the scopes around it
+    let mut variable_declarations = variable_declarations.to_owned();
+
+    *i += 1;
+    let arg_refs = match parse_args(
+        tokens,
+        i,
+        ast,
+        token_line_numbers,
+        &mut variable_declarations,
+    ) {
+        Ok(args) => args,
+        Err(err) => {
+            return AstNode::Error(err.to_string(), *starting_line_number);
+        }
+    };
+
+    *i += 1;
+
+    match parse_return_type_and_body(
+        tokens,
+        i,
+        ast,
+        token_line_numbers,
+        &mut variable_declarations,
+        &arg_refs,
+        None,
+    ) {
+        Ok((return_type, function_body)) => {
+            AstNode::AnonymousFunction(arg_refs, function_body, return_type)
+        }
+        Err(err) => err,
+    }
+}
+
+// Current token should be after the closing parenthesis of the arguments
+fn parse_return_type_and_body(
+    tokens: &Vec<Token>,
+    i: &mut usize,
+    ast: &Vec<AstNode>,
+    token_line_numbers: &Vec<u32>,
+    variable_declarations: &mut ScopeStack,
+    arg_refs: &Vec<Reference>,
+    name: Option<&String>,
+) -> Result<(DataType, Vec<AstNode>), AstNode> {
     // Return type is optional (can not return anything)
     let mut return_types: Vec<DataType> = Vec::new();
     if match &tokens[*i] {
@@ -43,20 +139,34 @@ pub fn create_function(
         _ => false,
     } {
         *i += 1;
-        return_types = match parse_return_type(tokens, i) {
+
+        // If there is no type after the arrow, the function just returns a single expression
+        let type_parameters = type_parameters(arg_refs);
+        if !is_return_type(tokens, *i, &type_parameters) {
+            return Ok(parse_shorthand_body(
+                tokens,
+                i,
+                ast,
+                token_line_numbers,
+                variable_declarations,
+                arg_refs,
+            ));
+        }
+
+        return_types = match parse_return_type(tokens, i, &type_parameters) {
             Ok(return_type) => return_type,
             Err(err) => {
-                return AstNode::Error(err.to_string(), token_line_numbers[*i]);
+                return Err(AstNode::Error(err.to_string(), token_line_numbers[*i]));
             }
         };
     }
 
     // Should now be at the colon
     if &tokens[*i] != &Token::Colon {
-        return AstNode::Error(
+        return Err(AstNode::Error(
             "Expected ':' to open function scope".to_string(),
             token_line_numbers[*i],
-        );
+        ));
     }
 
     *i += 1;
@@ -69,24 +179,110 @@ pub fn create_function(
         DataType::Tuple(Box::new(return_types))
     };
 
-    variable_declarations.push(Reference {
-        name: name.to_owned(),
-        data_type: DataType::Function(Box::new(arg_refs.clone()), Box::new(return_type.clone())),
-        default_value: None,
-    });
+    // Declared before the body is parsed so the function can call itself
+    if let Some(name) = name {
+        variable_declarations.declare(Reference {
+            name: name.to_owned(),
+            data_type: DataType::Function(
+                Box::new(arg_refs.clone()),
+                Box::new(return_type.clone()),
+            ),
+            default_value: None,
+        });
+    }
+
+    // The function body can see everything in the scopes around it
+    variable_declarations.push(ScopeKind::Function);
+    for arg in arg_refs {
+        variable_declarations.declare(arg.to_owned());
+    }
+
+    let function_body = match &tokens[*i] {
+        // The function ends with the 'end' keyword
+        Token::Newline => {
+            new_ast(
+                tokens.to_vec(),
+                i,
+                token_line_numbers,
+                variable_declarations,
+                &return_type,
+                false,
+            )
+            .0
+        }
+
+        // Or is a single return statement on the same line
+        Token::Return => {
+            *i += 1;
+            let return_value = create_expression(
+                tokens,
+                i,
+                true,
+                ast,
+                token_line_numbers,
+                &mut return_type.to_owned(),
+                false,
+                variable_declarations,
+            );
+            vec![AstNode::Return(Box::new(return_value))]
+        }
+
+        _ => {
+            variable_declarations.pop();
+            return Err(AstNode::Error(
+                "Expected a new line or a return statement after ':'".to_string(),
+                token_line_numbers[*i],
+            ));
+        }
+    };
+    variable_declarations.pop();
+
+    Ok((return_type, function_body))
+}
+
+fn parse_shorthand_body(
+    tokens: &Vec<Token>,
+    i: &mut usize,
+    ast: &Vec<AstNode>,
+    token_line_numbers: &Vec<u32>,
+    variable_declarations: &mut ScopeStack,
+    arg_refs: &Vec<Reference>,
+) -> (DataType, Vec<AstNode>) {
+    variable_declarations.push(ScopeKind::Function);
+    for arg in arg_refs {
+        variable_declarations.declare(arg.to_owned());
+    }
 
-    // The function ends with the 'end' keyword
-    let function_body = new_ast(
-        tokens.to_vec(),
+    let mut return_type = DataType::Inferred;
+    let return_value = create_expression(
+        tokens,
         i,
+        true,
+        ast,
         token_line_numbers,
-        arg_refs.clone(),
-        &return_type,
+        &mut return_type,
         false,
+        variable_declarations,
+    );
+    variable_declarations.pop();
+
+    (
+        return_value.get_type(),
+        vec![AstNode::Return(Box::new(return_value))],
     )
-    .0;
+}
 
-    AstNode::Function(name, arg_refs, function_body, is_exported, return_type)
+fn is_return_type(tokens: &[Token], i: usize, type_parameters: &[(String, DataType)]) -> bool {
+    match tokens.get(i) {
+        Some(Token::TypeKeyword(_)) => true,
+        Some(Token::Variable(name)) => type_parameters
+            .iter()
+            .any(|(parameter, _)| parameter == name),
+        Some(Token::OpenParenthesis | Token::OpenCurly) => {
+            is_return_type(tokens, i + 1, type_parameters)
+        }
+        _ => false,
+    }
 }
 
 fn parse_args(
@@ -94,9 +290,10 @@ fn parse_args(
     i: &mut usize,
     ast: &Vec<AstNode>,
     token_line_numbers: &Vec<u32>,
-    variable_declarations: &Vec<Reference>,
+    variable_declarations: &mut ScopeStack,
 ) -> Result<Vec<Reference>, &'static str> {
     let mut args = Vec::<Reference>::new();
+    let mut declared_type_parameters: Vec<(String, DataType)> = Vec::new();
 
     // Check if there are arguments
     let mut open_parenthesis = 0;
@@ -129,22 +326,37 @@ fn parse_args(
                     arg_name type
                 */
 
-                // Make sure function arguments are not redeclared variables
-                for var in variable_declarations {
-                    if var.name == *arg_name {
-                        return Err("Function arguments must have unique names");
-                    }
+                // Arguments can shadow variables from outer scopes, but not constants
+                if args.iter().any(|arg| arg.name == *arg_name)
+                    || declared_type_parameters
+                        .iter()
+                        .any(|(name, _)| name == arg_name)
+                {
+                    return Err("Function arguments must have unique names");
+                }
+                if arg_name.to_uppercase() == *arg_name
+                    && variable_declarations.get(arg_name).is_some()
+                {
+                    return Err("Function arguments can't shadow constants");
                 }
 
                 // Check if there is a type keyword
                 *i += 1;
 
-                let mut data_type = match &tokens[*i] {
-                    Token::TypeKeyword(data_type) => data_type.to_owned(),
-                    _ => {
-                        return Err("Expected type keyword after argument name");
-                    }
+                // Type parameters are worked out from the other arguments, so they are never passed in
+                // Number can still be part of a union, like Number | String
+                let constraint = match tokens.get(*i + 1) {
+                    Some(Token::Bar) => None,
+                    _ => type_parameter_constraint(&tokens[*i]),
                 };
+                if let Some(constraint) = constraint {
+                    declared_type_parameters.push((arg_name.to_owned(), constraint));
+                    next_in_list = false;
+                    *i += 1;
+                    continue;
+                }
+
+                let mut data_type = parse_type(tokens, i, &declared_type_parameters)?;
 
                 // Check if there is a default value
                 let mut default_value: Option<Box<AstNode>> = None;
@@ -152,20 +364,30 @@ fn parse_args(
                     Token::Assign => true,
                     _ => false,
                 } {
+                    if is_generic(&data_type) {
+                        return Err(
+                            "Arguments that use a type parameter can't have default values",
+                        );
+                    }
+
                     *i += 2;
                     // Function args are similar to a tuple,
                     // So create expression is told it's a tuple inside brackets
                     // So it only parses up to a comma or closing parenthesis
-                    default_value = Some(Box::new(create_expression(
+                    let value = create_expression(
                         tokens,
                         i,
                         true,
                         ast,
-                        &token_line_numbers[*i],
+                        token_line_numbers,
                         &mut data_type,
                         false,
                         variable_declarations,
-                    )));
+                    );
+                    default_value = Some(Box::new(fold_default_value(value, &data_type)?));
+
+                    // Stopped at the comma or closing parenthesis after the default value
+                    *i -= 1;
                 }
 
                 args.push(Reference {
@@ -193,10 +415,131 @@ fn parse_args(
         return Err("Wrong number of parenthesis used when declaring function arguments");
     }
 
+    // Type parameters can only be worked out if an argument uses them
+    if type_parameters(&args).len() != declared_type_parameters.len() {
+        return Err("Every type parameter must be used by at least one argument");
+    }
+
     return Ok(args);
 }
 
-fn parse_return_type(tokens: &Vec<Token>, i: &mut usize) -> Result<Vec<DataType>, &'static str> {
+// 'Type' allows any type to be used for the type parameter, 'Number' only allows Ints or Floats
+fn type_parameter_constraint(token: &Token) -> Option<DataType> {
+    match token {
+        Token::TypeKeyword(DataType::Type) => Some(DataType::Inferred),
+        Token::TypeKeyword(data_type) if data_type == &number_type() => Some(number_type()),
+        _ => None,
+    }
+}
+
+// Types of arguments and return values, the current token is left on the last token of the type
+fn parse_type(
+    tokens: &[Token],
+    i: &mut usize,
+    type_parameters: &[(String, DataType)],
+) -> Result<DataType, &'static str> {
+    match &tokens[*i] {
+        token
+            if type_parameter_constraint(token).is_some()
+                && tokens.get(*i + 1) != Some(&Token::Bar) =>
+        {
+            Err("Type and Number can only be used to declare type parameters")
+        }
+        Token::TypeKeyword(data_type) => parse_union(data_type.to_owned(), tokens, i),
+        Token::FunctionKeyword => parse_function_type(tokens, i),
+
+        // Type parameters are used by name
+        Token::Variable(name) => match type_parameters
+            .iter()
+            .find(|(parameter, _)| parameter == name)
+        {
+            Some((name, constraint)) => Ok(DataType::Generic(
+                name.to_owned(),
+                Box::new(constraint.to_owned()),
+            )),
+            None => Err("Unknown type, type parameters must be declared before they are used"),
+        },
+
+        // Collection of a single type
+        Token::OpenCurly => {
+            *i += 1;
+            let inner_type = parse_type(tokens, i, type_parameters)?;
+            *i += 1;
+            if tokens.get(*i) != Some(&Token::CloseCurly) {
+                return Err("Expected '}' after the type of the collection");
+            }
+            Ok(DataType::Collection(Box::new(inner_type)))
+        }
+
+        _ => Err("Expected type keyword after argument name"),
+    }
+}
+
+// Default values are filled in at compile time, so they must be constant
+fn fold_default_value(value: AstNode, data_type: &DataType) -> Result<AstNode, &'static str> {
+    match value {
+        // Constant folding currently always creates floats
+        AstNode::Literal(Token::FloatLiteral(float))
+            if data_type == &DataType::Int && float.fract() == 0.0 =>
+        {
+            Ok(AstNode::Literal(Token::IntLiteral(float as i64)))
+        }
+        AstNode::Literal(_) => Ok(value),
+        AstNode::Error(..) => Err("Invalid default value for function argument"),
+        _ => Err("Default values for function arguments must be constant expressions"),
+    }
+}
+
+// Function types look like the signature of a function without any names
+// fn(Int, Float) -> String
+fn parse_function_type(tokens: &[Token], i: &mut usize) -> Result<DataType, &'static str> {
+    *i += 1;
+    if tokens.get(*i) != Some(&Token::OpenParenthesis) {
+        return Err("Expected '(' after fn in function type");
+    }
+
+    let mut arg_types = Vec::<Reference>::new();
+    *i += 1;
+    while let Some(token) = tokens.get(*i) {
+        match token {
+            Token::CloseParenthesis => break,
+            Token::Comma => {}
+            Token::TypeKeyword(data_type) => {
+                arg_types.push(Reference {
+                    name: arg_types.len().to_string(),
+                    data_type: data_type.to_owned(),
+                    default_value: None,
+                });
+            }
+            _ => {
+                return Err("Invalid syntax for the arguments of a function type");
+            }
+        }
+        *i += 1;
+    }
+
+    let mut return_type = DataType::None;
+    if tokens.get(*i + 1) == Some(&Token::Arrow) {
+        *i += 2;
+        return_type = match tokens.get(*i) {
+            Some(Token::TypeKeyword(data_type)) => data_type.to_owned(),
+            _ => {
+                return Err("Expected a return type after '->' in function type");
+            }
+        };
+    }
+
+    Ok(DataType::Function(
+        Box::new(arg_types),
+        Box::new(return_type),
+    ))
+}
+
+fn parse_return_type(
+    tokens: &[Token],
+    i: &mut usize,
+    type_parameters: &[(String, DataType)],
+) -> Result<Vec<DataType>, &'static str> {
     let mut return_type = Vec::<DataType>::new();
 
     // Check if there is a return type
@@ -212,9 +555,9 @@ fn parse_return_type(tokens: &Vec<Token>, i: &mut usize) -> Result<Vec<DataType>
                 open_parenthesis -= 1;
                 *i += 1;
             }
-            Token::TypeKeyword(type_keyword) => {
+            Token::TypeKeyword(_) | Token::Variable(_) | Token::OpenCurly => {
                 if next_in_list {
-                    return_type.push(type_keyword.to_owned());
+                    return_type.push(parse_type(tokens, i, type_parameters)?);
                     next_in_list = false;
                     *i += 1;
                 } else {
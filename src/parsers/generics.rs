@@ -0,0 +1,468 @@
+use super::ast_nodes::{AstNode, Node, Reference};
+use crate::bs_types::{number_type, DataType};
+
+/*
+    Generic functions declare their type parameters in the list of arguments
+    Type parameters are never passed in, they are worked out from the other arguments at each call
+
+    largest fn(T Number, a T, b T) -> T
+    first fn(T Type, items {T}) -> T
+
+    JS values aren't typed, so the generic function is used as it is in JS.
+    For WASM, each set of number types a function is called with gets its own copy of the function.
+    Generic Type declarations can't be created yet, as there are no Type declarations
+*/
+
+// Concrete types of the type parameters in a call, by name
+pub type TypeBindings = Vec<(String, DataType)>;
+
+pub fn is_generic(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::Generic(..) => true,
+        DataType::Collection(inner_type) | DataType::Channel(inner_type) => is_generic(inner_type),
+        DataType::Tuple(types) => types.iter().any(is_generic),
+        DataType::Function(args, return_type) => {
+            args.iter().any(|arg| is_generic(&arg.data_type)) || is_generic(return_type)
+        }
+        _ => false,
+    }
+}
+
+// Type parameters used by the arguments of a function, in the order they are first used
+pub fn type_parameters(args: &[Reference]) -> Vec<(String, DataType)> {
+    let mut parameters = Vec::new();
+    for arg in args {
+        collect_type_parameters(&arg.data_type, &mut parameters);
+    }
+    parameters
+}
+
+fn collect_type_parameters(data_type: &DataType, parameters: &mut Vec<(String, DataType)>) {
+    match data_type {
+        DataType::Generic(name, constraint)
+            if !parameters.iter().any(|(existing, _)| existing == name) =>
+        {
+            parameters.push((name.to_owned(), *constraint.to_owned()));
+        }
+        DataType::Collection(inner_type) | DataType::Channel(inner_type) => {
+            collect_type_parameters(inner_type, parameters);
+        }
+        DataType::Tuple(types) => {
+            for inner_type in types.iter() {
+                collect_type_parameters(inner_type, parameters);
+            }
+        }
+        DataType::Function(args, return_type) => {
+            for arg in args.iter() {
+                collect_type_parameters(&arg.data_type, parameters);
+            }
+            collect_type_parameters(return_type, parameters);
+        }
+        _ => {}
+    }
+}
+
+// Replaces the type parameters that have been worked out with their concrete types
+pub fn substitute(data_type: &DataType, bindings: &TypeBindings) -> DataType {
+    match data_type {
+        DataType::Generic(name, _) => match bindings.iter().find(|(bound, _)| bound == name) {
+            Some((_, concrete_type)) => concrete_type.to_owned(),
+            None => data_type.to_owned(),
+        },
+        DataType::Collection(inner_type) => {
+            DataType::Collection(Box::new(substitute(inner_type, bindings)))
+        }
+        DataType::Channel(inner_type) => {
+            DataType::Channel(Box::new(substitute(inner_type, bindings)))
+        }
+        DataType::Tuple(types) => DataType::Tuple(Box::new(
+            types.iter().map(|t| substitute(t, bindings)).collect(),
+        )),
+        DataType::Function(args, return_type) => DataType::Function(
+            Box::new(
+                args.iter()
+                    .map(|arg| Reference {
+                        name: arg.name.to_owned(),
+                        data_type: substitute(&arg.data_type, bindings),
+                        default_value: arg.default_value.to_owned(),
+                    })
+                    .collect(),
+            ),
+            Box::new(substitute(return_type, bindings)),
+        ),
+        _ => data_type.to_owned(),
+    }
+}
+
+// Works out the type parameters in the type of an argument from the type of the value passed in
+pub fn bind(
+    parameter_type: &DataType,
+    value_type: &DataType,
+    bindings: &mut TypeBindings,
+) -> Result<(), String> {
+    // Bool literals have their value as their type
+    let value_type = match value_type {
+        DataType::True | DataType::False => &DataType::Bool,
+        _ => value_type,
+    };
+
+    match (parameter_type, value_type) {
+        _ if !is_generic(parameter_type) && parameter_type == value_type => Ok(()),
+
+        (DataType::Generic(name, constraint), _) => {
+            if let Some((_, bound_type)) = bindings.iter().find(|(bound, _)| bound == name) {
+                if bound_type != value_type {
+                    return Err(format!(
+                        "'{name}' is {:?} in this call, but this value is {:?}",
+                        bound_type, value_type
+                    ));
+                }
+                return Ok(());
+            }
+
+            if !satisfies(constraint, value_type) {
+                return Err(format!(
+                    "{:?} can't be used as '{name}', it must be a {}",
+                    value_type,
+                    constraint_name(constraint)
+                ));
+            }
+
+            bindings.push((name.to_owned(), value_type.to_owned()));
+            Ok(())
+        }
+
+        (DataType::Collection(parameter_inner), DataType::Collection(value_inner)) => {
+            bind(parameter_inner, value_inner, bindings)
+        }
+
+        (DataType::Tuple(parameter_types), DataType::Tuple(value_types))
+            if parameter_types.len() == value_types.len() =>
+        {
+            for (parameter_type, value_type) in parameter_types.iter().zip(value_types.iter()) {
+                bind(parameter_type, value_type, bindings)?;
+            }
+            Ok(())
+        }
+
+        _ => Err(format!(
+            "Expected type {:?}, but got type {:?}",
+            substitute(parameter_type, bindings),
+            value_type
+        )),
+    }
+}
+
+fn satisfies(constraint: &DataType, data_type: &DataType) -> bool {
+    match (constraint, data_type) {
+        (_, DataType::Inferred) => false,
+        (DataType::Inferred, _) => true,
+
+        // Type parameters from the function the call is inside of
+        (_, DataType::Generic(_, inner_constraint)) => inner_constraint.as_ref() == constraint,
+
+        (DataType::Union(types), _) => types.contains(data_type),
+        _ => constraint == data_type,
+    }
+}
+
+fn constraint_name(constraint: &DataType) -> String {
+    if *constraint == number_type() {
+        return "Number".to_string();
+    }
+    format!("{:?}", constraint)
+}
+
+// A copy of a generic function for one set of concrete types
+struct Instance {
+    generic_name: String,
+    bindings: TypeBindings,
+    name: String,
+}
+
+// Creates a copy of each generic function for every set of number types it is called with,
+// and changes the calls to use them. Each copy is placed straight after the generic function
+pub fn monomorphise(ast: Vec<AstNode>) -> Vec<AstNode> {
+    let generic_functions: Vec<AstNode> = ast
+        .iter()
+        .filter(|node| match node {
+            AstNode::Function(_, args, ..) => args.iter().any(|arg| is_generic(&arg.data_type)),
+            _ => false,
+        })
+        .cloned()
+        .collect();
+    if generic_functions.is_empty() {
+        return ast;
+    }
+
+    let mut instances = Vec::new();
+    let mut ast: Vec<AstNode> = ast
+        .into_iter()
+        .map(|node| use_instances(node, &generic_functions, &mut instances))
+        .collect();
+
+    // Copies can call other generic functions, which might need new copies as well
+    let mut created = 0;
+    while created < instances.len() {
+        let instance = &instances[created];
+        created += 1;
+
+        let Some(AstNode::Function(_, args, body, _, return_type)) = generic_functions.iter().find(
+            |node| matches!(node, AstNode::Function(name, ..) if *name == instance.generic_name),
+        ) else {
+            continue;
+        };
+        let name = instance.name.to_owned();
+        let bindings = instance.bindings.to_owned();
+        let generic_name = instance.generic_name.to_owned();
+
+        let function = AstNode::Function(
+            name,
+            args.iter()
+                .map(|arg| substitute_reference(arg, &bindings))
+                .collect(),
+            body.iter()
+                .map(|node| substitute_node(node, &bindings))
+                .collect(),
+            false,
+            substitute(return_type, &bindings),
+        );
+        let function = use_instances(function, &generic_functions, &mut instances);
+
+        // After the generic function and any copies of it already added
+        let mut index = ast
+            .iter()
+            .position(|node| matches!(node, AstNode::Function(name, ..) if *name == generic_name))
+            .unwrap_or(ast.len());
+        let copy_prefix = format!("{generic_name}$");
+        while let Some(AstNode::Function(name, ..)) = ast.get(index + 1) {
+            if !name.starts_with(&copy_prefix) {
+                break;
+            }
+            index += 1;
+        }
+        ast.insert(index + 1, function);
+    }
+
+    ast
+}
+
+// Changes calls to generic functions to use the copy for the number types they are called with
+fn use_instances(
+    node: AstNode,
+    generic_functions: &[AstNode],
+    instances: &mut Vec<Instance>,
+) -> AstNode {
+    let mut instance_of = |node: AstNode| use_instances(node, generic_functions, instances);
+
+    match node {
+        AstNode::FunctionCall(name, args, return_type) => {
+            let args = Box::new(instance_of(*args));
+            let name = match generic_functions
+                .iter()
+                .find_map(|function| match function {
+                    AstNode::Function(generic_name, arg_refs, ..) if *generic_name == name => {
+                        Some(arg_refs)
+                    }
+                    _ => None,
+                }) {
+                Some(arg_refs) => instance_name(&name, arg_refs, &args, instances).unwrap_or(name),
+                None => name,
+            };
+            AstNode::FunctionCall(name, args, return_type)
+        }
+        AstNode::Function(name, args, body, is_exported, return_type) => AstNode::Function(
+            name,
+            args,
+            body.into_iter().map(instance_of).collect(),
+            is_exported,
+            return_type,
+        ),
+        AstNode::AnonymousFunction(args, body, return_type) => AstNode::AnonymousFunction(
+            args,
+            body.into_iter().map(instance_of).collect(),
+            return_type,
+        ),
+        AstNode::If(condition, body, else_body) => AstNode::If(
+            Box::new(instance_of(*condition)),
+            body.into_iter().map(&mut instance_of).collect(),
+            else_body.into_iter().map(instance_of).collect(),
+        ),
+        AstNode::For(item, iterable, body) => AstNode::For(
+            item,
+            Box::new(instance_of(*iterable)),
+            body.into_iter().map(instance_of).collect(),
+        ),
+        AstNode::VarDeclaration(name, value, is_exported, data_type, is_const) => {
+            AstNode::VarDeclaration(
+                name,
+                Box::new(instance_of(*value)),
+                is_exported,
+                data_type,
+                is_const,
+            )
+        }
+        AstNode::Assignment(name, operator, value, data_type) => {
+            AstNode::Assignment(name, operator, Box::new(instance_of(*value)), data_type)
+        }
+        AstNode::Return(value) => AstNode::Return(Box::new(instance_of(*value))),
+        AstNode::Print(value) => AstNode::Print(Box::new(instance_of(*value))),
+        AstNode::Copy(value) => AstNode::Copy(Box::new(instance_of(*value))),
+        AstNode::ChannelSend(name, value) => {
+            AstNode::ChannelSend(name, Box::new(instance_of(*value)))
+        }
+        AstNode::Expression(nodes, line_number) => {
+            AstNode::Expression(nodes.into_iter().map(instance_of).collect(), line_number)
+        }
+        AstNode::RuntimeExpression(nodes, data_type) => {
+            AstNode::RuntimeExpression(nodes.into_iter().map(instance_of).collect(), data_type)
+        }
+        AstNode::Tuple(nodes, line_number) => {
+            AstNode::Tuple(nodes.into_iter().map(instance_of).collect(), line_number)
+        }
+        AstNode::Collection(nodes, data_type) => {
+            AstNode::Collection(nodes.into_iter().map(instance_of).collect(), data_type)
+        }
+        _ => node,
+    }
+}
+
+// Copies are named after the generic function and their number types, like add$Int
+pub fn is_instance(name: &str) -> bool {
+    name.contains('$')
+}
+
+// Only numbers have different types in WASM, so other types use the generic function in JS
+fn instance_name(
+    name: &str,
+    arg_refs: &[Reference],
+    args: &AstNode,
+    instances: &mut Vec<Instance>,
+) -> Option<String> {
+    let values = match args {
+        AstNode::Tuple(values, _) => values.iter().collect(),
+        AstNode::Empty => Vec::new(),
+        value => vec![value],
+    };
+
+    let mut bindings = TypeBindings::new();
+    for (arg, value) in arg_refs.iter().zip(values) {
+        bind(&arg.data_type, &value.get_type(), &mut bindings).ok()?;
+    }
+
+    let parameters = type_parameters(arg_refs);
+    let mut types = Vec::new();
+    for (parameter, _) in &parameters {
+        match bindings.iter().find(|(bound, _)| bound == parameter) {
+            Some((_, concrete_type @ (DataType::Int | DataType::Float))) => {
+                types.push(format!("{:?}", concrete_type))
+            }
+            _ => return None,
+        }
+    }
+
+    let instance_name = format!("{name}${}", types.join("$"));
+    if !instances
+        .iter()
+        .any(|instance| instance.name == instance_name)
+    {
+        instances.push(Instance {
+            generic_name: name.to_owned(),
+            bindings,
+            name: instance_name.to_owned(),
+        });
+    }
+    Some(instance_name)
+}
+
+fn substitute_reference(reference: &Reference, bindings: &TypeBindings) -> Reference {
+    Reference {
+        name: reference.name.to_owned(),
+        data_type: substitute(&reference.data_type, bindings),
+        default_value: reference
+            .default_value
+            .as_ref()
+            .map(|value| Box::new(substitute_node(value, bindings))),
+    }
+}
+
+// Replaces the type parameters in the types of a node and everything inside of it
+fn substitute_node(node: &AstNode, bindings: &TypeBindings) -> AstNode {
+    let all = |nodes: &Vec<AstNode>| -> Vec<AstNode> {
+        nodes
+            .iter()
+            .map(|node| substitute_node(node, bindings))
+            .collect()
+    };
+    let boxed = |node: &AstNode| Box::new(substitute_node(node, bindings));
+
+    match node {
+        AstNode::VarReference(name, data_type) => {
+            AstNode::VarReference(name.to_owned(), substitute(data_type, bindings))
+        }
+        AstNode::ConstReference(name, data_type) => {
+            AstNode::ConstReference(name.to_owned(), substitute(data_type, bindings))
+        }
+        AstNode::CollectionAccess(name, index, data_type) => {
+            AstNode::CollectionAccess(name.to_owned(), *index, substitute(data_type, bindings))
+        }
+        AstNode::TupleAccess(name, index, data_type) => {
+            AstNode::TupleAccess(name.to_owned(), *index, substitute(data_type, bindings))
+        }
+        AstNode::FunctionCall(name, args, return_type) => AstNode::FunctionCall(
+            name.to_owned(),
+            boxed(args),
+            substitute(return_type, bindings),
+        ),
+        AstNode::Function(name, args, body, is_exported, return_type) => AstNode::Function(
+            name.to_owned(),
+            args.iter()
+                .map(|arg| substitute_reference(arg, bindings))
+                .collect(),
+            all(body),
+            *is_exported,
+            substitute(return_type, bindings),
+        ),
+        AstNode::AnonymousFunction(args, body, return_type) => AstNode::AnonymousFunction(
+            args.iter()
+                .map(|arg| substitute_reference(arg, bindings))
+                .collect(),
+            all(body),
+            substitute(return_type, bindings),
+        ),
+        AstNode::If(condition, body, else_body) => {
+            AstNode::If(boxed(condition), all(body), all(else_body))
+        }
+        AstNode::For(item, iterable, body) => {
+            AstNode::For(item.to_owned(), boxed(iterable), all(body))
+        }
+        AstNode::VarDeclaration(name, value, is_exported, data_type, is_const) => {
+            AstNode::VarDeclaration(
+                name.to_owned(),
+                boxed(value),
+                *is_exported,
+                substitute(data_type, bindings),
+                *is_const,
+            )
+        }
+        AstNode::Assignment(name, operator, value, data_type) => AstNode::Assignment(
+            name.to_owned(),
+            operator.to_owned(),
+            boxed(value),
+            substitute(data_type, bindings),
+        ),
+        AstNode::Return(value) => AstNode::Return(boxed(value)),
+        AstNode::Print(value) => AstNode::Print(boxed(value)),
+        AstNode::Copy(value) => AstNode::Copy(boxed(value)),
+        AstNode::ChannelSend(name, value) => AstNode::ChannelSend(name.to_owned(), boxed(value)),
+        AstNode::Expression(nodes, line_number) => AstNode::Expression(all(nodes), *line_number),
+        AstNode::RuntimeExpression(nodes, data_type) => {
+            AstNode::RuntimeExpression(all(nodes), substitute(data_type, bindings))
+        }
+        AstNode::Tuple(nodes, line_number) => AstNode::Tuple(all(nodes), *line_number),
+        AstNode::Collection(nodes, data_type) => {
+            AstNode::Collection(all(nodes), substitute(data_type, bindings))
+        }
+        _ => node.to_owned(),
+    }
+}
@@ -0,0 +1,110 @@
+use super::{
+    ast_nodes::AstNode,
+    scope::{undefined_name_error, ScopeStack},
+};
+use crate::{bs_types::DataType, Token};
+
+/*
+    Ownership of values
+
+    Numbers, Bools and Strings are always copied
+    Collections only have one owner at a time:
+
+    - Giving a collection to another variable (or sending it through a channel) moves it
+      The old name can't be used again until it is given a new value
+    - Passing a collection into a function gives the function a mutable reference to it,
+      so the same collection can't be passed into one call more than once
+    - 'copy' creates a deep copy, so the original isn't moved
+
+    words = String.split("a b c", " ")
+    other = words           -- words is moved to other
+    kept = copy other       -- other can still be used
+*/
+
+pub fn is_owned(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::Collection(_))
+}
+
+// A value that is just the name of a collection moves that collection
+pub fn move_if_owned(
+    value: &AstNode,
+    line_number: u32,
+    variable_declarations: &mut ScopeStack,
+) -> Result<(), String> {
+    match value {
+        AstNode::VarReference(name, data_type) if is_owned(data_type) => {
+            variable_declarations.move_value(name, line_number)
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn moved_value_error(name: &str, moved_on: u32, line_number: u32) -> AstNode {
+    AstNode::Error(
+        format!(
+            "'{name}' can't be used after it was moved on line {moved_on}, use 'copy {name}' to keep using it"
+        ),
+        line_number,
+    )
+}
+
+// Every collection passed into a call is a mutable reference, so each one can only be passed in once
+pub fn check_borrows(function_name: &str, args: &AstNode) -> Result<(), String> {
+    let args = match args {
+        AstNode::Tuple(args, _) => args.as_slice(),
+        arg => std::slice::from_ref(arg),
+    };
+
+    let mut borrowed: Vec<&String> = Vec::new();
+    for arg in args {
+        if let AstNode::VarReference(name, data_type) = arg {
+            if !is_owned(data_type) {
+                continue;
+            }
+            if borrowed.contains(&name) {
+                return Err(format!(
+                    "'{name}' is passed into '{function_name}' more than once, but it can only have one mutable reference at a time. Use 'copy {name}' for the other arguments"
+                ));
+            }
+            borrowed.push(name);
+        }
+    }
+
+    Ok(())
+}
+
+// Current token should be the 'copy' keyword, this moves to the name being copied
+pub fn create_copy(
+    tokens: &[Token],
+    i: &mut usize,
+    line_number: u32,
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    let name = match tokens.get(*i + 1) {
+        Some(Token::Variable(name)) => name,
+        _ => {
+            return AstNode::Error(
+                "Expected the name of a variable after 'copy'".to_string(),
+                line_number,
+            );
+        }
+    };
+    *i += 1;
+
+    let var = match variable_declarations.get(name) {
+        Some(var) => var,
+        None => return undefined_name_error(name, tokens, *i, line_number),
+    };
+
+    if let Some(moved_on) = variable_declarations.moved_on(name) {
+        return moved_value_error(name, moved_on, line_number);
+    }
+
+    let reference = AstNode::VarReference(var.name.to_owned(), var.data_type.to_owned());
+
+    // Everything else is already copied when it's used
+    if is_owned(&var.data_type) {
+        return AstNode::Copy(Box::new(reference));
+    }
+    reference
+}
@@ -0,0 +1,260 @@
+use super::ast_nodes::{AstNode, Reference};
+use crate::Token;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScopeKind {
+    Module,
+    Function,
+    If,
+    For,
+    Scene,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scope {
+    pub kind: ScopeKind,
+    pub declarations: Vec<Reference>,
+
+    // Channels closed in this scope, so can't be used after this point
+    pub closed_channels: Vec<String>,
+
+    // Collections moved in this scope, with the line they were moved on
+    // None means the name was given a new value after it was moved
+    pub moved_values: Vec<(String, Option<u32>)>,
+}
+
+// Stack of lexical scopes, the innermost scope is always the last one
+// Names are resolved from the innermost scope outwards,
+// and everything declared inside a block is dropped when that block is popped
+#[derive(Debug, Clone)]
+pub struct ScopeStack {
+    scopes: Vec<Scope>,
+
+    // Functions whose calls were run at compile time in the block being parsed
+    folded_calls: Vec<String>,
+}
+
+impl ScopeStack {
+    // Creates the module scope, with any globals or imported references already declared
+    pub fn new(module_declarations: Vec<Reference>) -> ScopeStack {
+        ScopeStack {
+            scopes: vec![Scope {
+                kind: ScopeKind::Module,
+                declarations: module_declarations,
+                closed_channels: Vec::new(),
+                moved_values: Vec::new(),
+            }],
+            folded_calls: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, kind: ScopeKind) {
+        self.scopes.push(Scope {
+            kind,
+            declarations: Vec::new(),
+            closed_channels: Vec::new(),
+            moved_values: Vec::new(),
+        });
+    }
+
+    // The module scope is never popped
+    pub fn pop(&mut self) -> Option<Scope> {
+        if self.scopes.len() <= 1 {
+            return None;
+        }
+        let scope = self.scopes.pop()?;
+
+        // A value moved inside an if statement might have been moved, so it can't be used afterwards
+        if scope.kind == ScopeKind::If {
+            for (index, (name, moved_on)) in scope.moved_values.iter().enumerate() {
+                let changed_later = scope.moved_values[index + 1..]
+                    .iter()
+                    .any(|(later, _)| later == name);
+                let declared_inside = scope.declarations.iter().any(|r| &r.name == name);
+                if let (Some(line), false, false) = (moved_on, changed_later, declared_inside) {
+                    if let Some(parent) = self.scopes.last_mut() {
+                        parent.moved_values.push((name.to_owned(), Some(*line)));
+                    }
+                }
+            }
+        }
+
+        Some(scope)
+    }
+
+    pub fn current_kind(&self) -> &ScopeKind {
+        match self.scopes.last() {
+            Some(scope) => &scope.kind,
+            None => &ScopeKind::Module,
+        }
+    }
+
+    // Whether any enclosing scope is a function body
+    pub fn in_function(&self) -> bool {
+        self.scopes.iter().any(|s| s.kind == ScopeKind::Function)
+    }
+
+    // Finds the innermost visible declaration of a name
+    pub fn get(&self, name: &str) -> Option<&Reference> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(reference) = scope.declarations.iter().rev().find(|r| r.name == name) {
+                return Some(reference);
+            }
+        }
+        None
+    }
+
+    pub fn get_in_current_scope(&self, name: &str) -> Option<&Reference> {
+        self.scopes
+            .last()
+            .and_then(|s| s.declarations.iter().rev().find(|r| r.name == name))
+    }
+
+    // Closing a channel inside a block might not happen at runtime,
+    // so it only counts until the end of that block
+    pub fn close_channel(&mut self, name: &str) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.closed_channels.push(name.to_owned());
+        }
+    }
+
+    pub fn is_channel_closed(&self, name: &str) -> bool {
+        self.scopes
+            .iter()
+            .any(|s| s.closed_channels.iter().any(|closed| closed == name))
+    }
+
+    // Values moved inside of a loop or function could be moved more than once
+    pub fn move_value(&mut self, name: &str, line_number: u32) -> Result<(), String> {
+        let declared_in = match self.declaration_index(name) {
+            Some(index) => index,
+            None => return Err(format!("Undefined name '{name}'")),
+        };
+
+        if let Some(scope) = self.scopes[declared_in + 1..]
+            .iter()
+            .find(|s| matches!(s.kind, ScopeKind::For | ScopeKind::Function))
+        {
+            let place = match scope.kind {
+                ScopeKind::For => "a loop",
+                _ => "a function",
+            };
+            return Err(format!(
+                "'{name}' can't be moved inside of {place} it is declared outside of, use 'copy {name}' instead"
+            ));
+        }
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope
+                .moved_values
+                .push((name.to_owned(), Some(line_number)));
+        }
+        Ok(())
+    }
+
+    // Giving a moved name a new value means it can be used again
+    pub fn give_new_value(&mut self, name: &str) {
+        if self.moved_on(name).is_none() {
+            return;
+        }
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.moved_values.push((name.to_owned(), None));
+        }
+    }
+
+    // The line a name was moved on, if it can't be used any more
+    pub fn moved_on(&self, name: &str) -> Option<u32> {
+        let declared_in = self.declaration_index(name)?;
+        for scope in self.scopes[declared_in..].iter().rev() {
+            if let Some((_, moved_on)) = scope.moved_values.iter().rev().find(|(n, _)| n == name) {
+                return *moved_on;
+            }
+        }
+        None
+    }
+
+    fn declaration_index(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rposition(|s| s.declarations.iter().any(|r| r.name == name))
+    }
+
+    // Adds a new declaration to the innermost scope
+    pub fn declare(&mut self, reference: Reference) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.declarations.push(reference);
+        }
+    }
+
+    // The call is replaced with its result, so this is the only record the function was used
+    pub fn fold_call(&mut self, name: &str) {
+        self.folded_calls.push(name.to_owned());
+    }
+
+    pub fn take_folded_calls(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.folded_calls)
+    }
+
+    /*
+        Shadowing rules:
+        - A name that is already visible is a reference to it,
+          unless it is redeclared with a type annotation or as a function
+        - Anything declared in an outer scope can be shadowed by a declaration in an inner scope
+        - Names can't be redeclared in the same scope
+        - Constants can never be shadowed
+    */
+    pub fn check_shadowing(&self, name: &str) -> Result<(), String> {
+        let existing = match self.get(name) {
+            Some(reference) => reference,
+            None => return Ok(()),
+        };
+
+        if name.to_uppercase() == name {
+            return Err(format!("Constant '{name}' can't be shadowed"));
+        }
+
+        if self.get_in_current_scope(name).is_some() {
+            return Err(format!(
+                "'{name}' is already declared in this scope as {:?}",
+                existing.data_type
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+// Searches forwards from the current token for a declaration of this name
+// Returns the index of the declaration if there is one
+pub fn find_later_declaration(tokens: &[Token], i: usize, name: &str) -> Option<usize> {
+    let mut index = i + 1;
+    while index + 1 < tokens.len() {
+        if let Token::Variable(var_name) = &tokens[index] {
+            if var_name == name {
+                match tokens[index + 1] {
+                    Token::Assign
+                    | Token::TypeKeyword(_)
+                    | Token::FunctionKeyword
+                    | Token::Receive => {
+                        return Some(index);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        index += 1;
+    }
+
+    None
+}
+
+// The error for a name that isn't visible from the current scope
+pub fn undefined_name_error(name: &str, tokens: &[Token], i: usize, line_number: u32) -> AstNode {
+    match find_later_declaration(tokens, i, name) {
+        Some(_) => AstNode::Error(
+            format!("'{name}' is used before it is declared"),
+            line_number,
+        ),
+        None => AstNode::Error(format!("Undefined name '{name}'"), line_number),
+    }
+}
@@ -1,8 +1,7 @@
 use colour::red_ln;
 
 use super::{
-    ast_nodes::{AstNode, Reference},
-    expressions::parse_expression::create_expression,
+    ast_nodes::AstNode, expressions::parse_expression::create_expression, scope::ScopeStack,
 };
 use crate::{bs_types::DataType, parsers::ast_nodes::Node, Token};
 
@@ -17,9 +16,10 @@ pub fn new_tuple(
     i: &mut usize,
     data_type: &mut DataType,
     ast: &Vec<AstNode>,
-    starting_line_number: &u32,
-    variable_declarations: &Vec<Reference>,
+    token_line_numbers: &Vec<u32>,
+    variable_declarations: &mut ScopeStack,
 ) -> AstNode {
+    let starting_line_number = &token_line_numbers[*i];
     let mut item_data_types = match data_type {
         DataType::Tuple(inner_types) => *inner_types.to_owned(),
         _ => Vec::new(),
@@ -67,7 +67,7 @@ pub fn new_tuple(
                     i,
                     true,
                     &ast,
-                    starting_line_number,
+                    token_line_numbers,
                     &mut item_data_type,
                     tokens[*i] == Token::OpenParenthesis,
                     variable_declarations,
@@ -0,0 +1,162 @@
+use super::{
+    ast_nodes::AstNode,
+    scope::{undefined_name_error, ScopeStack},
+};
+use crate::{bs_types::DataType, Token};
+
+/*
+    Union types allow a value to be one of several types
+
+    value Int | String = 5
+
+    Checking the type with 'is' narrows the type of the variable inside of the if statement
+    Every type in the union must be checked for, unless there is an else block
+
+    if value is Int:
+        -- value is an Int here
+    else if value is String:
+        -- value is a String here
+    end
+*/
+
+// Combines types into a union, nested unions are flattened and repeated types are removed
+// A union of one type is just that type
+pub fn union_of(types: Vec<DataType>) -> DataType {
+    let mut members = Vec::new();
+    for data_type in types {
+        for member in union_members(&data_type) {
+            if !members.contains(&member) {
+                members.push(member);
+            }
+        }
+    }
+
+    match members.len() {
+        1 => members.remove(0),
+        _ => DataType::Union(Box::new(members)),
+    }
+}
+
+pub fn union_members(data_type: &DataType) -> Vec<DataType> {
+    match data_type {
+        DataType::Union(types) => *types.to_owned(),
+        _ => vec![data_type.to_owned()],
+    }
+}
+
+// Parses the rest of a union after its first type
+// The current token should be the last token of the first type, and is left on the last type of the union
+pub fn parse_union(
+    first_type: DataType,
+    tokens: &[Token],
+    i: &mut usize,
+) -> Result<DataType, &'static str> {
+    let mut types = vec![first_type];
+    while tokens.get(*i + 1) == Some(&Token::Bar) {
+        match tokens.get(*i + 2) {
+            Some(Token::TypeKeyword(DataType::Type)) => {
+                return Err("A union can't contain 'Type', as it could already be any type");
+            }
+            Some(Token::TypeKeyword(data_type)) => types.push(data_type.to_owned()),
+            _ => return Err("Expected a type after '|'"),
+        }
+        *i += 2;
+    }
+
+    if types.contains(&DataType::Type) {
+        return Err("A union can't contain 'Type', as it could already be any type");
+    }
+
+    Ok(union_of(types))
+}
+
+// Checks for 'name is Type:' at the start of an if statement
+pub fn is_type_check(tokens: &[Token], i: usize) -> bool {
+    matches!(
+        (tokens.get(i), tokens.get(i + 1), tokens.get(i + 2)),
+        (
+            Some(Token::Variable(_)),
+            Some(Token::Equal),
+            Some(Token::TypeKeyword(_))
+        )
+    )
+}
+
+// Returns the condition, then the type of the variable if the check passes and the types left if it doesn't
+// The current token should be the name of the variable, this moves to the token after the checked type
+pub fn create_type_check(
+    tokens: &[Token],
+    i: &mut usize,
+    line_number: u32,
+    variable_declarations: &mut ScopeStack,
+) -> Result<(AstNode, DataType, Vec<DataType>), AstNode> {
+    let name = match &tokens[*i] {
+        Token::Variable(name) => name,
+        _ => {
+            return Err(AstNode::Error(
+                "Expected the name of a variable before 'is'".to_string(),
+                line_number,
+            ));
+        }
+    };
+
+    let var = match variable_declarations.get(name) {
+        Some(var) => var,
+        None => return Err(undefined_name_error(name, tokens, *i, line_number)),
+    };
+
+    // The checked type can also be a union
+    let mut type_index = *i + 2;
+    let first_type = match &tokens[type_index] {
+        Token::TypeKeyword(data_type) => data_type.to_owned(),
+        _ => DataType::Inferred,
+    };
+    let checked_type = match parse_union(first_type, tokens, &mut type_index) {
+        Ok(data_type) => data_type,
+        Err(err) => return Err(AstNode::Error(err.to_string(), line_number)),
+    };
+
+    // Variables that have already been narrowed down to one type by an earlier check
+    if !matches!(var.data_type, DataType::Union(_)) {
+        if checked_type != var.data_type {
+            return Err(AstNode::Error(
+                format!(
+                    "'{name}' is always {:?}, so it can never be {:?}",
+                    var.data_type, checked_type
+                ),
+                line_number,
+            ));
+        }
+        *i = type_index + 1;
+        return Ok((
+            AstNode::Literal(Token::BoolLiteral(true)),
+            checked_type,
+            Vec::new(),
+        ));
+    }
+
+    let members = union_members(&var.data_type);
+    for checked_member in union_members(&checked_type) {
+        if !members.contains(&checked_member) {
+            return Err(AstNode::Error(
+                format!(
+                    "'{name}' can never be {:?}, it is {:?}",
+                    checked_member, var.data_type
+                ),
+                line_number,
+            ));
+        }
+    }
+
+    let remaining = members
+        .into_iter()
+        .filter(|member| !union_members(&checked_type).contains(member))
+        .collect();
+
+    *i = type_index + 1;
+    Ok((
+        AstNode::TypeCheck(name.to_owned(), checked_type.to_owned()),
+        checked_type,
+        remaining,
+    ))
+}
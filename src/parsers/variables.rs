@@ -1,15 +1,22 @@
 use crate::{bs_types::DataType, Token};
 
 use super::{
-    ast_nodes::{AstNode, Reference},
+    ast_nodes::{AstNode, Node, Reference},
+    channels::{create_channel, create_channel_close, create_channel_send, has_send},
     collections::new_collection,
-    expressions::parse_expression::{create_expression, get_args},
+    expressions::parse_expression::{
+        create_expression, get_function_args, has_pipe, FunctionCallContext,
+    },
     functions::create_function,
+    generics::{substitute, TypeBindings},
+    ownership::move_if_owned,
+    scope::{undefined_name_error, ScopeStack},
+    unions::parse_union,
 };
 
 pub fn create_new_var_or_ref(
     name: &String,
-    variable_declarations: &mut Vec<Reference>,
+    variable_declarations: &mut ScopeStack,
     tokens: &Vec<Token>,
     i: &mut usize,
     is_exported: bool,
@@ -18,22 +25,69 @@ pub fn create_new_var_or_ref(
 ) -> AstNode {
     let is_const = name.to_uppercase() == *name;
 
-    if let Some(var) = variable_declarations.iter().find(|v| v.name == *name) {
+    // A type annotation, function keyword or channel type after an existing name shadows it
+    let next_token = tokens.get(*i + 1).unwrap_or(&Token::EOF);
+    let is_redeclaration = matches!(
+        next_token,
+        Token::TypeKeyword(_) | Token::FunctionKeyword | Token::Receive
+    );
+    if is_redeclaration {
+        if let Err(err) = variable_declarations.check_shadowing(name) {
+            return AstNode::Error(err, token_line_numbers[*i]);
+        }
+    } else if let Some(var) = variable_declarations.get(name).cloned() {
+        // Sending a value to a channel
+        if !is_assignment_operator(next_token) && has_send(tokens, *i) {
+            return create_channel_send(tokens, i, ast, token_line_numbers, variable_declarations);
+        }
+
+        // Closing a channel
+        if matches!(var.data_type, DataType::Channel(_))
+            && next_token == &Token::Variable("close".to_string())
+        {
+            let line_number = token_line_numbers[*i];
+            return create_channel_close(name, tokens, i, line_number, variable_declarations);
+        }
+
+        // Piping a value into a function
+        if !is_assignment_operator(next_token) && has_pipe(tokens, *i) {
+            return create_expression(
+                tokens,
+                i,
+                false,
+                ast,
+                token_line_numbers,
+                &mut DataType::Inferred,
+                false,
+                variable_declarations,
+            );
+        }
+
         match var.data_type {
             DataType::Function(ref argument_refs, ref return_type) => {
                 // Parse arguments passed into the function
-                let args = match get_args(
+                *i += 1;
+                let mut type_bindings = TypeBindings::new();
+                let args = match get_function_args(
                     tokens,
                     i,
                     ast,
-                    &token_line_numbers[*i],
+                    token_line_numbers,
                     variable_declarations,
-                    argument_refs,
+                    FunctionCallContext {
+                        name,
+                        argument_refs,
+                        type_bindings: &mut type_bindings,
+                    },
                 ) {
+                    Some(AstNode::Error(err, line)) => {
+                        return AstNode::Error(err, line);
+                    }
                     Some(args) => args,
 
                     // Returning None here means no brackets, which means it's just a reference to the function
                     None => {
+                        *i -= 1;
                         return AstNode::VarReference(
                             var.name.to_owned(),
                             var.data_type.to_owned(),
@@ -44,15 +98,29 @@ pub fn create_new_var_or_ref(
                 return AstNode::FunctionCall(
                     name.to_owned(),
                     Box::new(args),
-                    *return_type.to_owned(),
+                    substitute(return_type, &type_bindings),
                 );
             }
             _ => {}
         }
+
+        if is_assignment_operator(next_token) {
+            return create_assignment(
+                var.to_owned(),
+                tokens,
+                i,
+                ast,
+                token_line_numbers,
+                variable_declarations,
+            );
+        }
+
         if is_const {
             return AstNode::ConstReference(var.name.to_owned(), var.data_type.to_owned());
         }
         return AstNode::VarReference(var.name.to_owned(), var.data_type.to_owned());
+    } else if !matches!(next_token, Token::Assign | Token::Receive) {
+        return undefined_name_error(name, tokens, *i, token_line_numbers[*i]);
     }
 
     new_variable(
@@ -67,6 +135,94 @@ pub fn create_new_var_or_ref(
     )
 }
 
+fn is_assignment_operator(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Assign
+            | Token::AddAssign
+            | Token::SubtractAssign
+            | Token::MultiplyAssign
+            | Token::DivideAssign
+            | Token::ModulusAssign
+            | Token::RemainderAssign
+            | Token::ExponentAssign
+            | Token::RootAssign
+    )
+}
+
+// Reassigns or mutates a variable that already exists
+// Current token should be the name of the variable
+fn create_assignment(
+    var: Reference,
+    tokens: &Vec<Token>,
+    i: &mut usize,
+    ast: &Vec<AstNode>,
+    token_line_numbers: &Vec<u32>,
+    variable_declarations: &mut ScopeStack,
+) -> AstNode {
+    let line_number = token_line_numbers[*i];
+
+    if var.name.to_uppercase() == var.name {
+        return AstNode::Error(
+            format!("Constant '{}' can't be reassigned", var.name),
+            line_number,
+        );
+    }
+
+    *i += 1;
+    let operator = tokens[*i].to_owned();
+
+    // Make sure the compound assignment makes sense for this type
+    let valid_operator = match var.data_type {
+        DataType::Int | DataType::Float => {
+            operator != Token::RootAssign || var.data_type == DataType::Float
+        }
+        DataType::String | DataType::Scene => {
+            operator == Token::Assign || operator == Token::AddAssign
+        }
+        DataType::Function(..) => false,
+        _ => operator == Token::Assign,
+    };
+    if !valid_operator {
+        return AstNode::Error(
+            format!(
+                "Can't use {:?} to change '{}' (type {:?})",
+                operator, var.name, var.data_type
+            ),
+            line_number,
+        );
+    }
+
+    *i += 1;
+    let mut data_type = var.data_type.to_owned();
+    let value = create_expression(
+        tokens,
+        i,
+        false,
+        ast,
+        token_line_numbers,
+        &mut data_type,
+        false,
+        variable_declarations,
+    );
+
+    if let AstNode::Error(err, line) = value {
+        return AstNode::Error(
+            format!("Invalid value assigned to '{}': {}", var.name, err),
+            line,
+        );
+    }
+
+    if operator == Token::Assign {
+        if let Err(err) = move_if_owned(&value, line_number, variable_declarations) {
+            return AstNode::Error(err, line_number);
+        }
+        variable_declarations.give_new_value(&var.name);
+    }
+
+    AstNode::Assignment(var.name, operator, Box::new(value), var.data_type)
+}
+
 // CAN RETURN:
 // VarDeclaration, Const, Error, Function, Tuple
 pub fn new_variable(
@@ -76,7 +232,7 @@ pub fn new_variable(
     is_exported: bool,
     ast: &Vec<AstNode>,
     token_line_numbers: &Vec<u32>,
-    variable_declarations: &mut Vec<Reference>,
+    variable_declarations: &mut ScopeStack,
     is_const: bool,
 ) -> AstNode {
     *i += 1;
@@ -101,7 +257,10 @@ pub fn new_variable(
 
         // Has a type declaration
         &Token::TypeKeyword(ref type_keyword) => {
-            data_type = type_keyword.to_owned();
+            data_type = match parse_union(type_keyword.to_owned(), tokens, i) {
+                Ok(data_type) => data_type,
+                Err(err) => return AstNode::Error(err.to_string(), token_line_numbers[*i]),
+            };
             *i += 1;
 
             match &tokens[*i] {
@@ -109,7 +268,7 @@ pub fn new_variable(
                 // If this is the end of the assignment, it is an uninitalised variable
                 // Currently just creates a zero value variable, should be uninitialised in future
                 &Token::Newline | &Token::EOF => {
-                    variable_declarations.push(Reference {
+                    variable_declarations.declare(Reference {
                         name: name.to_owned(),
                         data_type: data_type.to_owned(),
                         default_value: None,
@@ -133,6 +292,18 @@ pub fn new_variable(
             }
         }
 
+        // Channel declaration
+        &Token::Receive => {
+            return create_channel(
+                name,
+                tokens,
+                i,
+                is_exported,
+                token_line_numbers,
+                variable_declarations,
+            );
+        }
+
         // TO DO: Multiple assignments
         // &Token::Comma => {
         // }
@@ -162,11 +333,17 @@ pub fn new_variable(
             }
 
             // Dynamic Collection literal
-            let start_line_number = &token_line_numbers[*i];
-            let collection = new_collection(tokens, i, ast, start_line_number, &mut data_type);
+            let collection = new_collection(
+                tokens,
+                i,
+                ast,
+                token_line_numbers,
+                &mut data_type,
+                variable_declarations,
+            );
             match collection {
                 AstNode::Collection(..) => {
-                    variable_declarations.push(Reference {
+                    variable_declarations.declare(Reference {
                         name: name.to_owned(),
                         data_type: data_type.to_owned(),
                         default_value: None,
@@ -190,16 +367,15 @@ pub fn new_variable(
 
         // create_expression will automatically handle tuples
         _ => {
-            let start_line_number = &token_line_numbers[*i];
             parsed_expr = create_expression(
                 tokens,
                 i,
                 false,
                 &ast,
-                start_line_number,
+                token_line_numbers,
                 &mut data_type,
                 false,
-                &variable_declarations,
+                variable_declarations,
             );
         }
     }
@@ -208,6 +384,19 @@ pub fn new_variable(
     // Or whether it is a literal or expression
     // If the expression is an empty expression when the variable is NOT a function, return an error
     match parsed_expr {
+        // Unions keep their declared type, whatever type the value is
+        _ if matches!(data_type, DataType::Union(_))
+            && !matches!(parsed_expr, AstNode::Error(..)) =>
+        {
+            create_var_node(
+                is_const,
+                name.to_string(),
+                parsed_expr,
+                is_exported,
+                data_type,
+                variable_declarations,
+            )
+        }
         AstNode::RuntimeExpression(_, ref evaluated_type) => {
             return create_var_node(
                 is_const,
@@ -245,6 +434,57 @@ pub fn new_variable(
                 variable_declarations,
             );
         }
+        AstNode::FunctionCall(_, _, ref return_type) => {
+            let data_type = return_type.to_owned();
+            create_var_node(
+                is_const,
+                name.to_string(),
+                parsed_expr,
+                is_exported,
+                data_type,
+                variable_declarations,
+            )
+        }
+
+        // Function values
+        AstNode::AnonymousFunction(..) | AstNode::VarReference(_, DataType::Function(..)) => {
+            let data_type = parsed_expr.get_type();
+            create_var_node(
+                is_const,
+                name.to_string(),
+                parsed_expr,
+                is_exported,
+                data_type,
+                variable_declarations,
+            )
+        }
+        // References to other variables, collections are moved unless they are copied
+        AstNode::VarReference(..) | AstNode::ConstReference(..) | AstNode::Copy(..) => {
+            let line_number = token_line_numbers[*i - 1];
+            if let Err(err) = move_if_owned(&parsed_expr, line_number, variable_declarations) {
+                return AstNode::Error(err, line_number);
+            }
+            let data_type = parsed_expr.get_type();
+            create_var_node(
+                is_const,
+                name.to_string(),
+                parsed_expr,
+                is_exported,
+                data_type,
+                variable_declarations,
+            )
+        }
+        AstNode::ChannelReceive(_, ref inner_type) => {
+            let data_type = inner_type.to_owned();
+            create_var_node(
+                is_const,
+                name.to_string(),
+                parsed_expr,
+                is_exported,
+                data_type,
+                variable_declarations,
+            )
+        }
         AstNode::Scene(..) => {
             return create_var_node(
                 is_const,
@@ -281,9 +521,9 @@ fn create_var_node(
     var_value: AstNode,
     is_exported: bool,
     data_type: DataType,
-    variable_declarations: &mut Vec<Reference>,
+    variable_declarations: &mut ScopeStack,
 ) -> AstNode {
-    variable_declarations.push(Reference {
+    variable_declarations.declare(Reference {
         name: var_name.to_owned(),
         data_type: data_type.to_owned(),
         default_value: None,
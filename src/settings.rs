@@ -4,6 +4,10 @@ pub const INDEX_PAGE_KEYWORD: &str = "index";
 pub const CONFIG_FILE_NAME: &str = "#config.bs";
 pub const BS_VAR_PREFIX: &str = "bs_";
 
+// Limits for running functions at compile time
+pub const COMPTIME_STEP_LIMIT: usize = 100_000;
+pub const COMPTIME_CALL_DEPTH_LIMIT: usize = 200;
+
 #[allow(dead_code)]
 pub struct Config {
     pub project: String,
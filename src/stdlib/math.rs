@@ -0,0 +1,310 @@
+use crate::{
+    bs_types::DataType,
+    parsers::ast_nodes::Reference,
+    wasm_output::wasm_module::{Function, Import, Instruction, NumericOp, ValType, WasmModule},
+    Token,
+};
+
+// Functions from the math module are called as math.name(args)
+pub const MATH_MODULE_PREFIX: &str = "math.";
+
+pub struct MathFunction {
+    pub name: &'static str,
+    pub args: &'static [&'static str],
+
+    // Integer functions take and return Ints, everything else uses Floats
+    pub is_int: bool,
+
+    // Name of the function in JS
+    pub js: &'static str,
+    pub wasm: MathWasm,
+}
+
+pub enum MathWasm {
+    Instruction(NumericOp), // A single WASM instruction
+    Import,                 // Imported from the JS Math object
+    Function,               // Defined in the WASM prelude
+}
+
+const MATH_FUNCTIONS: &[MathFunction] = &[
+    MathFunction {
+        name: "sqrt",
+        args: &["x"],
+        is_int: false,
+        js: "Math.sqrt",
+        wasm: MathWasm::Instruction(NumericOp::F64Sqrt),
+    },
+    MathFunction {
+        name: "abs",
+        args: &["x"],
+        is_int: false,
+        js: "Math.abs",
+        wasm: MathWasm::Instruction(NumericOp::F64Abs),
+    },
+    MathFunction {
+        name: "floor",
+        args: &["x"],
+        is_int: false,
+        js: "Math.floor",
+        wasm: MathWasm::Instruction(NumericOp::F64Floor),
+    },
+    MathFunction {
+        name: "ceil",
+        args: &["x"],
+        is_int: false,
+        js: "Math.ceil",
+        wasm: MathWasm::Instruction(NumericOp::F64Ceil),
+    },
+    MathFunction {
+        name: "min",
+        args: &["a", "b"],
+        is_int: false,
+        js: "Math.min",
+        wasm: MathWasm::Instruction(NumericOp::F64Min),
+    },
+    MathFunction {
+        name: "max",
+        args: &["a", "b"],
+        is_int: false,
+        js: "Math.max",
+        wasm: MathWasm::Instruction(NumericOp::F64Max),
+    },
+    // WASM only has round to nearest even, so this uses JS to round halves up
+    MathFunction {
+        name: "round",
+        args: &["x"],
+        is_int: false,
+        js: "Math.round",
+        wasm: MathWasm::Import,
+    },
+    MathFunction {
+        name: "sin",
+        args: &["x"],
+        is_int: false,
+        js: "Math.sin",
+        wasm: MathWasm::Import,
+    },
+    MathFunction {
+        name: "cos",
+        args: &["x"],
+        is_int: false,
+        js: "Math.cos",
+        wasm: MathWasm::Import,
+    },
+    MathFunction {
+        name: "tan",
+        args: &["x"],
+        is_int: false,
+        js: "Math.tan",
+        wasm: MathWasm::Import,
+    },
+    MathFunction {
+        name: "log",
+        args: &["x"],
+        is_int: false,
+        js: "Math.log",
+        wasm: MathWasm::Import,
+    },
+    MathFunction {
+        name: "expo",
+        args: &["x"],
+        is_int: false,
+        js: "Math.exp",
+        wasm: MathWasm::Import,
+    },
+    MathFunction {
+        name: "rng",
+        args: &[],
+        is_int: false,
+        js: "Math.random",
+        wasm: MathWasm::Import,
+    },
+    MathFunction {
+        name: "gcd",
+        args: &["a", "b"],
+        is_int: true,
+        js: "wsx.math_gcd",
+        wasm: MathWasm::Function,
+    },
+];
+
+pub fn get_math_function(name: &str) -> Option<&'static MathFunction> {
+    MATH_FUNCTIONS.iter().find(|function| function.name == name)
+}
+
+// Finds the math function a call is for from the name of the call
+pub fn math_function_from_call(call_name: &str) -> Option<&'static MathFunction> {
+    call_name
+        .strip_prefix(MATH_MODULE_PREFIX)
+        .and_then(get_math_function)
+}
+
+impl MathFunction {
+    fn number_type(&self) -> DataType {
+        if self.is_int {
+            DataType::Int
+        } else {
+            DataType::Float
+        }
+    }
+
+    pub fn call_name(&self) -> String {
+        format!("{MATH_MODULE_PREFIX}{}", self.name)
+    }
+
+    pub fn argument_refs(&self) -> Vec<Reference> {
+        self.args
+            .iter()
+            .map(|arg| Reference {
+                name: arg.to_string(),
+                data_type: self.number_type(),
+                default_value: None,
+            })
+            .collect()
+    }
+
+    pub fn return_type(&self) -> DataType {
+        self.number_type()
+    }
+
+    pub fn reference(&self) -> Reference {
+        Reference {
+            name: self.call_name(),
+            data_type: DataType::Function(
+                Box::new(self.argument_refs()),
+                Box::new(self.return_type()),
+            ),
+            default_value: None,
+        }
+    }
+
+    // Works out the result at compile time
+    // Returns None for functions that can only run at runtime
+    pub fn fold(&self, args: &[Token]) -> Option<Token> {
+        if self.is_int {
+            let ints: Vec<i64> = args
+                .iter()
+                .map(|arg| match arg {
+                    Token::IntLiteral(value) => Some(*value),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?;
+
+            return match (self.name, ints.as_slice()) {
+                ("gcd", [a, b]) => Some(Token::IntLiteral(gcd(*a, *b))),
+                _ => None,
+            };
+        }
+
+        let floats: Vec<f64> = args
+            .iter()
+            .map(|arg| match arg {
+                Token::FloatLiteral(value) => Some(*value),
+                Token::IntLiteral(value) => Some(*value as f64),
+                _ => None,
+            })
+            .collect::<Option<_>>()?;
+
+        let result = match (self.name, floats.as_slice()) {
+            ("sqrt", [x]) => x.sqrt(),
+            ("abs", [x]) => x.abs(),
+            ("floor", [x]) => x.floor(),
+            ("ceil", [x]) => x.ceil(),
+            ("min", [a, b]) => a.min(*b),
+            ("max", [a, b]) => a.max(*b),
+            ("round", [x]) => (x + 0.5).floor(),
+            ("sin", [x]) => x.sin(),
+            ("cos", [x]) => x.cos(),
+            ("tan", [x]) => x.tan(),
+            ("log", [x]) => x.ln(),
+            ("expo", [x]) => x.exp(),
+            _ => return None,
+        };
+
+        Some(Token::FloatLiteral(result))
+    }
+
+    // Arguments should already be instructions that leave their values on the stack
+    pub fn call_to_wasm(&self, args: Vec<Instruction>) -> Vec<Instruction> {
+        let mut wasm = args;
+        match self.wasm {
+            MathWasm::Instruction(op) => wasm.push(Instruction::Numeric(op)),
+            MathWasm::Import | MathWasm::Function => {
+                wasm.push(Instruction::Call(format!("math_{}", self.name)))
+            }
+        }
+        wasm
+    }
+}
+
+// Imports and helper functions the math module needs in the WASM module
+// Helper functions are only added if the module or the JS of the page calls them
+pub fn math_wasm_prelude(wasm: &WasmModule, js: &str) -> WasmModule {
+    let mut module = WasmModule::new();
+
+    for function in MATH_FUNCTIONS {
+        if let MathWasm::Import = function.wasm {
+            module.imports.push(Import {
+                module: "Math".to_string(),
+                field: function.js.trim_start_matches("Math.").to_string(),
+                name: format!("math_{}", function.name),
+                params: vec![ValType::F64; function.args.len()],
+                result: Some(ValType::F64),
+            });
+        }
+    }
+
+    if !wasm.calls("math_gcd") && !js.contains("wsx.math_gcd") {
+        return module;
+    }
+
+    let get = |name: &str| Instruction::LocalGet(name.to_string());
+    let set = |name: &str| Instruction::LocalSet(name.to_string());
+    module.functions.push(Function {
+        name: "math_gcd".to_string(),
+        export: Some("math_gcd".to_string()),
+        params: vec![
+            ("a".to_string(), ValType::I64),
+            ("b".to_string(), ValType::I64),
+        ],
+        result: Some(ValType::I64),
+        locals: vec![("t".to_string(), ValType::I64)],
+        body: vec![
+            Instruction::Block("done".to_string()),
+            Instruction::Loop("next".to_string()),
+            get("b"),
+            Instruction::Numeric(NumericOp::I64Eqz),
+            Instruction::BrIf("done".to_string()),
+            get("a"),
+            get("b"),
+            Instruction::Numeric(NumericOp::I64RemS),
+            set("t"),
+            get("b"),
+            set("a"),
+            get("t"),
+            set("b"),
+            Instruction::Br("next".to_string()),
+            Instruction::End,
+            Instruction::End,
+            // Absolute value of a
+            get("a"),
+            Instruction::I64Const(0),
+            get("a"),
+            Instruction::Numeric(NumericOp::I64Sub),
+            get("a"),
+            Instruction::I64Const(0),
+            Instruction::Numeric(NumericOp::I64GeS),
+            Instruction::Select,
+        ],
+    });
+
+    module
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    let (mut a, mut b) = (a, b);
+    while b != 0 {
+        (a, b) = (b, a.wrapping_rem(b));
+    }
+    a.wrapping_abs()
+}
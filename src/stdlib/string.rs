@@ -0,0 +1,241 @@
+use crate::{
+    bs_types::DataType,
+    parsers::ast_nodes::{AstNode, Reference},
+    Token,
+};
+
+// Functions for strings are called from the String type keyword, as String.name(args)
+pub const STRING_MODULE_PREFIX: &str = "String.";
+
+#[derive(Clone, Copy)]
+pub enum StringArg {
+    String,
+    Int,
+    Float,
+    Bool,
+    Strings, // Collection of strings
+}
+
+pub struct StringFunction {
+    pub name: &'static str,
+    pub args: &'static [(&'static str, StringArg)],
+    pub returns: StringArg,
+
+    // JS for the call, {0}, {1}... are replaced with the arguments
+    pub js: &'static str,
+}
+
+const STRING_FUNCTIONS: &[StringFunction] = &[
+    StringFunction {
+        name: "length",
+        args: &[("text", StringArg::String)],
+        returns: StringArg::Int,
+        js: "BigInt({0}.length)",
+    },
+    StringFunction {
+        name: "slice",
+        args: &[
+            ("text", StringArg::String),
+            ("start", StringArg::Int),
+            ("end", StringArg::Int),
+        ],
+        returns: StringArg::String,
+        js: "{0}.slice(Number({1}),Number({2}))",
+    },
+    StringFunction {
+        name: "split",
+        args: &[
+            ("text", StringArg::String),
+            ("separator", StringArg::String),
+        ],
+        returns: StringArg::Strings,
+        js: "{0}.split({1})",
+    },
+    StringFunction {
+        name: "contains",
+        args: &[("text", StringArg::String), ("part", StringArg::String)],
+        returns: StringArg::Bool,
+        js: "{0}.includes({1})",
+    },
+    StringFunction {
+        name: "replace",
+        args: &[
+            ("text", StringArg::String),
+            ("from", StringArg::String),
+            ("to", StringArg::String),
+        ],
+        returns: StringArg::String,
+        js: "{0}.replaceAll({1},{2})",
+    },
+    StringFunction {
+        name: "upper",
+        args: &[("text", StringArg::String)],
+        returns: StringArg::String,
+        js: "{0}.toUpperCase()",
+    },
+    StringFunction {
+        name: "lower",
+        args: &[("text", StringArg::String)],
+        returns: StringArg::String,
+        js: "{0}.toLowerCase()",
+    },
+    StringFunction {
+        name: "trim",
+        args: &[("text", StringArg::String)],
+        returns: StringArg::String,
+        js: "{0}.trim()",
+    },
+    StringFunction {
+        name: "format",
+        args: &[("number", StringArg::Float), ("precision", StringArg::Int)],
+        returns: StringArg::String,
+        js: "({0}).toFixed(Number({1}))",
+    },
+];
+
+pub fn get_string_function(name: &str) -> Option<&'static StringFunction> {
+    STRING_FUNCTIONS
+        .iter()
+        .find(|function| function.name == name)
+}
+
+// Finds the string function a call is for from the name of the call
+pub fn string_function_from_call(call_name: &str) -> Option<&'static StringFunction> {
+    call_name
+        .strip_prefix(STRING_MODULE_PREFIX)
+        .and_then(get_string_function)
+}
+
+impl StringArg {
+    fn data_type(self) -> DataType {
+        match self {
+            StringArg::String => DataType::String,
+            StringArg::Int => DataType::Int,
+            StringArg::Float => DataType::Float,
+            StringArg::Bool => DataType::Bool,
+            StringArg::Strings => DataType::Collection(Box::new(DataType::String)),
+        }
+    }
+}
+
+impl StringFunction {
+    pub fn call_name(&self) -> String {
+        format!("{STRING_MODULE_PREFIX}{}", self.name)
+    }
+
+    pub fn reference(&self) -> Reference {
+        let args = self
+            .args
+            .iter()
+            .map(|(name, arg_type)| Reference {
+                name: name.to_string(),
+                data_type: arg_type.data_type(),
+                default_value: None,
+            })
+            .collect();
+
+        Reference {
+            name: self.call_name(),
+            data_type: DataType::Function(Box::new(args), Box::new(self.returns.data_type())),
+            default_value: None,
+        }
+    }
+
+    pub fn call_to_js(&self, args: &[String]) -> String {
+        let mut js = self.js.to_string();
+        for (index, arg) in args.iter().enumerate() {
+            // Expressions are wrapped so methods are called on the whole value
+            let arg = arg.trim();
+            let arg = if arg.contains(' ') {
+                format!("({arg})")
+            } else {
+                arg.to_string()
+            };
+            js = js.replace(&format!("{{{index}}}"), &arg);
+        }
+        js
+    }
+
+    // Arguments that are known at compile time and would throw an error in JS
+    pub fn check_args(&self, args: &AstNode) -> Result<(), String> {
+        match (self.name, args) {
+            ("format", AstNode::Tuple(args, _)) => match args.get(1) {
+                Some(AstNode::Literal(Token::IntLiteral(precision)))
+                    if !(0..=100).contains(precision) =>
+                {
+                    Err(format!(
+                        "The precision of '{}' must be from 0 to 100, not {precision}",
+                        self.call_name()
+                    ))
+                }
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    // Works out the result at compile time, the same way JS would
+    // Returns None if the result can only be worked out at runtime
+    pub fn fold(&self, args: &[Token]) -> Option<Token> {
+        let result = match (self.name, args) {
+            ("length", [Token::StringLiteral(text)]) => {
+                Token::IntLiteral(text.encode_utf16().count() as i64)
+            }
+            (
+                "slice",
+                [Token::StringLiteral(text), Token::IntLiteral(start), Token::IntLiteral(end)],
+            ) => Token::StringLiteral(js_slice(text, *start, *end)),
+            ("contains", [Token::StringLiteral(text), Token::StringLiteral(part)]) => {
+                Token::BoolLiteral(text.contains(part.as_str()))
+            }
+            (
+                "replace",
+                [Token::StringLiteral(text), Token::StringLiteral(from), Token::StringLiteral(to)],
+            ) => Token::StringLiteral(text.replace(from.as_str(), to)),
+            ("upper", [Token::StringLiteral(text)]) => Token::StringLiteral(text.to_uppercase()),
+            ("lower", [Token::StringLiteral(text)]) => Token::StringLiteral(text.to_lowercase()),
+            ("trim", [Token::StringLiteral(text)]) => Token::StringLiteral(text.trim().to_string()),
+            ("format", [Token::FloatLiteral(number), Token::IntLiteral(precision)]) => {
+                Token::StringLiteral(to_fixed(*number, *precision)?)
+            }
+
+            // Collections can't be created at compile time yet
+            _ => return None,
+        };
+
+        Some(result)
+    }
+}
+
+// Negative indexes count back from the end, and indexes past the end are clamped
+fn js_slice(text: &str, start: i64, end: i64) -> String {
+    let units: Vec<u16> = text.encode_utf16().collect();
+    let length = units.len() as i64;
+    let clamp = |index: i64| {
+        if index < 0 {
+            (length + index).max(0)
+        } else {
+            index.min(length)
+        }
+    };
+
+    let (start, end) = (clamp(start), clamp(end));
+    if start >= end {
+        return String::new();
+    }
+    String::from_utf16_lossy(&units[start as usize..end as usize])
+}
+
+// JS rounds halfway values up when formatting, so those are left for runtime
+fn to_fixed(number: f64, precision: i64) -> Option<String> {
+    if !(0..=100).contains(&precision) || !number.is_finite() {
+        return None;
+    }
+
+    let scaled = number * 10f64.powi(precision as i32);
+    if (scaled - scaled.trunc()).abs() == 0.5 {
+        return None;
+    }
+
+    Some(format!("{:.*}", precision as usize, number))
+}
@@ -4,8 +4,12 @@ use colour::{blue_ln_bold, dark_grey_ln, dark_yellow_ln, green_ln_bold, yellow_l
 use crate::bs_types::DataType;
 use crate::html_output::web_parser;
 use crate::parsers::ast_nodes::AstNode;
+use crate::parsers::scope::ScopeStack;
 use crate::settings::get_html_config;
 use crate::tokenizer;
+use crate::wasm_output::js_glue::new_js_glue;
+use crate::wasm_output::wasm_validator::validate_module;
+use crate::wasm_output::wat_parser::new_page_module;
 use crate::Token;
 use crate::{dev_server, parsers};
 use std::error::Error;
@@ -54,7 +58,7 @@ pub fn test_build() -> Result<(), Box<dyn Error>> {
         tokens,
         &mut 0,
         &token_line_numbers,
-        Vec::new(),
+        &mut ScopeStack::new(Vec::new()),
         &DataType::None,
         true,
     );
@@ -73,7 +77,7 @@ pub fn test_build() -> Result<(), Box<dyn Error>> {
             AstNode::Literal(_) => {
                 cyan_ln!("{:?}", node);
             }
-            AstNode::Comment(_) => {
+            AstNode::Comment(_) | AstNode::DocComment(_) => {
                 grey_ln!("{:?}", node);
             }
             _ => {
@@ -89,6 +93,7 @@ pub fn test_build() -> Result<(), Box<dyn Error>> {
         false,
         "test",
         false,
+        None,
         &String::new(),
     ) {
         Ok(output) => output,
@@ -102,12 +107,20 @@ pub fn test_build() -> Result<(), Box<dyn Error>> {
         println!("{:?}", export.module_path);
     }
     println!("CSS EXPORTS: {}", parser_output.exported_css);
+    println!(
+        "JS GLUE: {}",
+        new_js_glue(&parser_output.wasm_exports, "test")
+    );
 
-    let all_parsed_wasm = &format!(
-        "(module {}(func (export \"set_wasm_globals\"){}))",
-        &parser_output.wat, parser_output.wat_globals
+    let wasm_module = new_page_module(
+        parser_output.wasm,
+        parser_output.wasm_globals,
+        &parser_output.js,
     );
-    println!("WAT: {}", all_parsed_wasm);
+    println!("WAT: {}", wasm_module.to_wat());
+    if let Err(e) = validate_module(&wasm_module) {
+        red_ln!("Invalid WASM module: {}", e);
+    }
 
     /*
 
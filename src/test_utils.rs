@@ -0,0 +1,88 @@
+// Compiles Beanstalk source for the tests and runs the output with Node
+use crate::bs_types::DataType;
+use crate::build::{build, Target};
+use crate::parsers;
+use crate::parsers::dead_code::remove_dead_code;
+use crate::parsers::scope::ScopeStack;
+use crate::tokenizer;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Runs the page script through its JS glue, printing each element the page updates
+const PAGE_RUNNER: &str = r#"
+const fs = require('fs');
+const path = require('path');
+const { pathToFileURL } = require('url');
+const f = path.resolve(process.argv[2]);
+const html = fs.readFileSync(f, 'utf8');
+let body = html.match(/<script type="module">([\s\S]*?)<\/script>/)[1]
+    .replace(/function uInnerHTML\(id,update\)\{[\s\S]*?\n            \}\n/, '');
+const glue = 'import require_fs from "fs";\n' + fs.readFileSync(f.replace(/\.html$/, '.bs.js'), 'utf8').replace(
+    /WebAssembly\.instantiateStreaming\(fetch\(new URL\("\.\/(.*?)",import\.meta\.url\)\),/,
+    (_, w) => `WebAssembly.instantiate(require_fs.readFileSync(${JSON.stringify(path.join(path.dirname(f), w))}),`);
+const glueFile = f.replace(/\.html$/, '.node-glue.mjs');
+const pageFile = f.replace(/\.html$/, '.node-page.mjs');
+fs.writeFileSync(glueFile, glue);
+fs.writeFileSync(pageFile, body.replace(/from "\.\/[^"]*\.bs\.js"/, `from ${JSON.stringify(pathToFileURL(glueFile).href)}`));
+globalThis.uInnerHTML = (id, v) => console.log(id, '=', String(v));
+import(pathToFileURL(pageFile).href).catch(e => console.log('ERR', e.message));
+"#;
+
+const WASI_RUNNER: &str = r#"
+const { WASI } = require('node:wasi');
+const fs = require('fs');
+const wasi = new WASI({ version: 'preview1' });
+WebAssembly.instantiate(fs.readFileSync(process.argv[2]), wasi.getImportObject())
+    .then(({ instance }) => wasi.start(instance))
+    .catch(e => console.log('ERR', e.message));
+"#;
+
+// Each test gets its own directory, so they can run at the same time
+fn write_source(name: &str, source: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("beanstalk_tests").join(name);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name).with_extension("bs");
+    fs::write(&path, source).unwrap();
+    path
+}
+
+fn run_node(runner: &str, file: PathBuf) -> String {
+    let runner_path = file.with_extension("runner.js");
+    fs::write(&runner_path, runner).unwrap();
+    let output = Command::new("node")
+        .arg("--no-warnings")
+        .arg(&runner_path)
+        .arg(&file)
+        .output()
+        .expect("Node is needed to run the compiled output");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+// Builds a page and returns what it writes to each element, one "id = value" per line
+pub fn run_page(name: &str, source: &str) -> String {
+    let path = write_source(name, source);
+    build(path.to_string_lossy().into_owned(), true, Target::Web).unwrap();
+    run_node(PAGE_RUNNER, path.with_extension("html"))
+}
+
+// Builds a WASI module and returns what it prints
+pub fn run_wasi(name: &str, source: &str) -> String {
+    let path = write_source(name, source);
+    build(path.to_string_lossy().into_owned(), true, Target::Wasi).unwrap();
+    run_node(WASI_RUNNER, path.with_extension("wasm"))
+}
+
+// Warnings given when the unused code is removed from the module
+pub fn dead_code_warnings(source: &str) -> Vec<String> {
+    let (tokens, token_line_numbers) = tokenizer::tokenize(source, "test");
+    let (ast, imports) = parsers::build_ast::new_ast(
+        tokens,
+        &mut 0,
+        &token_line_numbers,
+        &mut ScopeStack::new(Vec::new()),
+        &DataType::None,
+        true,
+    );
+    remove_dead_code(ast, imports).unwrap().2
+}
@@ -1,5 +1,5 @@
 use super::tokens::{Token, TokenizeMode};
-use crate::bs_types::DataType;
+use crate::bs_types::{number_type, DataType};
 use crate::tokenize_scene::{tokenize_codeblock, tokenize_markdown};
 use std::iter::Peekable;
 use std::str::Chars;
@@ -12,7 +12,6 @@ pub fn tokenize(source_code: &str, module_name: &str) -> (Vec<Token>, Vec<u32>)
     let mut tokenize_mode: TokenizeMode = TokenizeMode::Normal;
     let mut scene_nesting_level: &mut i64 = &mut 0;
 
-    // For variable optimisation
     let mut token: Token = Token::ModuleStart(module_name.to_string());
 
     loop {
@@ -30,19 +29,37 @@ pub fn tokenize(source_code: &str, module_name: &str) -> (Vec<Token>, Vec<u32>)
         );
     }
 
-    // Mark unused variables for removal in AST
-    // DISABLED FOR NOW
-    // for var_dec in var_names.iter() {
-    //     if !var_dec.has_ref && !var_dec.is_exported {
-    //         tokens[var_dec.index] = Token::DeadVarible(var_dec.name.to_string());
-    //     }
-    // }
-
     tokens.push(token);
     token_line_numbers.push(line_number);
     (tokens, token_line_numbers)
 }
 
+// Tokenizes an expression inside of a string up to the closing curly brace
+// Strings inside of the expression can have their own braces
+fn tokenize_string_expression(chars: &mut Peekable<Chars>) -> Vec<Token> {
+    let mut source = String::new();
+    let mut depth = 0;
+    let mut inside_string = false;
+
+    for ch in chars.by_ref() {
+        match ch {
+            '"' => inside_string = !inside_string,
+            '{' if !inside_string => depth += 1,
+            '}' if !inside_string => {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        source.push(ch);
+    }
+
+    // Skip the module start token
+    tokenize(&source, "").0.into_iter().skip(1).collect()
+}
+
 pub fn get_next_token(
     chars: &mut Peekable<Chars>,
     tokenize_mode: &mut TokenizeMode,
@@ -169,18 +186,31 @@ pub fn get_next_token(
     }
 
     // Check for string literals
+    // Expressions inside of curly braces are inserted into the string
     if current_char == '"' {
+        let mut parts = Vec::new();
+        let mut expressions = Vec::new();
         while let Some(ch) = chars.next() {
-            // Check for escape characters
-            if ch == '\\' {
-                if let Some(next_char) = chars.next() {
-                    token_value.push(next_char);
+            match ch {
+                // Check for escape characters
+                '\\' => {
+                    if let Some(next_char) = chars.next() {
+                        token_value.push(next_char);
+                    }
                 }
+                '"' => {
+                    if expressions.is_empty() {
+                        return Token::StringLiteral(token_value);
+                    }
+                    parts.push(token_value);
+                    return Token::StringTemplate(parts, expressions);
+                }
+                '{' => {
+                    parts.push(std::mem::take(&mut token_value));
+                    expressions.push(tokenize_string_expression(chars));
+                }
+                _ => token_value.push(ch),
             }
-            if ch == '"' {
-                return Token::StringLiteral(token_value);
-            }
-            token_value.push(ch);
         }
     }
 
@@ -204,11 +234,19 @@ pub fn get_next_token(
 
     // Context Free Grammars
     if current_char == '=' {
+        // Pipes a value into the first argument of a function
+        if chars.peek() == Some(&'>') {
+            chars.next();
+            return Token::Pipe;
+        }
         return Token::Assign;
     }
     if current_char == ',' {
         return Token::Comma;
     }
+    if current_char == '|' {
+        return Token::Bar;
+    }
     if current_char == '.' {
         return Token::Dot;
     }
@@ -247,6 +285,26 @@ pub fn get_next_token(
             if next_char == '-' {
                 chars.next();
 
+                // Doc comments start with a triple minus and document the declaration after them
+                if chars.peek() == Some(&'-') {
+                    chars.next();
+                    if chars.peek().is_some_and(|ch| *ch != '\n') {
+                        for ch in chars.by_ref() {
+                            if ch == '\n' {
+                                *line_number += 1;
+                                break;
+                            }
+                            token_value.push(ch);
+                        }
+                        return Token::DocComment(token_value.trim().to_string());
+                    }
+                    token_value.push('-');
+                    if chars.next() == Some('\n') {
+                        *line_number += 1;
+                    }
+                    return Token::Comment(token_value);
+                }
+
                 // Check for multiline
                 if let Some(&next_next_char) = chars.peek() {
                     if next_next_char == '\n' {
@@ -371,6 +429,11 @@ pub fn get_next_token(
                 chars.next();
                 return Token::GreaterThanOrEqual;
             }
+            // Sends a value to a channel
+            if next_char == '>' {
+                chars.next();
+                return Token::Send;
+            }
             return Token::GreaterThan;
         }
     }
@@ -380,6 +443,11 @@ pub fn get_next_token(
                 chars.next();
                 return Token::LessThanOrEqual;
             }
+            // Receives a value from a channel
+            if next_char == '<' {
+                chars.next();
+                return Token::Receive;
+            }
             return Token::LessThan;
         }
     }
@@ -390,40 +458,8 @@ pub fn get_next_token(
     }
 
     // Numbers
-    if current_char.is_numeric() {
-        token_value.push(current_char);
-        let mut dot_count = 0;
-
-        while let Some(&next_char) = chars.peek() {
-            if next_char == '_' {
-                chars.next();
-                continue;
-            }
-
-            if next_char == '.' {
-                dot_count += 1;
-                // Stop if too many dots
-                if dot_count > 1 {
-                    return Token::Error(
-                        "Cannot have more than one decimal point in a number".to_string(),
-                        *line_number,
-                    );
-                }
-                token_value.push(chars.next().unwrap());
-                continue;
-            }
-
-            if next_char.is_numeric() {
-                token_value.push(chars.next().unwrap());
-            } else {
-                break;
-            }
-        }
-
-        if dot_count == 0 {
-            return Token::IntLiteral(token_value.parse::<i64>().unwrap());
-        }
-        return Token::FloatLiteral(token_value.parse::<f64>().unwrap());
+    if current_char.is_ascii_digit() {
+        return number_literal(chars, current_char, line_number);
     }
 
     if current_char.is_alphabetic() {
@@ -461,7 +497,8 @@ fn keyword_or_variable(
                 }
                 true
             }
-            None => false,
+            // Names can also end at the end of the source
+            None => !token_value.is_empty(),
         };
 
         // Always check if token value is a keyword in every other case
@@ -482,6 +519,7 @@ fn keyword_or_variable(
             "in" => return Token::In,
             "as" => return Token::As,
             "copy" => return Token::Copy,
+            "comptime" => return Token::Comptime,
 
             // Logical
             "is" => return Token::Equal,
@@ -498,6 +536,7 @@ fn keyword_or_variable(
             "String" => return Token::TypeKeyword(DataType::String),
             "Bool" => return Token::TypeKeyword(DataType::Bool),
             "type" | "Type" => return Token::TypeKeyword(DataType::Type),
+            "Number" => return Token::TypeKeyword(number_type()),
 
             // To be moved to standard library in future
             "print" => return Token::Print,
@@ -627,6 +666,126 @@ fn keyword_or_variable(
     );
 }
 
+/*
+    Number literals
+    - Underscores can be used to separate digits: 1_000_000
+    - Integers can be written in hex, binary or octal: 0xff, 0b1010, 0o17
+    - Floats can have an exponent: 1.5e-3, 2e10
+*/
+fn number_literal(chars: &mut Peekable<Chars>, first_digit: char, line_number: &u32) -> Token {
+    let radix = match (first_digit, chars.peek()) {
+        ('0', Some('x' | 'X')) => 16,
+        ('0', Some('b' | 'B')) => 2,
+        ('0', Some('o' | 'O')) => 8,
+        _ => 10,
+    };
+
+    if radix != 10 {
+        let prefix = chars.next().unwrap_or_default();
+        let mut digits = String::new();
+        while let Some(&next_char) = chars.peek() {
+            if next_char == '_' {
+                chars.next();
+                continue;
+            }
+            if !next_char.is_alphanumeric() {
+                break;
+            }
+            if !next_char.is_digit(radix) {
+                return Token::Error(
+                    format!("'{next_char}' is not a valid digit in a 0{prefix} number"),
+                    *line_number,
+                );
+            }
+            digits.push(chars.next().unwrap());
+        }
+
+        if digits.is_empty() {
+            return Token::Error(format!("Expected digits after '0{prefix}'"), *line_number);
+        }
+
+        return match i64::from_str_radix(&digits, radix) {
+            Ok(int) => Token::IntLiteral(int),
+            Err(_) => Token::Error(
+                format!("0{prefix}{digits} is too large to fit in an Int"),
+                *line_number,
+            ),
+        };
+    }
+
+    let mut token_value = String::from(first_digit);
+    let mut dot_count = 0;
+    let mut has_exponent = false;
+
+    while let Some(&next_char) = chars.peek() {
+        if next_char == '_' {
+            chars.next();
+            continue;
+        }
+
+        if next_char == '.' {
+            dot_count += 1;
+            // Stop if too many dots
+            if dot_count > 1 {
+                return Token::Error(
+                    "Cannot have more than one decimal point in a number".to_string(),
+                    *line_number,
+                );
+            }
+            if has_exponent {
+                return Token::Error(
+                    "The exponent of a number can't have a decimal point".to_string(),
+                    *line_number,
+                );
+            }
+            token_value.push(chars.next().unwrap());
+            continue;
+        }
+
+        // Scientific notation, the exponent can be negative
+        if (next_char == 'e' || next_char == 'E') && !has_exponent {
+            has_exponent = true;
+            token_value.push(chars.next().unwrap());
+            if let Some(&sign) = chars.peek() {
+                if sign == '-' || sign == '+' {
+                    token_value.push(chars.next().unwrap());
+                }
+            }
+            if !chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+                return Token::Error(
+                    format!("Expected digits after the exponent in {token_value}"),
+                    *line_number,
+                );
+            }
+            continue;
+        }
+
+        if next_char.is_ascii_digit() {
+            token_value.push(chars.next().unwrap());
+        } else {
+            break;
+        }
+    }
+
+    if dot_count == 0 && !has_exponent {
+        return match token_value.parse::<i64>() {
+            Ok(int) => Token::IntLiteral(int),
+            Err(_) => Token::Error(
+                format!("{token_value} is too large to fit in an Int, use a Float instead"),
+                *line_number,
+            ),
+        };
+    }
+
+    match token_value.parse::<f64>() {
+        Ok(float) if float.is_finite() => Token::FloatLiteral(float),
+        _ => Token::Error(
+            format!("{token_value} is too large to fit in a Float"),
+            *line_number,
+        ),
+    }
+}
+
 // Checking if the variable name is valid
 fn is_valid_identifier(s: &str) -> bool {
     // Check if the string is a valid identifier (variable name)
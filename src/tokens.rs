@@ -15,7 +15,6 @@ pub enum Token {
     ModuleStart(String),
     Comptime,
     Error(String, u32),  // Error message, line number
-    DeadVarible(String), // Name. Variable that is never used, to be removed in the AST
     EOF,                 // End of file
 
     // Module Import/Export
@@ -43,10 +42,14 @@ pub enum Token {
 
     // Variables / Functions
     Arrow,
+    Pipe,    // =>
+    Send,    // >>
+    Receive, // <<
     Variable(String),
 
     // Literals
     StringLiteral(String),
+    StringTemplate(Vec<String>, Vec<Vec<Token>>), // Parts of the string, tokens of the expressions between them
     PathLiteral(String),
     FloatLiteral(f64),
     IntLiteral(i64),
@@ -68,6 +71,7 @@ pub enum Token {
     Dot,
     Colon,  // :
     Assign, // =
+    Bar,    // | (between the types of a union)
 
     // Scope
     OpenParenthesis,  // (
@@ -0,0 +1,154 @@
+use crate::{bs_types::DataType, settings::BS_VAR_PREFIX};
+
+/*
+    Every page gets a JS module next to its WASM module that loads it and wraps its exports.
+    The page imports it, and any other JS can import it to use the page's Beanstalk variables and functions
+    without knowing how they are stored in WASM.
+
+    import { ready, bs } from "./page.bs.js";
+    await ready;
+    bs.count += 1n;
+
+    Changing a variable calls every update watching it, which is how scenes showing the variable are re-rendered.
+*/
+
+// A Beanstalk variable or function the page module exports
+pub struct WasmExport {
+    pub name: String,
+    pub data_type: DataType,
+    pub mutable: bool,
+}
+
+const GLUE_JS: &str = "let wsx;
+const bsDecoder=new TextDecoder();const bsEncoder=new TextEncoder();
+const bsWatchers={};
+
+// Resolves with the exports of the WASM module once it has loaded and set its globals
+// WASM passes the name of a variable to bs.changed when a setter changes it
+const imports={Math,bs:{changed:(ptr)=>bs_changed(readString(ptr))}};
+export const ready=WebAssembly.instantiateStreaming(fetch(new URL(\"./wasm-module-name.wasm\",import.meta.url)),imports).then((obj)=>{
+    wsx=obj.instance.exports;wsx.set_wasm_globals();return wsx
+});
+
+// Strings are a 4 byte length followed by the UTF-8 bytes, 0 is an empty string
+function readString(ptr){
+    if(ptr===0){return \"\"}
+    const len=new DataView(wsx.memory.buffer).getUint32(ptr,true);
+    return bsDecoder.decode(new Uint8Array(wsx.memory.buffer,ptr+4,len))
+}
+function writeString(value){
+    const bytes=bsEncoder.encode(String(value));
+    const ptr=wsx.alloc(bytes.length+4);
+    new DataView(wsx.memory.buffer).setUint32(ptr,bytes.length,true);
+    new Uint8Array(wsx.memory.buffer,ptr+4,bytes.length).set(bytes);
+    return ptr
+}
+
+// Runs the update now, then again every time the variable changes
+export function bs_render(name,update){(bsWatchers[name]??=[]).push(update);update()}
+export function bs_changed(name){for(const update of bsWatchers[name]??[]){update()}}
+
+// Lets the page use a string in WASM memory as if it was a normal JS variable
+export function bs_wasm_string(name){Object.defineProperty(globalThis,name,{configurable:true,
+    get(){return readString(wsx[name].value)},
+    set(value){wsx[name].value=writeString(value)}
+})}
+";
+
+// Imports the glue into the page's script
+pub fn glue_import(file_name: &str) -> String {
+    format!(
+        "import {{ready, bs, bs_wasm_string, bs_render, bs_changed}} from \"./{file_name}.bs.js\";"
+    )
+}
+
+pub fn new_js_glue(exports: &[WasmExport], file_name: &str) -> String {
+    let mut js = format!(
+        "// Generated by Beanstalk for {file_name}.wasm\n{}",
+        GLUE_JS.replace("wasm-module-name", file_name)
+    );
+
+    // Variables are read straight from their exported global, so WASM getters are only kept if the page uses them
+    js.push_str("\nexport const bs={\n");
+    for export in exports {
+        let name = &export.name;
+        let wasm_name = format!("{BS_VAR_PREFIX}{name}");
+
+        match &export.data_type {
+            DataType::Float | DataType::Int => {
+                js.push_str(&format!(
+                    "    /** @type {{{}}} */\n    get {name}(){{return wsx.{wasm_name}.value}},\n",
+                    js_type(&export.data_type)
+                ));
+                if export.mutable {
+                    js.push_str(&format!(
+                        "    set {name}(value){{wsx.set_{wasm_name}(value)}},\n"
+                    ));
+                }
+            }
+            DataType::String | DataType::Scene => {
+                js.push_str(&format!(
+                    "    /** @type {{string}} */\n    get {name}(){{return readString(wsx.{wasm_name}.value)}},\n"
+                ));
+                if export.mutable {
+                    js.push_str(&format!(
+                        "    set {name}(value){{wsx.set_{wasm_name}(writeString(value))}},\n"
+                    ));
+                }
+            }
+
+            // Only the number items of a tuple are stored in WASM
+            DataType::Tuple(data_types) => {
+                let items: Vec<(usize, &DataType)> = data_types
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, data_type)| matches!(data_type, DataType::Float | DataType::Int))
+                    .collect();
+                let item_types = items
+                    .iter()
+                    .map(|(index, data_type)| format!("{index}: {}", js_type(data_type)))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                let item_values = items
+                    .iter()
+                    .map(|(index, _)| format!("{index}: wsx.{wasm_name}_{index}.value"))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                js.push_str(&format!(
+                    "    /** @type {{{{{item_types}}}}} */\n    get {name}(){{return {{{item_values}}}}},\n"
+                ));
+            }
+
+            DataType::Function(args, return_type) => {
+                let arg_names = args
+                    .iter()
+                    .map(|arg| arg.name.to_owned())
+                    .collect::<Vec<String>>()
+                    .join(",");
+                let arg_docs: String = args
+                    .iter()
+                    .map(|arg| format!(" @param {{{}}} {}", js_type(&arg.data_type), arg.name))
+                    .collect();
+                js.push_str(&format!(
+                    "    /**{arg_docs} @returns {{{}}} */\n    {name}({arg_names}){{return wsx.{wasm_name}({arg_names})}},\n",
+                    js_type(return_type)
+                ));
+            }
+
+            _ => {}
+        }
+    }
+    js.push_str("};\n");
+
+    js
+}
+
+// Ints are 64 bit in WASM, so they are BigInts in JS
+fn js_type(data_type: &DataType) -> &'static str {
+    match data_type {
+        DataType::Float => "number",
+        DataType::Int => "bigint",
+        DataType::String | DataType::Scene => "string",
+        _ => "any",
+    }
+}
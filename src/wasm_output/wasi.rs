@@ -0,0 +1,792 @@
+use crate::{
+    bs_types::DataType,
+    parsers::{
+        ast_nodes::{AstNode, Node},
+        generics::{is_generic, monomorphise},
+    },
+    settings::BS_VAR_PREFIX,
+    wasm_output::{
+        wasm_module::{DataSegment, Function, Import, Instruction, NumericOp, ValType, WasmModule},
+        wat_parser::{
+            assignment_to_wat, expression_to_wat, is_wat_expression, new_base_module,
+            new_wasm_string, new_wat_function, new_wat_var, string_bytes, wat_value_type,
+        },
+    },
+    Token,
+};
+
+/*
+    Compiles a Beanstalk program into a module that runs on its own under a WASI runtime instead of in a page.
+    Top level code becomes the _start function WASI runtimes call, and print writes to stdout with fd_write.
+    There is no JS, so only code that can run entirely in WASM can be used.
+*/
+
+const STDOUT: i32 = 1;
+
+pub fn new_wasi_module(ast: Vec<AstNode>) -> Result<WasmModule, String> {
+    let mut wasm = WasmModule::new();
+    let mut start = Vec::new();
+    let mut line_number = 0;
+
+    // Number variables and functions that can be used from WASM
+    let mut globals: Vec<String> = Vec::new();
+    let mut functions: Vec<String> = Vec::new();
+
+    for node in monomorphise(ast) {
+        match node {
+            AstNode::LineNumber(line) => {
+                line_number = line;
+            }
+
+            AstNode::VarDeclaration(id, expr, _, data_type, _) => match data_type {
+                DataType::Float | DataType::Int | DataType::Bool => {
+                    if !is_wat_expression(&expr, &[], &globals, &functions) {
+                        return Err(unsupported(line_number, "This value"));
+                    }
+                    new_wat_var(&id, &expr, &data_type, line_number, &mut wasm, &mut start)?;
+                    globals.push(id);
+                }
+                DataType::String => match *expr {
+                    // Constant strings go straight into the data section
+                    AstNode::Literal(Token::StringLiteral(value)) => {
+                        new_wasm_string(&id, Some(&value), &mut wasm);
+                    }
+                    _ => {
+                        new_wasm_string(&id, None, &mut wasm);
+                        start.push(Instruction::SourceLine(line_number));
+                        start.extend(string_to_wasm(
+                            &expr,
+                            &mut wasm,
+                            line_number,
+                            &globals,
+                            &functions,
+                        )?);
+                        start.push(Instruction::GlobalSet(format!("{BS_VAR_PREFIX}{id}")));
+                    }
+                },
+                _ => {
+                    return Err(unsupported(
+                        line_number,
+                        &format!("{:?} variables", data_type),
+                    ));
+                }
+            },
+
+            AstNode::Assignment(target, operator, value, data_type) => {
+                let set = Instruction::GlobalSet(format!("{BS_VAR_PREFIX}{target}"));
+                start.push(Instruction::SourceLine(line_number));
+                match (&data_type, wat_value_type(&data_type)) {
+                    (_, Some(_)) if is_wat_expression(&value, &[], &globals, &functions) => {
+                        match assignment_to_wat(
+                            &target,
+                            &operator,
+                            &value,
+                            &data_type,
+                            set,
+                            &[],
+                            line_number,
+                        )? {
+                            Some(instructions) => start.extend(instructions),
+                            None => return Err(unsupported(line_number, "This assignment")),
+                        }
+                    }
+                    (DataType::String, _) => {
+                        if operator == Token::AddAssign {
+                            start.push(Instruction::GlobalGet(format!("{BS_VAR_PREFIX}{target}")));
+                        }
+                        start.extend(string_to_wasm(
+                            &value,
+                            &mut wasm,
+                            line_number,
+                            &globals,
+                            &functions,
+                        )?);
+                        if operator == Token::AddAssign {
+                            start.push(Instruction::Call("string_concat".to_string()));
+                        }
+                        start.push(set);
+                    }
+                    _ => return Err(unsupported(line_number, "This assignment")),
+                }
+            }
+
+            AstNode::Print(expr) => {
+                start.push(Instruction::SourceLine(line_number));
+                start.extend(string_to_wasm(
+                    &expr,
+                    &mut wasm,
+                    line_number,
+                    &globals,
+                    &functions,
+                )?);
+                start.push(Instruction::Call("print".to_string()));
+            }
+
+            // Calls use the copies of generic functions made for the types they are called with
+            AstNode::Function(_, ref args, ..)
+                if args.iter().any(|arg| is_generic(&arg.data_type)) => {}
+
+            AstNode::Function(name, args, body, _, return_type) => {
+                match new_wat_function(
+                    &name,
+                    &args,
+                    &body,
+                    &return_type,
+                    &globals,
+                    &functions,
+                    line_number,
+                )? {
+                    Some(function) => wasm.functions.push(function),
+                    None => {
+                        return Err(format!(
+                            "Line {line_number}: The function '{name}' can't be used with the WASI target yet. Only functions that take and return numbers can be compiled to WASM"
+                        ));
+                    }
+                }
+                functions.push(name);
+            }
+
+            // Calls just for what the function does, the result isn't used
+            AstNode::FunctionCall(_, _, ref return_type) => {
+                if !is_wat_expression(&node, &[], &globals, &functions) {
+                    return Err(unsupported(line_number, "This function call"));
+                }
+                start.push(Instruction::SourceLine(line_number));
+                start.extend(expression_to_wat(&node, return_type, &[], line_number)?);
+                start.push(Instruction::Drop);
+            }
+
+            AstNode::Comment(_)
+            | AstNode::DocComment(_)
+            | AstNode::FoldedCall(_)
+            | AstNode::Empty => {}
+
+            AstNode::Scene(..) => return Err(unsupported(line_number, "Scenes")),
+            AstNode::JS(_) => return Err(unsupported(line_number, "JS")),
+            AstNode::If(..) => return Err(unsupported(line_number, "If statements")),
+            AstNode::For(..) => return Err(unsupported(line_number, "Loops")),
+            _ => return Err(unsupported(line_number, "This statement")),
+        }
+    }
+
+    wasm.functions.push(Function {
+        name: "_start".to_string(),
+        export: Some("_start".to_string()),
+        params: Vec::new(),
+        result: None,
+        locals: Vec::new(),
+        body: start,
+    });
+    let mut module = new_base_module(wasm, "");
+
+    // Setters tell JS when a value changes on a page, but there is no JS here
+    module.functions.push(Function {
+        name: "value_changed".to_string(),
+        export: None,
+        params: vec![("name".to_string(), ValType::I32)],
+        result: None,
+        locals: Vec::new(),
+        body: Vec::new(),
+    });
+
+    module.imports.push(Import {
+        module: "wasi_snapshot_preview1".to_string(),
+        field: "fd_write".to_string(),
+        name: "fd_write".to_string(),
+        params: vec![ValType::I32; 4],
+        result: Some(ValType::I32),
+    });
+    module.data.push(DataSegment {
+        name: "wasi_iovecs".to_string(),
+        bytes: vec![0; 20],
+    });
+    for (name, value) in [
+        ("wasi_newline", "\n"),
+        ("wasi_minus", "-"),
+        ("wasi_true", "true"),
+        ("wasi_false", "false"),
+        ("wasi_nan", "NaN"),
+        ("wasi_infinity", "Infinity"),
+        ("wasi_exponent", "e+"),
+    ] {
+        module.data.push(DataSegment {
+            name: name.to_string(),
+            bytes: string_bytes(value),
+        });
+    }
+    module.functions.push(print_function());
+    module.functions.push(string_concat_function());
+    module.functions.push(int_to_string_function());
+    module.functions.push(float_to_string_function());
+
+    remove_js_imports(&mut module)?;
+
+    Ok(module)
+}
+
+fn unsupported(line_number: u32, what: &str) -> String {
+    match line_number {
+        0 => format!("{what} can't be used with the WASI target yet"),
+        _ => format!("Line {line_number}: {what} can't be used with the WASI target yet"),
+    }
+}
+
+// Leaves the address of a new string on the stack
+// Strings can only be joined together with '+'
+fn string_to_wasm(
+    expr: &AstNode,
+    wasm: &mut WasmModule,
+    line_number: u32,
+    globals: &[String],
+    functions: &[String],
+) -> Result<Vec<Instruction>, String> {
+    match expr {
+        AstNode::Literal(Token::StringLiteral(value)) => {
+            let name = format!("string_{}", wasm.data.len());
+            wasm.data.push(DataSegment {
+                name: name.to_owned(),
+                bytes: string_bytes(value),
+            });
+            Ok(vec![Instruction::DataOffset(name)])
+        }
+
+        AstNode::VarReference(name, DataType::String)
+        | AstNode::ConstReference(name, DataType::String) => Ok(vec![Instruction::GlobalGet(
+            format!("{BS_VAR_PREFIX}{name}"),
+        )]),
+
+        AstNode::RuntimeExpression(nodes, DataType::String) => {
+            let mut instructions = Vec::new();
+            for (index, node) in nodes.iter().enumerate() {
+                match node {
+                    AstNode::BinaryOperator(Token::Add, _) if index % 2 == 1 => {}
+                    _ if index % 2 == 0 => {
+                        instructions.extend(string_to_wasm(
+                            node,
+                            wasm,
+                            line_number,
+                            globals,
+                            functions,
+                        )?);
+                        if index > 0 {
+                            instructions.push(Instruction::Call("string_concat".to_string()));
+                        }
+                    }
+                    _ => return Err(unsupported(line_number, "This string operation")),
+                }
+            }
+            Ok(instructions)
+        }
+
+        // Numbers and bools are written into a new string
+        _ if matches!(
+            expr.get_type(),
+            DataType::Int | DataType::Float | DataType::Bool
+        ) =>
+        {
+            if !is_wat_expression(expr, &[], globals, functions) {
+                return Err(unsupported(line_number, "This value"));
+            }
+            let data_type = expr.get_type();
+            let value = expression_to_wat(expr, &data_type, &[], line_number)?;
+            let to_string = match data_type {
+                DataType::Int => "int_to_string",
+                DataType::Float => "float_to_string",
+
+                // Bools are one of two constant strings
+                _ => {
+                    return Ok([
+                        vec![
+                            Instruction::DataOffset("wasi_true".to_string()),
+                            Instruction::DataOffset("wasi_false".to_string()),
+                        ],
+                        value,
+                        vec![Instruction::Select],
+                    ]
+                    .concat());
+                }
+            };
+            Ok([value, vec![Instruction::Call(to_string.to_string())]].concat())
+        }
+
+        _ => Err(unsupported(line_number, "This string")),
+    }
+}
+
+// Writes a string and then a new line to stdout
+fn print_function() -> Function {
+    use Instruction::*;
+    let iovecs = |offset: i32| {
+        vec![
+            DataOffset("wasi_iovecs".to_string()),
+            I32Const(offset),
+            Numeric(NumericOp::I32Add),
+        ]
+    };
+
+    // Each iovec is the address of the bytes to write and how many bytes there are
+    let mut body = iovecs(0);
+    body.extend([
+        LocalGet("string".to_string()),
+        I32Const(4),
+        Numeric(NumericOp::I32Add),
+        I32Store,
+    ]);
+    body.extend(iovecs(4));
+    body.extend([LocalGet("string".to_string()), I32Load, I32Store]);
+    body.extend(iovecs(8));
+    body.extend([
+        DataOffset("wasi_newline".to_string()),
+        I32Const(4),
+        Numeric(NumericOp::I32Add),
+        I32Store,
+    ]);
+    body.extend(iovecs(12));
+    body.extend([I32Const(1), I32Store]);
+
+    // fd_write(stdout, iovecs, 2 iovecs, where to write the number of bytes written)
+    body.extend([
+        I32Const(STDOUT),
+        DataOffset("wasi_iovecs".to_string()),
+        I32Const(2),
+    ]);
+    body.extend(iovecs(16));
+    body.extend([Call("fd_write".to_string()), Drop]);
+
+    Function {
+        name: "print".to_string(),
+        export: None,
+        params: vec![("string".to_string(), ValType::I32)],
+        result: None,
+        locals: Vec::new(),
+        body,
+    }
+}
+
+// Copies two strings into a new string
+fn string_concat_function() -> Function {
+    use Instruction::*;
+    let get = |name: &str| LocalGet(name.to_string());
+    let set = |name: &str| LocalSet(name.to_string());
+    let add = || Numeric(NumericOp::I32Add);
+
+    Function {
+        name: "string_concat".to_string(),
+        export: None,
+        params: vec![
+            ("first".to_string(), ValType::I32),
+            ("second".to_string(), ValType::I32),
+        ],
+        result: Some(ValType::I32),
+        locals: vec![
+            ("first_len".to_string(), ValType::I32),
+            ("second_len".to_string(), ValType::I32),
+            ("ptr".to_string(), ValType::I32),
+        ],
+        body: vec![
+            get("first"),
+            I32Load,
+            set("first_len"),
+            get("second"),
+            I32Load,
+            set("second_len"),
+            // New string with the length of both
+            get("first_len"),
+            get("second_len"),
+            add(),
+            I32Const(4),
+            add(),
+            Call("alloc".to_string()),
+            set("ptr"),
+            get("ptr"),
+            get("first_len"),
+            get("second_len"),
+            add(),
+            I32Store,
+            // Bytes of the first string
+            get("ptr"),
+            I32Const(4),
+            add(),
+            get("first"),
+            I32Const(4),
+            add(),
+            get("first_len"),
+            MemoryCopy,
+            // Bytes of the second string straight after
+            get("ptr"),
+            I32Const(4),
+            add(),
+            get("first_len"),
+            add(),
+            get("second"),
+            I32Const(4),
+            add(),
+            get("second_len"),
+            MemoryCopy,
+            get("ptr"),
+        ],
+    }
+}
+
+// Writes the digits of an Int into a new string
+// The digits are worked out from the negative version of the number,
+// as the lowest Int has no positive version
+fn int_to_string_function() -> Function {
+    use Instruction::*;
+    use NumericOp::*;
+    let get = |name: &str| LocalGet(name.to_string());
+    let set = |name: &str| LocalSet(name.to_string());
+
+    Function {
+        name: "int_to_string".to_string(),
+        export: None,
+        params: vec![("value".to_string(), ValType::I64)],
+        result: Some(ValType::I32),
+        locals: vec![
+            ("negative".to_string(), ValType::I64),
+            ("ptr".to_string(), ValType::I32),
+            ("pos".to_string(), ValType::I32),
+            ("len".to_string(), ValType::I32),
+        ],
+        body: vec![
+            get("value"),
+            I64Const(0),
+            get("value"),
+            Numeric(I64Sub),
+            get("value"),
+            I64Const(0),
+            Numeric(I64LtS),
+            Select,
+            set("negative"),
+            // The longest Int is 20 characters with its sign
+            I32Const(24),
+            Call("alloc".to_string()),
+            set("ptr"),
+            get("ptr"),
+            I32Const(24),
+            Numeric(I32Add),
+            set("pos"),
+            // Digits are written backwards from the end of the string
+            Block("done".to_string()),
+            Loop("digit".to_string()),
+            get("pos"),
+            I32Const(1),
+            Numeric(I32Sub),
+            set("pos"),
+            get("pos"),
+            I64Const(0),
+            get("negative"),
+            I64Const(10),
+            Numeric(I64RemS),
+            Numeric(I64Sub),
+            Numeric(I32WrapI64),
+            I32Const(b'0' as i32),
+            Numeric(I32Add),
+            I32Store8,
+            get("negative"),
+            I64Const(10),
+            Numeric(I64DivS),
+            set("negative"),
+            get("negative"),
+            Numeric(I64Eqz),
+            BrIf("done".to_string()),
+            Br("digit".to_string()),
+            End,
+            End,
+            Block("positive".to_string()),
+            get("value"),
+            I64Const(0),
+            Numeric(I64GeS),
+            BrIf("positive".to_string()),
+            get("pos"),
+            I32Const(1),
+            Numeric(I32Sub),
+            set("pos"),
+            get("pos"),
+            I32Const(b'-' as i32),
+            I32Store8,
+            End,
+            // Move the characters to just after the length
+            get("ptr"),
+            I32Const(24),
+            Numeric(I32Add),
+            get("pos"),
+            Numeric(I32Sub),
+            set("len"),
+            get("ptr"),
+            get("len"),
+            I32Store,
+            get("ptr"),
+            I32Const(4),
+            Numeric(I32Add),
+            get("pos"),
+            get("len"),
+            MemoryCopy,
+            get("ptr"),
+        ],
+    }
+}
+
+// Floats are written with up to 6 decimal places, without any zeros at the end
+// Whole numbers have no decimal point, the same as JS
+// Floats too big to fit in an Int are written with an exponent instead, like 1e+20
+fn float_to_string_function() -> Function {
+    use Instruction::*;
+    use NumericOp::*;
+    let get = |name: &str| LocalGet(name.to_string());
+    let set = |name: &str| LocalSet(name.to_string());
+    let int_to_string = || Call("int_to_string".to_string());
+    let concat = || Call("string_concat".to_string());
+
+    Function {
+        name: "float_to_string".to_string(),
+        export: None,
+        params: vec![("value".to_string(), ValType::F64)],
+        result: Some(ValType::I32),
+        locals: vec![
+            ("whole".to_string(), ValType::I64),
+            ("fraction".to_string(), ValType::I64),
+            ("places".to_string(), ValType::I64),
+            ("string".to_string(), ValType::I32),
+            ("digits".to_string(), ValType::I32),
+            ("mantissa".to_string(), ValType::F64),
+        ],
+        body: vec![
+            // NaN is the only value that isn't equal to itself
+            Block("number".to_string()),
+            get("value"),
+            get("value"),
+            Numeric(F64Eq),
+            BrIf("number".to_string()),
+            DataOffset("wasi_nan".to_string()),
+            Return,
+            End,
+            Block("fits_int".to_string()),
+            get("value"),
+            Numeric(F64Abs),
+            F64Const(9_223_372_036_854_775_808.0),
+            Numeric(F64Lt),
+            BrIf("fits_int".to_string()),
+            Block("large".to_string()),
+            Block("finite".to_string()),
+            get("value"),
+            Numeric(F64Abs),
+            F64Const(f64::INFINITY),
+            Numeric(F64Lt),
+            BrIf("finite".to_string()),
+            DataOffset("wasi_infinity".to_string()),
+            set("string"),
+            Br("large".to_string()),
+            End,
+            // Divided down to one digit before the decimal point, counting the powers of 10 as the exponent
+            get("value"),
+            Numeric(F64Abs),
+            set("mantissa"),
+            I64Const(0),
+            set("places"),
+            Loop("scale".to_string()),
+            get("mantissa"),
+            F64Const(10.0),
+            Numeric(F64Div),
+            set("mantissa"),
+            get("places"),
+            I64Const(1),
+            Numeric(I64Add),
+            set("places"),
+            get("mantissa"),
+            F64Const(10.0),
+            Numeric(F64Ge),
+            BrIf("scale".to_string()),
+            End,
+            // Rounding to 6 decimal places can reach 10
+            Block("normalised".to_string()),
+            get("mantissa"),
+            F64Const(9.999_999_5),
+            Numeric(F64Lt),
+            BrIf("normalised".to_string()),
+            get("mantissa"),
+            F64Const(10.0),
+            Numeric(F64Div),
+            set("mantissa"),
+            get("places"),
+            I64Const(1),
+            Numeric(I64Add),
+            set("places"),
+            End,
+            get("mantissa"),
+            Call("float_to_string".to_string()),
+            DataOffset("wasi_exponent".to_string()),
+            concat(),
+            get("places"),
+            int_to_string(),
+            concat(),
+            set("string"),
+            End,
+            Block("positive_large".to_string()),
+            get("value"),
+            F64Const(0.0),
+            Numeric(F64Gt),
+            BrIf("positive_large".to_string()),
+            DataOffset("wasi_minus".to_string()),
+            get("string"),
+            concat(),
+            set("string"),
+            End,
+            get("string"),
+            Return,
+            End,
+            get("value"),
+            Numeric(F64Abs),
+            Numeric(I64TruncSatF64S),
+            set("whole"),
+            // The fraction rounded to 6 decimal places
+            get("value"),
+            Numeric(F64Abs),
+            get("value"),
+            Numeric(F64Abs),
+            Numeric(F64Trunc),
+            Numeric(F64Sub),
+            F64Const(1_000_000.0),
+            Numeric(F64Mul),
+            F64Const(0.5),
+            Numeric(F64Add),
+            Numeric(F64Floor),
+            Numeric(I64TruncSatF64S),
+            set("fraction"),
+            // Rounding up can reach the next whole number
+            Block("rounded".to_string()),
+            get("fraction"),
+            I64Const(1_000_000),
+            Numeric(I64LtS),
+            BrIf("rounded".to_string()),
+            get("whole"),
+            I64Const(1),
+            Numeric(I64Add),
+            set("whole"),
+            I64Const(0),
+            set("fraction"),
+            End,
+            get("whole"),
+            int_to_string(),
+            set("string"),
+            // Numbers that round to 0 have no sign
+            Block("positive".to_string()),
+            get("value"),
+            F64Const(0.0),
+            Numeric(F64Ge),
+            BrIf("positive".to_string()),
+            get("whole"),
+            get("fraction"),
+            Numeric(I64Add),
+            Numeric(I64Eqz),
+            BrIf("positive".to_string()),
+            DataOffset("wasi_minus".to_string()),
+            get("string"),
+            concat(),
+            set("string"),
+            End,
+            Block("whole_number".to_string()),
+            get("fraction"),
+            Numeric(I64Eqz),
+            BrIf("whole_number".to_string()),
+            I64Const(1_000_000),
+            set("places"),
+            Block("trimmed".to_string()),
+            Loop("trim".to_string()),
+            get("fraction"),
+            I64Const(10),
+            Numeric(I64RemS),
+            Numeric(I32WrapI64),
+            BrIf("trimmed".to_string()),
+            get("fraction"),
+            I64Const(10),
+            Numeric(I64DivS),
+            set("fraction"),
+            get("places"),
+            I64Const(10),
+            Numeric(I64DivS),
+            set("places"),
+            Br("trim".to_string()),
+            End,
+            End,
+            // Adding the places keeps the zeros at the start of the fraction,
+            // and the extra 1 in front of them is replaced with the decimal point
+            get("places"),
+            get("fraction"),
+            Numeric(I64Add),
+            int_to_string(),
+            set("digits"),
+            get("digits"),
+            I32Const(4),
+            Numeric(I32Add),
+            I32Const(b'.' as i32),
+            I32Store8,
+            get("string"),
+            get("digits"),
+            concat(),
+            set("string"),
+            End,
+            get("string"),
+        ],
+    }
+}
+
+// The maths prelude imports functions from JS, which a WASI runtime doesn't have
+// They are removed if nothing calls them
+fn remove_js_imports(module: &mut WasmModule) -> Result<(), String> {
+    let called: Vec<&String> = module
+        .functions
+        .iter()
+        .flat_map(|function| &function.body)
+        .filter_map(|instruction| match instruction {
+            Instruction::Call(name) => Some(name),
+            _ => None,
+        })
+        .collect();
+
+    if let Some(import) = module
+        .imports
+        .iter()
+        .find(|import| import.module == "Math" && called.contains(&&import.name))
+    {
+        return Err(format!(
+            "Math.{} can't be used with the WASI target yet",
+            import.field
+        ));
+    }
+
+    module.imports.retain(|import| import.module != "Math");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::run_wasi;
+
+    #[test]
+    fn prints_floats() {
+        let output = run_wasi(
+            "prints_floats",
+            "a = 2.5\nprint(a)\nb = 0.0 - 1234.5678\nprint(b)\n",
+        );
+        assert_eq!(output, "2.5\n-1234.5678\n");
+    }
+
+    #[test]
+    fn prints_large_floats_with_an_exponent() {
+        let output = run_wasi(
+            "prints_large_floats",
+            "a = 1e20\nprint(a)\nb = 0.0 - 1.5e300\nprint(b)\n",
+        );
+        assert_eq!(output, "1e+20\n-1.5e+300\n");
+    }
+
+    #[test]
+    fn prints_infinity_and_nan() {
+        let output = run_wasi(
+            "prints_infinity_and_nan",
+            "a = 1.0\nb = a / 0.0\nprint(b)\nc = 0.0 - b\nprint(c)\nd = b + c\nprint(d)\n",
+        );
+        assert_eq!(output, "Infinity\n-Infinity\nNaN\n");
+    }
+}
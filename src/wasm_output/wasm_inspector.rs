@@ -0,0 +1,160 @@
+use super::wasm_module::{instruction_to_wat, Instruction, ValType, WasmModule, PAGE_SIZE};
+use crate::build::{compile, OutputFile};
+use crate::settings::get_default_config;
+use colour::{blue_ln_bold, dark_yellow_ln, grey_ln, print_bold, red_ln};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+/*
+    Compiles a page the same way the build command does, then prints what is in its WASM module.
+    Nothing is written, so it can be used to check what the compiler creates for a page while working on it.
+    The full WAT of a page is written next to its .wasm file in dev builds.
+*/
+
+pub fn inspect_page(path: &str) -> Result<(), Box<dyn Error>> {
+    let file = PathBuf::from(path);
+    if file.extension() != Some("bs".as_ref()) {
+        red_ln!("Only .bs files can be inspected: {:?}", file);
+        return Err("Only .bs files can be inspected".into());
+    }
+
+    let page = OutputFile {
+        source_code: fs::read_to_string(&file)?,
+        file: file.with_extension("html"),
+        compiled_code: String::new(),
+        wasm: Vec::new(),
+        js_glue: String::new(),
+        wat: String::new(),
+        imports: Vec::new(),
+        global: false,
+    };
+    let (_, wasm_module, _, _) = compile(
+        &page,
+        true,
+        &get_default_config(),
+        &mut Vec::new(),
+        &mut String::new(),
+    )?;
+
+    inspect_module(&wasm_module)?;
+    Ok(())
+}
+
+// Prints the exports, globals, functions and data of a module, and how big each part of it is
+pub fn inspect_module(module: &WasmModule) -> Result<(), String> {
+    let wasm = module.encode()?;
+    let function_sizes = module.function_sizes()?;
+    let (data, data_end) = module.data_layout();
+
+    print_bold!("\nWASM module: ");
+    dark_yellow_ln!("{} bytes", wasm.len());
+    grey_ln!(
+        "{} functions ({} bytes), {} globals, {} data segments ({} bytes)",
+        module.functions.len(),
+        function_sizes.iter().sum::<usize>(),
+        module.globals.len(),
+        module.data.len(),
+        module
+            .data
+            .iter()
+            .map(|segment| segment.bytes.len())
+            .sum::<usize>()
+    );
+
+    blue_ln_bold!("\nImports");
+    for import in &module.imports {
+        println!(
+            "  {}.{}{}",
+            import.module,
+            import.field,
+            signature(import.params.iter().map(|t| ("", *t)), import.result)
+        );
+    }
+
+    blue_ln_bold!("\nExports");
+    if let Some(memory) = &module.memory {
+        if let Some(export) = &memory.export {
+            let pages = memory.pages.max(data_end.div_ceil(PAGE_SIZE));
+            println!("  {export}: memory, {pages} page(s)");
+        }
+    }
+    for global in &module.globals {
+        if let Some(export) = &global.export {
+            println!(
+                "  {export}: global {}",
+                global_type(global.mutable, global.val_type)
+            );
+        }
+    }
+    for function in &module.functions {
+        if let Some(export) = &function.export {
+            let params = function.params.iter().map(|(n, t)| (n.as_str(), *t));
+            println!("  {export}: function{}", signature(params, function.result));
+        }
+    }
+
+    blue_ln_bold!("\nGlobals");
+    for global in &module.globals {
+        let init = match &global.init {
+            // Data addresses are only known once the data has been placed in memory
+            Instruction::DataOffset(name) => {
+                let offset = data.iter().find(|(n, _)| n == name).map(|(_, o)| *o);
+                format!("address {} ({name})", offset.unwrap_or_default())
+            }
+            Instruction::DataEnd => format!("address {data_end} (end of data)"),
+            init => instruction_to_wat(init),
+        };
+        println!(
+            "  ${}: {} = {init}",
+            global.name,
+            global_type(global.mutable, global.val_type)
+        );
+    }
+
+    blue_ln_bold!("\nFunctions");
+    for (function, size) in module.functions.iter().zip(&function_sizes) {
+        let params = function.params.iter().map(|(n, t)| (n.as_str(), *t));
+        print!("  ${}{}", function.name, signature(params, function.result));
+        grey_ln!(
+            "  {size} bytes, {} locals, {} instructions",
+            function.locals.len(),
+            function
+                .body
+                .iter()
+                .filter(|instruction| !matches!(instruction, Instruction::SourceLine(_)))
+                .count()
+        );
+    }
+
+    blue_ln_bold!("\nData");
+    for (segment, (name, offset)) in module.data.iter().zip(&data) {
+        println!("  {name}: {} bytes at {offset}", segment.bytes.len());
+    }
+    println!();
+
+    Ok(())
+}
+
+fn signature<'a>(
+    params: impl Iterator<Item = (&'a str, ValType)>,
+    result: Option<ValType>,
+) -> String {
+    let params: Vec<String> = params
+        .map(|(name, val_type)| match name {
+            "" => val_type.name().to_string(),
+            _ => format!("{name} {}", val_type.name()),
+        })
+        .collect();
+    match result {
+        Some(result) => format!("({}) -> {}", params.join(", "), result.name()),
+        None => format!("({})", params.join(", ")),
+    }
+}
+
+fn global_type(mutable: bool, val_type: ValType) -> String {
+    match mutable {
+        true => format!("mut {}", val_type.name()),
+        false => val_type.name().to_string(),
+    }
+}
@@ -0,0 +1,868 @@
+use super::wasm_validator::validate_module;
+
+/*
+    Builds a WASM module in memory and encodes it straight to the binary format
+
+    Functions, globals, locals and block labels are referred to by name.
+    Names are turned into indexes when the module is encoded,
+    so an instruction that refers to something that doesn't exist is an error instead of a broken module.
+    Modules are always validated before they are encoded.
+
+    Data segments are placed one after another in memory when the module is encoded.
+    The first few bytes of memory are left empty, so 0 can be used as an empty value.
+*/
+
+pub const PAGE_SIZE: u32 = 65536;
+const DATA_START: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValType {
+    I32,
+    I64,
+    F64,
+}
+
+impl ValType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F64 => "f64",
+        }
+    }
+
+    fn encode(&self) -> u8 {
+        match self {
+            ValType::I32 => 0x7F,
+            ValType::I64 => 0x7E,
+            ValType::F64 => 0x7C,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    // Constants
+    I32Const(i32),
+    I64Const(i64),
+    F64Const(f64),
+
+    // Variables
+    LocalGet(String),
+    LocalSet(String),
+    GlobalGet(String),
+    GlobalSet(String),
+
+    // Control flow
+    Block(String), // Label
+    Loop(String),  // Label
+    End,
+    Br(String),   // Label
+    BrIf(String), // Label
+    Call(String), // Function name
+    Return,
+    Select,
+    Drop,
+
+    // Memory
+    I32Load,   // Address
+    I32Store,  // Address, value
+    I32Store8, // Address, value (only the lowest byte is stored)
+    MemorySize,
+    MemoryGrow,
+    MemoryCopy,         // Destination, source, length
+    DataOffset(String), // Address of a data segment, as an i32
+    DataEnd,            // First address after all the data, as an i32
+
+    // Numbers
+    Numeric(NumericOp),
+
+    // Line in the Beanstalk source the following instructions were created from
+    // Only used for reporting errors, nothing is encoded for it
+    SourceLine(u32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumericOp {
+    I32LeU,
+    I32Add,
+    I32Sub,
+    I32And,
+    I32Or,
+    I32Shl,
+    I32ShrU,
+
+    I64Eqz,
+    I64Eq,
+    I64LtS,
+    I64GtS,
+    I64LeS,
+    I64GeS,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64RemS,
+
+    F64Eq,
+    F64Lt,
+    F64Gt,
+    F64Le,
+    F64Ge,
+    F64Abs,
+    F64Neg,
+    F64Ceil,
+    F64Floor,
+    F64Trunc,
+    F64Sqrt,
+    F64Add,
+    F64Sub,
+    F64Mul,
+    F64Div,
+    F64Min,
+    F64Max,
+
+    // Conversions
+    I64ExtendI32S,
+    F64ConvertI32S,
+    F64ConvertI64S,
+    I64TruncSatF64S,
+    I32WrapI64,
+}
+
+impl NumericOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NumericOp::I32LeU => "i32.le_u",
+            NumericOp::I32Add => "i32.add",
+            NumericOp::I32Sub => "i32.sub",
+            NumericOp::I32And => "i32.and",
+            NumericOp::I32Or => "i32.or",
+            NumericOp::I32Shl => "i32.shl",
+            NumericOp::I32ShrU => "i32.shr_u",
+            NumericOp::I64Eqz => "i64.eqz",
+            NumericOp::I64Eq => "i64.eq",
+            NumericOp::I64LtS => "i64.lt_s",
+            NumericOp::I64GtS => "i64.gt_s",
+            NumericOp::I64LeS => "i64.le_s",
+            NumericOp::I64GeS => "i64.ge_s",
+            NumericOp::I64Add => "i64.add",
+            NumericOp::I64Sub => "i64.sub",
+            NumericOp::I64Mul => "i64.mul",
+            NumericOp::I64DivS => "i64.div_s",
+            NumericOp::I64RemS => "i64.rem_s",
+            NumericOp::F64Eq => "f64.eq",
+            NumericOp::F64Lt => "f64.lt",
+            NumericOp::F64Gt => "f64.gt",
+            NumericOp::F64Le => "f64.le",
+            NumericOp::F64Ge => "f64.ge",
+            NumericOp::F64Abs => "f64.abs",
+            NumericOp::F64Neg => "f64.neg",
+            NumericOp::F64Ceil => "f64.ceil",
+            NumericOp::F64Floor => "f64.floor",
+            NumericOp::F64Trunc => "f64.trunc",
+            NumericOp::F64Sqrt => "f64.sqrt",
+            NumericOp::F64Add => "f64.add",
+            NumericOp::F64Sub => "f64.sub",
+            NumericOp::F64Mul => "f64.mul",
+            NumericOp::F64Div => "f64.div",
+            NumericOp::F64Min => "f64.min",
+            NumericOp::F64Max => "f64.max",
+            NumericOp::I64ExtendI32S => "i64.extend_i32_s",
+            NumericOp::F64ConvertI32S => "f64.convert_i32_s",
+            NumericOp::F64ConvertI64S => "f64.convert_i64_s",
+            NumericOp::I64TruncSatF64S => "i64.trunc_sat_f64_s",
+            NumericOp::I32WrapI64 => "i32.wrap_i64",
+        }
+    }
+
+    // Types of the values taken from the stack and the type of the result
+    pub fn signature(&self) -> (&'static [ValType], ValType) {
+        use ValType::*;
+        match self {
+            NumericOp::I32LeU
+            | NumericOp::I32Add
+            | NumericOp::I32Sub
+            | NumericOp::I32And
+            | NumericOp::I32Or
+            | NumericOp::I32Shl
+            | NumericOp::I32ShrU => (&[I32, I32], I32),
+            NumericOp::I64Eqz => (&[I64], I32),
+            NumericOp::I64Eq
+            | NumericOp::I64LtS
+            | NumericOp::I64GtS
+            | NumericOp::I64LeS
+            | NumericOp::I64GeS => (&[I64, I64], I32),
+            NumericOp::I64Add
+            | NumericOp::I64Sub
+            | NumericOp::I64Mul
+            | NumericOp::I64DivS
+            | NumericOp::I64RemS => (&[I64, I64], I64),
+            NumericOp::F64Eq
+            | NumericOp::F64Lt
+            | NumericOp::F64Gt
+            | NumericOp::F64Le
+            | NumericOp::F64Ge => (&[F64, F64], I32),
+            NumericOp::F64Abs
+            | NumericOp::F64Neg
+            | NumericOp::F64Ceil
+            | NumericOp::F64Floor
+            | NumericOp::F64Trunc
+            | NumericOp::F64Sqrt => (&[F64], F64),
+            NumericOp::F64Add
+            | NumericOp::F64Sub
+            | NumericOp::F64Mul
+            | NumericOp::F64Div
+            | NumericOp::F64Min
+            | NumericOp::F64Max => (&[F64, F64], F64),
+            NumericOp::I64ExtendI32S => (&[I32], I64),
+            NumericOp::F64ConvertI32S => (&[I32], F64),
+            NumericOp::F64ConvertI64S => (&[I64], F64),
+            NumericOp::I64TruncSatF64S => (&[F64], I64),
+            NumericOp::I32WrapI64 => (&[I64], I32),
+        }
+    }
+
+    fn opcode(&self) -> &'static [u8] {
+        match self {
+            NumericOp::I32LeU => &[0x4D],
+            NumericOp::I64Eqz => &[0x50],
+            NumericOp::I64Eq => &[0x51],
+            NumericOp::I64LtS => &[0x53],
+            NumericOp::I64GtS => &[0x55],
+            NumericOp::I64LeS => &[0x57],
+            NumericOp::I64GeS => &[0x59],
+            NumericOp::F64Eq => &[0x61],
+            NumericOp::F64Lt => &[0x63],
+            NumericOp::F64Gt => &[0x64],
+            NumericOp::F64Le => &[0x65],
+            NumericOp::F64Ge => &[0x66],
+            NumericOp::I32And => &[0x71],
+            NumericOp::I32Or => &[0x72],
+            NumericOp::I32Shl => &[0x74],
+            NumericOp::I32ShrU => &[0x76],
+            NumericOp::I32Add => &[0x6A],
+            NumericOp::I32Sub => &[0x6B],
+            NumericOp::I64Add => &[0x7C],
+            NumericOp::I64Sub => &[0x7D],
+            NumericOp::I64Mul => &[0x7E],
+            NumericOp::I64DivS => &[0x7F],
+            NumericOp::I64RemS => &[0x81],
+            NumericOp::F64Abs => &[0x99],
+            NumericOp::F64Neg => &[0x9A],
+            NumericOp::F64Ceil => &[0x9B],
+            NumericOp::F64Floor => &[0x9C],
+            NumericOp::F64Trunc => &[0x9D],
+            NumericOp::F64Sqrt => &[0x9F],
+            NumericOp::F64Add => &[0xA0],
+            NumericOp::F64Sub => &[0xA1],
+            NumericOp::F64Mul => &[0xA2],
+            NumericOp::F64Div => &[0xA3],
+            NumericOp::F64Min => &[0xA4],
+            NumericOp::F64Max => &[0xA5],
+            NumericOp::I32WrapI64 => &[0xA7],
+            NumericOp::I64ExtendI32S => &[0xAC],
+            NumericOp::F64ConvertI32S => &[0xB7],
+            NumericOp::F64ConvertI64S => &[0xB9],
+            NumericOp::I64TruncSatF64S => &[0xFC, 0x06],
+        }
+    }
+}
+
+// Functions imported from JS
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub module: String,
+    pub field: String,
+    pub name: String,
+    pub params: Vec<ValType>,
+    pub result: Option<ValType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub export: Option<String>,
+    pub params: Vec<(String, ValType)>,
+    pub result: Option<ValType>,
+    pub locals: Vec<(String, ValType)>,
+    pub body: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub export: Option<String>,
+    pub val_type: ValType,
+    pub mutable: bool,
+    pub init: Instruction, // Must be a constant
+}
+
+// Bytes copied into memory when the module is created
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataSegment {
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Memory {
+    pub pages: u32, // Grows to fit all of the data
+    pub export: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WasmModule {
+    pub imports: Vec<Import>,
+    pub functions: Vec<Function>,
+    pub globals: Vec<Global>,
+    pub memory: Option<Memory>,
+    pub data: Vec<DataSegment>,
+}
+
+impl WasmModule {
+    pub fn new() -> WasmModule {
+        WasmModule::default()
+    }
+
+    // Adds everything from another module, such as the WASM created by the body of a block
+    pub fn append(&mut self, other: WasmModule) {
+        self.imports.extend(other.imports);
+        self.functions.extend(other.functions);
+        self.globals.extend(other.globals);
+        self.data.extend(other.data);
+        if other.memory.is_some() {
+            self.memory = other.memory;
+        }
+    }
+
+    pub fn calls(&self, name: &str) -> bool {
+        self.functions
+            .iter()
+            .flat_map(|function| &function.body)
+            .any(|instruction| matches!(instruction, Instruction::Call(called) if called == name))
+    }
+
+    // Where each data segment starts in memory, and the first address after all of them
+    // Everything is aligned to 8 bytes
+    pub fn data_layout(&self) -> (Vec<(&str, u32)>, u32) {
+        let mut offsets = Vec::new();
+        let mut offset = DATA_START;
+        for segment in &self.data {
+            offsets.push((segment.name.as_str(), offset));
+            offset = (offset + segment.bytes.len() as u32).next_multiple_of(8);
+        }
+        (offsets, offset)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        validate_module(self)?;
+
+        let mut wasm = vec![0x00, 0x61, 0x73, 0x6D, 0x01, 0x00, 0x00, 0x00];
+
+        // Every function signature is only added once
+        let mut types: Vec<(Vec<ValType>, Option<ValType>)> = Vec::new();
+        let mut type_index = |params: Vec<ValType>, result: Option<ValType>| -> u32 {
+            let signature = (params, result);
+            match types.iter().position(|t| *t == signature) {
+                Some(index) => index as u32,
+                None => {
+                    types.push(signature);
+                    types.len() as u32 - 1
+                }
+            }
+        };
+
+        let import_types: Vec<u32> = self
+            .imports
+            .iter()
+            .map(|import| type_index(import.params.to_owned(), import.result))
+            .collect();
+        let function_types: Vec<u32> = self
+            .functions
+            .iter()
+            .map(|function| {
+                let params = function.params.iter().map(|(_, t)| *t).collect();
+                type_index(params, function.result)
+            })
+            .collect();
+
+        let (data, data_end) = self.data_layout();
+
+        // Types
+        let mut section = Vec::new();
+        write_u32(&mut section, types.len() as u32);
+        for (params, result) in &types {
+            section.push(0x60);
+            write_u32(&mut section, params.len() as u32);
+            section.extend(params.iter().map(ValType::encode));
+            match result {
+                Some(result) => section.extend([0x01, result.encode()]),
+                None => section.push(0x00),
+            }
+        }
+        write_section(&mut wasm, 1, section);
+
+        // Imports
+        if !self.imports.is_empty() {
+            let mut section = Vec::new();
+            write_u32(&mut section, self.imports.len() as u32);
+            for (import, type_index) in self.imports.iter().zip(import_types) {
+                write_name(&mut section, &import.module);
+                write_name(&mut section, &import.field);
+                section.push(0x00);
+                write_u32(&mut section, type_index);
+            }
+            write_section(&mut wasm, 2, section);
+        }
+
+        // Functions
+        let mut section = Vec::new();
+        write_u32(&mut section, function_types.len() as u32);
+        for type_index in function_types {
+            write_u32(&mut section, type_index);
+        }
+        write_section(&mut wasm, 3, section);
+
+        // Memory
+        if let Some(memory) = &self.memory {
+            let mut section = vec![0x01, 0x00];
+            write_u32(&mut section, memory.pages.max(data_end.div_ceil(PAGE_SIZE)));
+            write_section(&mut wasm, 5, section);
+        }
+
+        // Globals
+        let mut section = Vec::new();
+        write_u32(&mut section, self.globals.len() as u32);
+        for global in &self.globals {
+            section.push(global.val_type.encode());
+            section.push(global.mutable as u8);
+            let names = Names {
+                data: &data,
+                data_end,
+                ..Names::default()
+            };
+            encode_instruction(&mut section, &global.init, &names)?;
+            section.push(0x0B);
+        }
+        write_section(&mut wasm, 6, section);
+
+        // Exports
+        let mut exports: Vec<(&str, u8, u32)> = Vec::new();
+        if let Some(Memory {
+            export: Some(name), ..
+        }) = &self.memory
+        {
+            exports.push((name, 0x02, 0));
+        }
+        for (index, global) in self.globals.iter().enumerate() {
+            if let Some(name) = &global.export {
+                exports.push((name, 0x03, index as u32));
+            }
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            if let Some(name) = &function.export {
+                exports.push((name, 0x00, (self.imports.len() + index) as u32));
+            }
+        }
+        let mut section = Vec::new();
+        write_u32(&mut section, exports.len() as u32);
+        for (name, kind, index) in exports {
+            write_name(&mut section, name);
+            section.push(kind);
+            write_u32(&mut section, index);
+        }
+        write_section(&mut wasm, 7, section);
+
+        // Code
+        let mut section = Vec::new();
+        write_u32(&mut section, self.functions.len() as u32);
+        for body in self.encode_functions()? {
+            write_u32(&mut section, body.len() as u32);
+            section.extend(body);
+        }
+        write_section(&mut wasm, 10, section);
+
+        // Data
+        if !self.data.is_empty() {
+            let mut section = Vec::new();
+            write_u32(&mut section, self.data.len() as u32);
+            for (segment, (_, offset)) in self.data.iter().zip(&data) {
+                section.push(0x00);
+                section.push(0x41);
+                write_i64(&mut section, *offset as i64);
+                section.push(0x0B);
+                write_u32(&mut section, segment.bytes.len() as u32);
+                section.extend(&segment.bytes);
+            }
+            write_section(&mut wasm, 11, section);
+        }
+
+        Ok(wasm)
+    }
+
+    // Size in bytes of the encoded body of each function
+    pub fn function_sizes(&self) -> Result<Vec<usize>, String> {
+        validate_module(self)?;
+        Ok(self.encode_functions()?.iter().map(Vec::len).collect())
+    }
+
+    fn encode_functions(&self) -> Result<Vec<Vec<u8>>, String> {
+        let function_names: Vec<&str> = self
+            .imports
+            .iter()
+            .map(|import| import.name.as_str())
+            .chain(self.functions.iter().map(|function| function.name.as_str()))
+            .collect();
+        let global_names: Vec<&str> = self.globals.iter().map(|g| g.name.as_str()).collect();
+        let (data, data_end) = self.data_layout();
+
+        let mut bodies = Vec::new();
+        for function in &self.functions {
+            let locals: Vec<&str> = function
+                .params
+                .iter()
+                .chain(function.locals.iter())
+                .map(|(name, _)| name.as_str())
+                .collect();
+
+            let names = Names {
+                functions: &function_names,
+                globals: &global_names,
+                locals: &locals,
+                data: &data,
+                data_end,
+                labels: Vec::new(),
+            };
+
+            let mut body = Vec::new();
+            write_u32(&mut body, function.locals.len() as u32);
+            for (_, val_type) in &function.locals {
+                body.push(0x01);
+                body.push(val_type.encode());
+            }
+            encode_body(&mut body, &function.body, names)
+                .map_err(|err| format!("{err} (in function '{}')", function.name))?;
+            bodies.push(body);
+        }
+        Ok(bodies)
+    }
+
+    // The module in the WebAssembly text format, for reading what the compiler created
+    pub fn to_wat(&self) -> String {
+        let mut wat = String::from("(module");
+        let (data, data_end) = self.data_layout();
+
+        // Data addresses are only known once the data has been placed in memory
+        let instruction_to_wat = |instruction: &Instruction| match instruction {
+            Instruction::DataOffset(name) => {
+                let offset = data.iter().find(|(n, _)| n == name).map(|(_, o)| *o);
+                format!("i32.const {} (; {name} ;)", offset.unwrap_or_default())
+            }
+            Instruction::DataEnd => format!("i32.const {data_end} (; end of data ;)"),
+            _ => instruction_to_wat(instruction),
+        };
+
+        for import in &self.imports {
+            wat.push_str(&format!(
+                "\n  (import \"{}\" \"{}\" (func ${}{}))",
+                import.module,
+                import.field,
+                import.name,
+                signature_to_wat(&import.params, import.result)
+            ));
+        }
+
+        if let Some(memory) = &self.memory {
+            wat.push_str("\n  (memory");
+            if let Some(export) = &memory.export {
+                wat.push_str(&format!(" (export \"{export}\")"));
+            }
+            wat.push_str(&format!(
+                " {})",
+                memory.pages.max(data_end.div_ceil(PAGE_SIZE))
+            ));
+        }
+
+        for global in &self.globals {
+            wat.push_str(&format!("\n  (global ${}", global.name));
+            if let Some(export) = &global.export {
+                wat.push_str(&format!(" (export \"{export}\")"));
+            }
+            match global.mutable {
+                true => wat.push_str(&format!(" (mut {})", global.val_type.name())),
+                false => wat.push_str(&format!(" {}", global.val_type.name())),
+            }
+            wat.push_str(&format!(" ({}))", instruction_to_wat(&global.init)));
+        }
+
+        for function in &self.functions {
+            wat.push_str(&format!("\n  (func ${}", function.name));
+            if let Some(export) = &function.export {
+                wat.push_str(&format!(" (export \"{export}\")"));
+            }
+            for (name, val_type) in &function.params {
+                wat.push_str(&format!(" (param ${name} {})", val_type.name()));
+            }
+            if let Some(result) = function.result {
+                wat.push_str(&format!(" (result {})", result.name()));
+            }
+            for (name, val_type) in &function.locals {
+                wat.push_str(&format!("\n    (local ${name} {})", val_type.name()));
+            }
+
+            let mut depth = 2;
+            for instruction in &function.body {
+                if let Instruction::End = instruction {
+                    depth -= 1;
+                }
+                wat.push_str(&format!(
+                    "\n{}{}",
+                    "  ".repeat(depth),
+                    instruction_to_wat(instruction)
+                ));
+                if let Instruction::Block(_) | Instruction::Loop(_) = instruction {
+                    depth += 1;
+                }
+            }
+            wat.push(')');
+        }
+
+        for (segment, (name, offset)) in self.data.iter().zip(&data) {
+            let bytes: String = segment
+                .bytes
+                .iter()
+                .map(|byte| format!("\\{byte:02x}"))
+                .collect();
+            wat.push_str(&format!(
+                "\n  (data (; {name} ;) (i32.const {offset}) \"{bytes}\")"
+            ));
+        }
+
+        wat.push_str("\n)\n");
+        wat
+    }
+}
+
+pub fn instruction_to_wat(instruction: &Instruction) -> String {
+    match instruction {
+        Instruction::I32Const(value) => format!("i32.const {value}"),
+        Instruction::I64Const(value) => format!("i64.const {value}"),
+        Instruction::F64Const(value) => format!("f64.const {}", float_to_wat(*value)),
+        Instruction::LocalGet(name) => format!("local.get ${name}"),
+        Instruction::LocalSet(name) => format!("local.set ${name}"),
+        Instruction::GlobalGet(name) => format!("global.get ${name}"),
+        Instruction::GlobalSet(name) => format!("global.set ${name}"),
+        Instruction::Block(label) => format!("block ${label}"),
+        Instruction::Loop(label) => format!("loop ${label}"),
+        Instruction::End => "end".to_string(),
+        Instruction::Br(label) => format!("br ${label}"),
+        Instruction::BrIf(label) => format!("br_if ${label}"),
+        Instruction::Call(name) => format!("call ${name}"),
+        Instruction::Return => "return".to_string(),
+        Instruction::Select => "select".to_string(),
+        Instruction::Drop => "drop".to_string(),
+        Instruction::I32Load => "i32.load".to_string(),
+        Instruction::I32Store => "i32.store".to_string(),
+        Instruction::I32Store8 => "i32.store8".to_string(),
+        Instruction::MemorySize => "memory.size".to_string(),
+        Instruction::MemoryGrow => "memory.grow".to_string(),
+        Instruction::MemoryCopy => "memory.copy".to_string(),
+        Instruction::DataOffset(name) => format!("data.offset ${name}"),
+        Instruction::DataEnd => "data.end".to_string(),
+        Instruction::Numeric(op) => op.name().to_string(),
+        Instruction::SourceLine(line) => format!("(; line {line} ;)"),
+    }
+}
+
+fn signature_to_wat(params: &[ValType], result: Option<ValType>) -> String {
+    let mut wat = String::new();
+    if !params.is_empty() {
+        let params: Vec<&str> = params.iter().map(ValType::name).collect();
+        wat.push_str(&format!(" (param {})", params.join(" ")));
+    }
+    if let Some(result) = result {
+        wat.push_str(&format!(" (result {})", result.name()));
+    }
+    wat
+}
+
+fn float_to_wat(value: f64) -> String {
+    match value {
+        _ if value.is_nan() => "nan".to_string(),
+        f64::INFINITY => "inf".to_string(),
+        f64::NEG_INFINITY => "-inf".to_string(),
+        _ => format!("{value:?}"),
+    }
+}
+
+// Everything an instruction inside of a function body can refer to by name
+#[derive(Default)]
+struct Names<'a> {
+    functions: &'a [&'a str],
+    globals: &'a [&'a str],
+    locals: &'a [&'a str],
+    data: &'a [(&'a str, u32)],
+    data_end: u32,
+
+    // Labels of the blocks the current instruction is inside of, innermost last
+    labels: Vec<&'a str>,
+}
+
+fn encode_body<'a>(
+    wasm: &mut Vec<u8>,
+    body: &'a [Instruction],
+    mut names: Names<'a>,
+) -> Result<(), String> {
+    for instruction in body {
+        match instruction {
+            Instruction::Block(label) | Instruction::Loop(label) => {
+                names.labels.push(label);
+            }
+            Instruction::End if names.labels.pop().is_none() => {
+                return Err("'end' without a block to close".to_string());
+            }
+            _ => {}
+        }
+        encode_instruction(wasm, instruction, &names)?;
+    }
+
+    if !names.labels.is_empty() {
+        return Err("A block is missing its 'end'".to_string());
+    }
+    wasm.push(0x0B);
+    Ok(())
+}
+
+fn encode_instruction(
+    wasm: &mut Vec<u8>,
+    instruction: &Instruction,
+    names: &Names,
+) -> Result<(), String> {
+    let index_of = |kind: &str, list: &[&str], name: &str| -> Result<u32, String> {
+        match list.iter().position(|n| *n == name) {
+            Some(index) => Ok(index as u32),
+            None => Err(format!("No {kind} called '{name}' in the WASM module")),
+        }
+    };
+    let label_depth = |label: &str| -> Result<u32, String> {
+        match names.labels.iter().rev().position(|l| *l == label) {
+            Some(depth) => Ok(depth as u32),
+            None => Err(format!("No block called '{label}' to break out of")),
+        }
+    };
+
+    match instruction {
+        Instruction::I32Const(value) => {
+            wasm.push(0x41);
+            write_i64(wasm, *value as i64);
+        }
+        Instruction::I64Const(value) => {
+            wasm.push(0x42);
+            write_i64(wasm, *value);
+        }
+        Instruction::F64Const(value) => {
+            wasm.push(0x44);
+            wasm.extend(value.to_le_bytes());
+        }
+        Instruction::LocalGet(name) => {
+            wasm.push(0x20);
+            write_u32(wasm, index_of("local", names.locals, name)?);
+        }
+        Instruction::LocalSet(name) => {
+            wasm.push(0x21);
+            write_u32(wasm, index_of("local", names.locals, name)?);
+        }
+        Instruction::GlobalGet(name) => {
+            wasm.push(0x23);
+            write_u32(wasm, index_of("global", names.globals, name)?);
+        }
+        Instruction::GlobalSet(name) => {
+            wasm.push(0x24);
+            write_u32(wasm, index_of("global", names.globals, name)?);
+        }
+        Instruction::Block(_) => wasm.extend([0x02, 0x40]),
+        Instruction::Loop(_) => wasm.extend([0x03, 0x40]),
+        Instruction::End => wasm.push(0x0B),
+        Instruction::Br(label) => {
+            wasm.push(0x0C);
+            write_u32(wasm, label_depth(label)?);
+        }
+        Instruction::BrIf(label) => {
+            wasm.push(0x0D);
+            write_u32(wasm, label_depth(label)?);
+        }
+        Instruction::Call(name) => {
+            wasm.push(0x10);
+            write_u32(wasm, index_of("function", names.functions, name)?);
+        }
+        Instruction::Return => wasm.push(0x0F),
+        Instruction::Select => wasm.push(0x1B),
+        Instruction::Drop => wasm.push(0x1A),
+        // Aligned to 4 bytes with no offset
+        Instruction::I32Load => wasm.extend([0x28, 0x02, 0x00]),
+        Instruction::I32Store => wasm.extend([0x36, 0x02, 0x00]),
+        Instruction::I32Store8 => wasm.extend([0x3A, 0x00, 0x00]),
+        Instruction::MemorySize => wasm.extend([0x3F, 0x00]),
+        Instruction::MemoryGrow => wasm.extend([0x40, 0x00]),
+        Instruction::MemoryCopy => wasm.extend([0xFC, 0x0A, 0x00, 0x00]),
+        Instruction::DataOffset(name) => {
+            let offset = match names.data.iter().find(|(n, _)| n == name) {
+                Some((_, offset)) => *offset,
+                None => return Err(format!("No data called '{name}' in the WASM module")),
+            };
+            wasm.push(0x41);
+            write_i64(wasm, offset as i64);
+        }
+        Instruction::DataEnd => {
+            wasm.push(0x41);
+            write_i64(wasm, names.data_end as i64);
+        }
+        Instruction::Numeric(op) => wasm.extend(op.opcode()),
+        Instruction::SourceLine(_) => {}
+    }
+
+    Ok(())
+}
+
+// Sections that only contain a count of 0 are left out
+fn write_section(wasm: &mut Vec<u8>, id: u8, section: Vec<u8>) {
+    if section == [0x00] {
+        return;
+    }
+    wasm.push(id);
+    write_u32(wasm, section.len() as u32);
+    wasm.extend(section);
+}
+
+fn write_name(wasm: &mut Vec<u8>, name: &str) {
+    write_u32(wasm, name.len() as u32);
+    wasm.extend(name.as_bytes());
+}
+
+// Unsigned LEB128
+fn write_u32(wasm: &mut Vec<u8>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            wasm.push(byte);
+            return;
+        }
+        wasm.push(byte | 0x80);
+    }
+}
+
+// Signed LEB128
+fn write_i64(wasm: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let sign_bit_clear = byte & 0x40 == 0;
+        if (value == 0 && sign_bit_clear) || (value == -1 && !sign_bit_clear) {
+            wasm.push(byte);
+            return;
+        }
+        wasm.push(byte | 0x80);
+    }
+}
@@ -0,0 +1,475 @@
+use super::wasm_module::{Function, Instruction, NumericOp, WasmModule};
+use crate::settings::BS_VAR_PREFIX;
+use std::collections::HashSet;
+
+/*
+    Cleans up a module after it has been created, before it is validated and encoded.
+    The WAT parser creates a getter, setter and exported mutable global for every variable,
+    and gives every global its starting value when the start function runs.
+
+    Every build:
+    - Getters and setters JS never uses are removed
+    - Globals that are only ever given a constant value become immutable globals that start with it
+
+    Release builds also:
+    - Fold instructions that only use constants into a single constant,
+      including globals that can't have changed, so globals worked out from them can become constant too
+    - Inline calls to tiny functions
+    - Remove unreachable instructions, and anything the module never uses
+*/
+
+// Functions with more instructions than this are never inlined
+const MAX_INLINE_SIZE: usize = 8;
+
+// Start is the function that gives globals their starting values, which runs once before anything else
+// JS is all of the JS that can use the module, so anything it uses is kept
+pub fn optimise_module(module: &mut WasmModule, start: &str, js: &str, release_build: bool) {
+    if release_build {
+        for function in &mut module.functions {
+            fold_constants(&mut function.body);
+        }
+    }
+
+    remove_unused_accessors(module, js);
+    constant_globals(module, start, js);
+
+    if release_build {
+        inline_functions(module, start);
+
+        // Globals worked out from constant globals can become constant once they are folded
+        loop {
+            use_constant_globals(module, start);
+            if !constant_globals(module, start, js) {
+                break;
+            }
+        }
+        for function in &mut module.functions {
+            remove_unreachable(&mut function.body);
+        }
+        remove_dead_code(module, start);
+    }
+}
+
+// Immutable globals are replaced with their value, then folded into what uses them
+// The start function runs first, so until it sets a global or calls anything,
+// any global it reads still has its starting value
+fn use_constant_globals(module: &mut WasmModule, start: &str) {
+    let globals = module.globals.to_owned();
+    for function in &mut module.functions {
+        let mut starting_values = function.name == start;
+        let mut set: Vec<String> = Vec::new();
+        for instruction in &mut function.body {
+            match instruction {
+                Instruction::Call(_) | Instruction::Loop(_) => starting_values = false,
+                Instruction::GlobalSet(name) => set.push(name.to_owned()),
+                Instruction::GlobalGet(name) => {
+                    if let Some(global) = globals.iter().find(|g| g.name == *name) {
+                        if !global.mutable || (starting_values && !set.contains(name)) {
+                            *instruction = global.init.to_owned();
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        fold_constants(&mut function.body);
+    }
+}
+
+// Variables are only exported so JS can use them,
+// so their getters, setters and globals are no longer exported if the JS doesn't mention them
+fn remove_unused_accessors(module: &mut WasmModule, js: &str) {
+    let getter = format!("get_{BS_VAR_PREFIX}");
+    let setter = format!("set_{BS_VAR_PREFIX}");
+    for function in &mut module.functions {
+        if let Some(export) = &function.export {
+            if (export.starts_with(&getter) || export.starts_with(&setter)) && !mentions(js, export)
+            {
+                function.export = None;
+            }
+        }
+    }
+    for global in &mut module.globals {
+        if let Some(export) = &global.export {
+            if export.starts_with(BS_VAR_PREFIX) && !mentions(js, export) {
+                global.export = None;
+            }
+        }
+    }
+
+    let called = called_functions(module);
+    module.functions.retain(|function| {
+        let accessor = function.name.starts_with(&getter) || function.name.starts_with(&setter);
+        !accessor || function.export.is_some() || called.contains(&function.name)
+    });
+    remove_unused_data(module);
+}
+
+// A global that is only set to a constant by the start function starts with that value instead
+// Its setter can only be used once the start function has run, so it can still have one
+// It becomes immutable if it has no setter and JS never changes it
+// Returns true if any global was given a constant starting value
+fn constant_globals(module: &mut WasmModule, start: &str, js: &str) -> bool {
+    let called = called_functions(module);
+    let mut changed = false;
+    for global in &mut module.globals {
+        let name = &global.name;
+        let setter = format!("set_{name}");
+        if called.contains(&setter) {
+            continue;
+        }
+
+        let set = Instruction::GlobalSet(name.to_owned());
+        let sets: Vec<&str> = module
+            .functions
+            .iter()
+            .filter(|function| function.name != setter)
+            .flat_map(|function| {
+                let count = function.body.iter().filter(|i| **i == set).count();
+                vec![function.name.as_str(); count]
+            })
+            .collect();
+
+        match sets.as_slice() {
+            [] => {}
+            [function] if *function == start => {
+                let Some(function) = module.functions.iter_mut().find(|f| f.name == start) else {
+                    continue;
+                };
+                let Some(index) = function.body.iter().position(|i| *i == set) else {
+                    continue;
+                };
+
+                // Values set inside of a block might never be set
+                let in_block = function.body[..index]
+                    .iter()
+                    .any(|i| matches!(i, Instruction::Block(_) | Instruction::Loop(_)));
+                let value = match index.checked_sub(1).map(|i| &function.body[i]) {
+                    Some(
+                        value @ (Instruction::I32Const(_)
+                        | Instruction::I64Const(_)
+                        | Instruction::F64Const(_)
+                        | Instruction::DataOffset(_)),
+                    ) if !in_block => value.to_owned(),
+                    _ => continue,
+                };
+                function.body.drain(index - 1..=index);
+                global.init = value;
+                changed = true;
+            }
+            _ => continue,
+        }
+
+        let has_setter = module.functions.iter().any(|f| f.name == setter);
+        global.mutable = has_setter || writes(js, name);
+    }
+    changed
+}
+
+// Replaces calls to tiny functions with the body of the function
+// Arguments are stored in new locals of the function calling it
+fn inline_functions(module: &mut WasmModule, start: &str) {
+    let tiny: Vec<Function> = module
+        .functions
+        .iter()
+        .filter(|function| can_inline(function) && function.name != start)
+        .cloned()
+        .collect();
+
+    for function in &mut module.functions {
+        let mut body = Vec::new();
+        let mut inlined = 0;
+        for instruction in function.body.drain(..) {
+            let callee = match &instruction {
+                Instruction::Call(name) if *name != function.name => {
+                    tiny.iter().find(|tiny| tiny.name == *name)
+                }
+                _ => None,
+            };
+            let Some(callee) = callee else {
+                body.push(instruction);
+                continue;
+            };
+
+            let local = |param: &str| format!("{}_{inlined}_{param}", callee.name);
+            for (param, val_type) in callee.params.iter().rev() {
+                function.locals.push((local(param), *val_type));
+                body.push(Instruction::LocalSet(local(param)));
+            }
+
+            for instruction in &callee.body {
+                body.push(match instruction {
+                    Instruction::LocalGet(name) => Instruction::LocalGet(local(name)),
+                    Instruction::LocalSet(name) => Instruction::LocalSet(local(name)),
+                    Instruction::Return | Instruction::SourceLine(_) => continue,
+                    _ => instruction.to_owned(),
+                });
+            }
+            inlined += 1;
+        }
+        function.body = body;
+    }
+}
+
+// Small functions that only use their arguments, with nothing to jump to
+// Return can only be the last instruction, as it would return from the function it is inlined into
+fn can_inline(function: &Function) -> bool {
+    let body: Vec<&Instruction> = function
+        .body
+        .iter()
+        .filter(|instruction| !matches!(instruction, Instruction::SourceLine(_)))
+        .collect();
+
+    body.len() <= MAX_INLINE_SIZE
+        && function.locals.is_empty()
+        && body
+            .iter()
+            .enumerate()
+            .all(|(index, instruction)| match instruction {
+                Instruction::Block(_)
+                | Instruction::Loop(_)
+                | Instruction::End
+                | Instruction::Br(_)
+                | Instruction::BrIf(_) => false,
+                Instruction::Return => index == body.len() - 1,
+                Instruction::Call(name) => *name != function.name,
+                _ => true,
+            })
+}
+
+// Instructions that only use constants are replaced with the constant they create
+// Anything that would trap is left for the WASM engine
+pub fn fold_constants(body: &mut Vec<Instruction>) {
+    let mut folded: Vec<Instruction> = Vec::new();
+    for instruction in body.drain(..) {
+        let length = folded.len();
+        match (&instruction, &folded[length.saturating_sub(2)..]) {
+            (Instruction::Numeric(op), [.., value]) if op.signature().0.len() == 1 => {
+                if let Some(result) = fold_unary(*op, value) {
+                    folded[length - 1] = result;
+                    continue;
+                }
+            }
+            (Instruction::Numeric(op), [left, right]) => {
+                if let Some(result) = fold_binary(*op, left, right) {
+                    folded.truncate(length - 2);
+                    folded.push(result);
+                    continue;
+                }
+            }
+            (
+                Instruction::Drop,
+                [.., Instruction::I32Const(_) | Instruction::I64Const(_) | Instruction::F64Const(_)],
+            ) => {
+                folded.pop();
+                continue;
+            }
+            (Instruction::BrIf(label), [.., Instruction::I32Const(condition)]) => {
+                let condition = *condition;
+                folded.pop();
+                if condition != 0 {
+                    folded.push(Instruction::Br(label.to_owned()));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        folded.push(instruction);
+    }
+    *body = folded;
+}
+
+fn fold_unary(op: NumericOp, value: &Instruction) -> Option<Instruction> {
+    use Instruction::*;
+    Some(match (op, value) {
+        (NumericOp::I64Eqz, I64Const(a)) => I32Const((*a == 0) as i32),
+        (NumericOp::F64Abs, F64Const(a)) => F64Const(a.abs()),
+        (NumericOp::F64Neg, F64Const(a)) => F64Const(-a),
+        (NumericOp::F64Ceil, F64Const(a)) => F64Const(a.ceil()),
+        (NumericOp::F64Floor, F64Const(a)) => F64Const(a.floor()),
+        (NumericOp::F64Trunc, F64Const(a)) => F64Const(a.trunc()),
+        (NumericOp::F64Sqrt, F64Const(a)) => F64Const(a.sqrt()),
+        (NumericOp::I64ExtendI32S, I32Const(a)) => I64Const(*a as i64),
+        (NumericOp::F64ConvertI32S, I32Const(a)) => F64Const(*a as f64),
+        (NumericOp::F64ConvertI64S, I64Const(a)) => F64Const(*a as f64),
+
+        // Saturates the same way 'as' does
+        (NumericOp::I64TruncSatF64S, F64Const(a)) => I64Const(*a as i64),
+        _ => return None,
+    })
+}
+
+fn fold_binary(op: NumericOp, left: &Instruction, right: &Instruction) -> Option<Instruction> {
+    use Instruction::*;
+    Some(match (left, right) {
+        (I32Const(a), I32Const(b)) => I32Const(match op {
+            NumericOp::I32LeU => ((*a as u32) <= (*b as u32)) as i32,
+            NumericOp::I32Add => a.wrapping_add(*b),
+            NumericOp::I32Sub => a.wrapping_sub(*b),
+            NumericOp::I32And => a & b,
+            NumericOp::I32Or => a | b,
+            NumericOp::I32Shl => a.wrapping_shl(*b as u32),
+            NumericOp::I32ShrU => (*a as u32).wrapping_shr(*b as u32) as i32,
+            _ => return None,
+        }),
+        (I64Const(a), I64Const(b)) => match op {
+            NumericOp::I64Eq => I32Const((a == b) as i32),
+            NumericOp::I64LtS => I32Const((a < b) as i32),
+            NumericOp::I64GtS => I32Const((a > b) as i32),
+            NumericOp::I64LeS => I32Const((a <= b) as i32),
+            NumericOp::I64GeS => I32Const((a >= b) as i32),
+            NumericOp::I64Add => I64Const(a.wrapping_add(*b)),
+            NumericOp::I64Sub => I64Const(a.wrapping_sub(*b)),
+            NumericOp::I64Mul => I64Const(a.wrapping_mul(*b)),
+
+            // Dividing by zero or overflowing traps
+            NumericOp::I64DivS => I64Const(a.checked_div(*b)?),
+            NumericOp::I64RemS if *b != 0 => I64Const(a.wrapping_rem(*b)),
+            _ => return None,
+        },
+        (F64Const(a), F64Const(b)) => match op {
+            NumericOp::F64Eq => I32Const((a == b) as i32),
+            NumericOp::F64Lt => I32Const((a < b) as i32),
+            NumericOp::F64Gt => I32Const((a > b) as i32),
+            NumericOp::F64Le => I32Const((a <= b) as i32),
+            NumericOp::F64Ge => I32Const((a >= b) as i32),
+            NumericOp::F64Add => F64Const(a + b),
+            NumericOp::F64Sub => F64Const(a - b),
+            NumericOp::F64Mul => F64Const(a * b),
+            NumericOp::F64Div => F64Const(a / b),
+
+            // WASM and Rust disagree about NaN and the sign of zero
+            NumericOp::F64Min if !a.is_nan() && !b.is_nan() && a != b => F64Const(a.min(*b)),
+            NumericOp::F64Max if !a.is_nan() && !b.is_nan() && a != b => F64Const(a.max(*b)),
+            _ => return None,
+        },
+        _ => return None,
+    })
+}
+
+// Removes instructions after a branch or return, up to the end of the block they are in
+fn remove_unreachable(body: &mut Vec<Instruction>) {
+    let mut reachable = Vec::new();
+
+    // How many blocks deep the skipped instructions are, if they are being skipped
+    let mut skipping: Option<usize> = None;
+    for instruction in body.drain(..) {
+        if let Some(depth) = skipping {
+            match instruction {
+                Instruction::Block(_) | Instruction::Loop(_) => skipping = Some(depth + 1),
+                Instruction::End if depth == 0 => {
+                    skipping = None;
+                    reachable.push(instruction);
+                }
+                Instruction::End => skipping = Some(depth - 1),
+                _ => {}
+            }
+            continue;
+        }
+
+        if let Instruction::Br(_) | Instruction::Return = instruction {
+            skipping = Some(0);
+        }
+        reachable.push(instruction);
+    }
+    *body = reachable;
+}
+
+// Removes functions, globals, imports and data that nothing uses
+// Anything exported and the start function are always kept
+fn remove_dead_code(module: &mut WasmModule, start: &str) {
+    let mut used: HashSet<String> = module
+        .functions
+        .iter()
+        .filter(|function| function.export.is_some() || function.name == start)
+        .map(|function| function.name.to_owned())
+        .collect();
+
+    // Follow every call from the functions that are kept
+    let mut unchecked: Vec<String> = used.iter().cloned().collect();
+    while let Some(name) = unchecked.pop() {
+        let Some(function) = module.functions.iter().find(|f| f.name == name) else {
+            continue;
+        };
+        for instruction in &function.body {
+            if let Instruction::Call(callee) = instruction {
+                if used.insert(callee.to_owned()) {
+                    unchecked.push(callee.to_owned());
+                }
+            }
+        }
+    }
+    module
+        .functions
+        .retain(|function| used.contains(&function.name));
+    module.imports.retain(|import| used.contains(&import.name));
+
+    let globals: HashSet<&String> = module
+        .functions
+        .iter()
+        .flat_map(|function| &function.body)
+        .filter_map(|instruction| match instruction {
+            Instruction::GlobalGet(name) | Instruction::GlobalSet(name) => Some(name),
+            _ => None,
+        })
+        .collect();
+    let globals: HashSet<String> = globals.into_iter().cloned().collect();
+    module
+        .globals
+        .retain(|global| global.export.is_some() || globals.contains(&global.name));
+
+    remove_unused_data(module);
+}
+
+fn remove_unused_data(module: &mut WasmModule) {
+    let used: HashSet<String> = module
+        .functions
+        .iter()
+        .flat_map(|function| &function.body)
+        .chain(module.globals.iter().map(|global| &global.init))
+        .filter_map(|instruction| match instruction {
+            Instruction::DataOffset(name) => Some(name.to_owned()),
+            _ => None,
+        })
+        .collect();
+    module.data.retain(|segment| used.contains(&segment.name));
+}
+
+fn called_functions(module: &WasmModule) -> HashSet<String> {
+    module
+        .functions
+        .iter()
+        .flat_map(|function| &function.body)
+        .filter_map(|instruction| match instruction {
+            Instruction::Call(name) => Some(name.to_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Whether the JS uses a name, and not just a longer name that contains it
+fn mentions(js: &str, name: &str) -> bool {
+    mention_ends(js, name).next().is_some()
+}
+
+// Whether the JS assigns to a name, or to the value of the exported global with that name
+// Strings are assigned by name, as the page defines a property that writes them into memory
+fn writes(js: &str, name: &str) -> bool {
+    mention_ends(js, name).any(|end| {
+        let rest = js[end..].trim_start_matches(".value").trim_start();
+        rest.starts_with('=') && !rest.starts_with("==")
+    })
+}
+
+// Where each use of a name in the JS ends
+fn mention_ends<'a>(js: &'a str, name: &'a str) -> impl Iterator<Item = usize> + 'a {
+    let is_identifier = |ch: char| ch.is_alphanumeric() || ch == '_' || ch == '$';
+    js.match_indices(name).filter_map(move |(index, _)| {
+        let before = js[..index].chars().next_back();
+        let after = js[index + name.len()..].chars().next();
+        match before.is_some_and(is_identifier) || after.is_some_and(is_identifier) {
+            true => None,
+            false => Some(index + name.len()),
+        }
+    })
+}
@@ -0,0 +1,411 @@
+use super::wasm_module::{instruction_to_wat, Function, Instruction, ValType, WasmModule};
+use std::collections::{HashMap, HashSet};
+
+/*
+    Checks a module is valid before it is encoded, so a page never gets a broken module.
+    Every function body is stepped through with a stack of the types of its values,
+    the same way a WASM engine validates it when the module is loaded.
+    Errors include the line of Beanstalk the bad instruction was created from if it is known.
+*/
+
+type Signature = (Vec<ValType>, Option<ValType>);
+
+pub fn validate_module(module: &WasmModule) -> Result<(), String> {
+    let mut functions: HashMap<&str, Signature> = HashMap::new();
+    for import in &module.imports {
+        add_unique(
+            &mut functions,
+            "function",
+            &import.name,
+            (import.params.to_owned(), import.result),
+        )?;
+    }
+    for function in &module.functions {
+        let params = function.params.iter().map(|(_, t)| *t).collect();
+        add_unique(
+            &mut functions,
+            "function",
+            &function.name,
+            (params, function.result),
+        )?;
+    }
+
+    let mut data = HashSet::new();
+    for segment in &module.data {
+        if module.memory.is_none() {
+            return Err("Data can't be added to a module without memory".to_string());
+        }
+        if !data.insert(segment.name.as_str()) {
+            return Err(format!(
+                "There is more than one data segment called '{}'",
+                segment.name
+            ));
+        }
+    }
+
+    // Type and whether it can be changed
+    let mut globals: HashMap<&str, (ValType, bool)> = HashMap::new();
+    for global in &module.globals {
+        add_unique(
+            &mut globals,
+            "global",
+            &global.name,
+            (global.val_type, global.mutable),
+        )?;
+
+        let init_type = match &global.init {
+            Instruction::I32Const(_) | Instruction::DataEnd => ValType::I32,
+            Instruction::DataOffset(name) if data.contains(name.as_str()) => ValType::I32,
+            Instruction::I64Const(_) => ValType::I64,
+            Instruction::F64Const(_) => ValType::F64,
+            _ => {
+                return Err(format!(
+                    "Global '{}' must start with a constant value",
+                    global.name
+                ));
+            }
+        };
+        if init_type != global.val_type {
+            return Err(format!(
+                "Global '{}' is an {} but starts with an {}",
+                global.name,
+                global.val_type.name(),
+                init_type.name()
+            ));
+        }
+    }
+
+    let mut exports = HashSet::new();
+    let export_names = module
+        .memory
+        .iter()
+        .filter_map(|memory| memory.export.as_ref())
+        .chain(module.globals.iter().filter_map(|g| g.export.as_ref()))
+        .chain(module.functions.iter().filter_map(|f| f.export.as_ref()));
+    for name in export_names {
+        if !exports.insert(name) {
+            return Err(format!("There is more than one export called '{name}'"));
+        }
+    }
+
+    for function in &module.functions {
+        validate_function(
+            function,
+            &functions,
+            &globals,
+            &data,
+            module.memory.is_some(),
+        )?;
+    }
+
+    Ok(())
+}
+
+fn add_unique<'a, T>(
+    names: &mut HashMap<&'a str, T>,
+    kind: &str,
+    name: &'a str,
+    value: T,
+) -> Result<(), String> {
+    match names.insert(name, value) {
+        Some(_) => Err(format!("There is more than one {kind} called '{name}'")),
+        None => Ok(()),
+    }
+}
+
+fn validate_function(
+    function: &Function,
+    functions: &HashMap<&str, Signature>,
+    globals: &HashMap<&str, (ValType, bool)>,
+    data: &HashSet<&str>,
+    has_memory: bool,
+) -> Result<(), String> {
+    let mut locals = HashMap::new();
+    for (name, val_type) in function.params.iter().chain(function.locals.iter()) {
+        add_unique(&mut locals, "local", name, *val_type)
+            .map_err(|err| format!("{err} in function '{}'", function.name))?;
+    }
+
+    let mut body = Body {
+        functions,
+        globals,
+        locals,
+        data,
+        has_memory,
+        result: function.result,
+        stack: Vec::new(),
+        blocks: vec![Block {
+            label: None,
+            height: 0,
+            unreachable: false,
+        }],
+    };
+
+    let mut line = None;
+    let error = |line: Option<u32>, instruction: Option<&Instruction>, message: String| {
+        let location = match line {
+            Some(line) => format!("Line {line}: "),
+            None => String::new(),
+        };
+        let instruction = match instruction {
+            Some(instruction) => format!("'{}' ", instruction_to_wat(instruction)),
+            None => String::new(),
+        };
+        format!(
+            "{location}Invalid WASM created in function '{}': {instruction}{message}",
+            function.name
+        )
+    };
+
+    for instruction in &function.body {
+        if let Instruction::SourceLine(source_line) = instruction {
+            line = Some(*source_line);
+            continue;
+        }
+        body.check(instruction)
+            .map_err(|message| error(line, Some(instruction), message))?;
+    }
+
+    if body.blocks.len() > 1 {
+        return Err(error(
+            line,
+            None,
+            "a block is missing its 'end'".to_string(),
+        ));
+    }
+    if let Some(result) = function.result {
+        body.pop(result)
+            .map_err(|message| error(line, None, format!("the function result {message}")))?;
+    }
+    if body.stack.len() > body.blocks[0].height {
+        return Err(error(
+            line,
+            None,
+            "the function leaves extra values on the stack".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+struct Block<'a> {
+    label: Option<&'a str>,
+
+    // Size of the stack when the block started
+    height: usize,
+
+    // After a branch or return nothing else in the block runs,
+    // so any value can be taken from the stack
+    unreachable: bool,
+}
+
+struct Body<'a> {
+    functions: &'a HashMap<&'a str, Signature>,
+    globals: &'a HashMap<&'a str, (ValType, bool)>,
+    locals: HashMap<&'a str, ValType>,
+    data: &'a HashSet<&'a str>,
+    has_memory: bool,
+    result: Option<ValType>,
+    stack: Vec<ValType>,
+    blocks: Vec<Block<'a>>,
+}
+
+impl<'a> Body<'a> {
+    fn check(&mut self, instruction: &'a Instruction) -> Result<(), String> {
+        match instruction {
+            Instruction::I32Const(_) => self.stack.push(ValType::I32),
+            Instruction::I64Const(_) => self.stack.push(ValType::I64),
+            Instruction::F64Const(_) => self.stack.push(ValType::F64),
+
+            Instruction::LocalGet(name) => {
+                let val_type = self.local(name)?;
+                self.stack.push(val_type);
+            }
+            Instruction::LocalSet(name) => {
+                let val_type = self.local(name)?;
+                self.pop(val_type)?;
+            }
+            Instruction::GlobalGet(name) => {
+                let (val_type, _) = self.global(name)?;
+                self.stack.push(val_type);
+            }
+            Instruction::GlobalSet(name) => {
+                let (val_type, mutable) = self.global(name)?;
+                if !mutable {
+                    return Err(format!("the global '{name}' can't be changed"));
+                }
+                self.pop(val_type)?;
+            }
+
+            Instruction::Block(label) | Instruction::Loop(label) => {
+                self.blocks.push(Block {
+                    label: Some(label),
+                    height: self.stack.len(),
+                    unreachable: false,
+                });
+            }
+            Instruction::End => {
+                // The body of the function itself doesn't have an 'end' instruction
+                if self.blocks.len() == 1 {
+                    return Err("there is no block to end".to_string());
+                }
+                if let Some(block) = self.blocks.pop() {
+                    if !block.unreachable && self.stack.len() != block.height {
+                        return Err("the block leaves values on the stack".to_string());
+                    }
+                    self.stack.truncate(block.height);
+                }
+            }
+            Instruction::Br(label) => {
+                self.label(label)?;
+                self.set_unreachable();
+            }
+            Instruction::BrIf(label) => {
+                self.pop(ValType::I32)?;
+                self.label(label)?;
+            }
+            Instruction::Call(name) => {
+                let (params, result) = match self.functions.get(name.as_str()) {
+                    Some(signature) => signature,
+                    None => return Err(format!("there is no function called '{name}'")),
+                };
+                for param in params.iter().rev() {
+                    self.pop(*param)?;
+                }
+                self.stack.extend(result);
+            }
+            Instruction::Return => {
+                if let Some(result) = self.result {
+                    self.pop(result)?;
+                }
+                self.set_unreachable();
+            }
+            Instruction::Select => {
+                self.pop(ValType::I32)?;
+                let second = self.pop_any()?;
+                let first = self.pop_any()?;
+                match (first, second) {
+                    (Some(first), Some(second)) if first != second => {
+                        return Err(format!(
+                            "can't choose between an {} and an {}",
+                            first.name(),
+                            second.name()
+                        ));
+                    }
+                    (Some(val_type), _) | (_, Some(val_type)) => self.stack.push(val_type),
+                    (None, None) => {}
+                }
+            }
+
+            Instruction::Drop => {
+                self.pop_any()?;
+            }
+
+            Instruction::I32Load => {
+                self.memory()?;
+                self.pop(ValType::I32)?;
+                self.stack.push(ValType::I32);
+            }
+            Instruction::I32Store | Instruction::I32Store8 => {
+                self.memory()?;
+                self.pop(ValType::I32)?;
+                self.pop(ValType::I32)?;
+            }
+            Instruction::MemoryCopy => {
+                self.memory()?;
+                for _ in 0..3 {
+                    self.pop(ValType::I32)?;
+                }
+            }
+            Instruction::MemorySize => {
+                self.memory()?;
+                self.stack.push(ValType::I32);
+            }
+            Instruction::MemoryGrow => {
+                self.memory()?;
+                self.pop(ValType::I32)?;
+                self.stack.push(ValType::I32);
+            }
+            Instruction::DataOffset(name) => {
+                if !self.data.contains(name.as_str()) {
+                    return Err(format!("there is no data called '{name}'"));
+                }
+                self.stack.push(ValType::I32);
+            }
+            Instruction::DataEnd => self.stack.push(ValType::I32),
+
+            Instruction::Numeric(op) => {
+                let (params, result) = op.signature();
+                for param in params.iter().rev() {
+                    self.pop(*param)?;
+                }
+                self.stack.push(result);
+            }
+
+            Instruction::SourceLine(_) => {}
+        }
+
+        Ok(())
+    }
+
+    fn pop(&mut self, expected: ValType) -> Result<(), String> {
+        match self.pop_any()? {
+            Some(found) if found != expected => Err(format!(
+                "expected an {} on the stack but found an {}",
+                expected.name(),
+                found.name()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // Returns None if the code can't be reached, so the value could be any type
+    fn pop_any(&mut self) -> Result<Option<ValType>, String> {
+        let block = &self.blocks[self.blocks.len() - 1];
+        if self.stack.len() == block.height {
+            return match block.unreachable {
+                true => Ok(None),
+                false => {
+                    Err("expected a value on the stack but there was nothing there".to_string())
+                }
+            };
+        }
+        Ok(self.stack.pop())
+    }
+
+    fn set_unreachable(&mut self) {
+        let last = self.blocks.len() - 1;
+        self.stack.truncate(self.blocks[last].height);
+        self.blocks[last].unreachable = true;
+    }
+
+    fn local(&self, name: &str) -> Result<ValType, String> {
+        match self.locals.get(name) {
+            Some(val_type) => Ok(*val_type),
+            None => Err(format!("there is no local called '{name}'")),
+        }
+    }
+
+    fn global(&self, name: &str) -> Result<(ValType, bool), String> {
+        match self.globals.get(name) {
+            Some(global) => Ok(*global),
+            None => Err(format!("there is no global called '{name}'")),
+        }
+    }
+
+    fn memory(&self) -> Result<(), String> {
+        match self.has_memory {
+            true => Ok(()),
+            false => Err("the module has no memory".to_string()),
+        }
+    }
+
+    fn label(&self, label: &str) -> Result<(), String> {
+        match self.blocks.iter().any(|block| block.label == Some(label)) {
+            true => Ok(()),
+            false => Err(format!(
+                "there is no block called '{label}' to break out of"
+            )),
+        }
+    }
+}
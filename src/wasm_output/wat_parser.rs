@@ -1,124 +1,746 @@
-use crate::{bs_types::DataType, parsers::ast_nodes::AstNode, settings::BS_VAR_PREFIX, Token};
-use colour::red_ln;
+use crate::{
+    bs_types::DataType,
+    parsers::ast_nodes::{AstNode, Reference},
+    settings::BS_VAR_PREFIX,
+    stdlib::math::{math_function_from_call, math_wasm_prelude},
+    wasm_output::wasm_module::{
+        DataSegment, Function, Global, Import, Instruction, Memory, NumericOp, ValType, WasmModule,
+    },
+    Token,
+};
 
 pub fn new_wat_var(
     id: &String,
     expr: &AstNode,
     datatype: &DataType,
-    wat: &mut String,
-    wat_global_initilisation: &mut String,
-) {
-    match datatype {
-        DataType::Float => {
-            wat.push_str(&format!(
-                "
-                \n(global ${BS_VAR_PREFIX}{id} (export \"{BS_VAR_PREFIX}{id}\") (mut f64) (f64.const 0))
-                \n(func (export \"get_{BS_VAR_PREFIX}{id}\") (result f64) (global.get ${BS_VAR_PREFIX}{id}))",
-            ));
-
-            wat_global_initilisation.push_str(&format!(
-                "(global.set ${BS_VAR_PREFIX}{id} {})",
-                expression_to_wat(&expr)
+    line_number: u32,
+    wasm: &mut WasmModule,
+    wasm_global_initilisation: &mut Vec<Instruction>,
+) -> Result<(), String> {
+    let val_type = match wat_value_type(datatype) {
+        Some(val_type) => val_type,
+        None => {
+            return Err(wat_error(
+                line_number,
+                &format!("{:?} variables can't be stored in WASM", datatype),
             ));
         }
-        DataType::Int => {
-            wat.push_str(&format!(
-                "
-                \n(global ${BS_VAR_PREFIX}{id} (export \"{BS_VAR_PREFIX}{id}\") (mut i64) (i64.const 0))
-                \n(func (export \"get_{BS_VAR_PREFIX}{id}\") (result i64) (global.get ${BS_VAR_PREFIX}{id}))",
-            ));
+    };
 
-            wat_global_initilisation.push_str(&format!(
-                "(global.set ${BS_VAR_PREFIX}{id} {})",
-                expression_to_wat(&expr)
-            ));
-        }
-        _ => {
-            red_ln!("Unsupported datatype found in WAT var creation");
+    let name = format!("{BS_VAR_PREFIX}{id}");
+    new_exported_global(id, val_type, zero(val_type), wasm);
+
+    wasm_global_initilisation.push(Instruction::SourceLine(line_number));
+    wasm_global_initilisation.extend(expression_to_wat(expr, datatype, &[], line_number)?);
+    wasm_global_initilisation.push(Instruction::GlobalSet(name));
+    Ok(())
+}
+
+// Strings are stored in linear memory as a 4 byte length followed by the UTF-8 bytes
+// The global holds the address of the string, and 0 is an empty string
+// Constant strings are put in the data section, anything else is written into memory by JS
+pub fn new_wasm_string(id: &String, value: Option<&str>, wasm: &mut WasmModule) {
+    let name = format!("{BS_VAR_PREFIX}{id}");
+    let init = match value {
+        Some(value) => {
+            wasm.data.push(DataSegment {
+                name: name.to_owned(),
+                bytes: string_bytes(value),
+            });
+            Instruction::DataOffset(name.to_owned())
         }
-    }
+        None => Instruction::I32Const(0),
+    };
+
+    new_exported_global(id, ValType::I32, init, wasm);
+}
+
+pub fn string_bytes(value: &str) -> Vec<u8> {
+    let mut bytes = (value.len() as u32).to_le_bytes().to_vec();
+    bytes.extend(value.as_bytes());
+    bytes
+}
+
+// A global JS can change, and functions JS can use to read and change it
+// The setter tells JS the value changed, so anything showing it on the page can be updated
+// Any of these JS never uses are removed when the module is optimised
+fn new_exported_global(id: &str, val_type: ValType, init: Instruction, wasm: &mut WasmModule) {
+    let name = format!("{BS_VAR_PREFIX}{id}");
+
+    // The name JS uses for the variable, so it knows which one changed
+    let id_string = format!("id_{name}");
+    wasm.data.push(DataSegment {
+        name: id_string.to_owned(),
+        bytes: string_bytes(id),
+    });
+
+    wasm.globals.push(Global {
+        name: name.to_owned(),
+        export: Some(name.to_owned()),
+        val_type,
+        mutable: true,
+        init,
+    });
+    wasm.functions.push(Function {
+        name: format!("get_{name}"),
+        export: Some(format!("get_{name}")),
+        params: Vec::new(),
+        result: Some(val_type),
+        locals: Vec::new(),
+        body: vec![Instruction::GlobalGet(name.to_owned())],
+    });
+    wasm.functions.push(Function {
+        name: format!("set_{name}"),
+        export: Some(format!("set_{name}")),
+        params: vec![("value".to_string(), val_type)],
+        result: None,
+        locals: Vec::new(),
+        body: vec![
+            Instruction::LocalGet("value".to_string()),
+            Instruction::GlobalSet(name),
+            Instruction::DataOffset(id_string),
+            Instruction::Call("value_changed".to_string()),
+        ],
+    });
+}
+
+// The module for a whole page
+// Globals are given their starting values when JS calls set_wasm_globals
+pub fn new_page_module(wasm: WasmModule, wasm_globals: Vec<Instruction>, js: &str) -> WasmModule {
+    let mut wasm = wasm;
+    wasm.functions.push(Function {
+        name: "set_wasm_globals".to_string(),
+        export: Some("set_wasm_globals".to_string()),
+        params: Vec::new(),
+        result: None,
+        locals: Vec::new(),
+        body: wasm_globals,
+    });
+    let mut module = new_base_module(wasm, js);
+
+    // Given the name of a variable when a setter changes it
+    module.imports.push(Import {
+        module: "bs".to_string(),
+        field: "changed".to_string(),
+        name: "value_changed".to_string(),
+        params: vec![ValType::I32],
+        result: None,
+    });
+    module
 }
 
-pub fn expression_to_wat(expr: &AstNode) -> String {
-    let mut wat = String::new();
+// Everything a module needs no matter where it runs
+// The JS is whatever runs alongside the module, which can call its maths helpers
+pub fn new_base_module(wasm: WasmModule, js: &str) -> WasmModule {
+    let mut module = math_wasm_prelude(&wasm, js);
+    module.functions.push(f64_rem_function());
+    module.append(wasm);
 
+    // Memory for strings, anything after the data section is given out by alloc
+    module.memory = Some(Memory {
+        pages: 1,
+        export: Some("memory".to_string()),
+    });
+    module.globals.push(Global {
+        name: "heap_pointer".to_string(),
+        export: None,
+        val_type: ValType::I32,
+        mutable: true,
+        init: Instruction::DataEnd,
+    });
+    module.functions.push(alloc_function());
+    module
+}
+
+// Locals are the arguments and variables of the function the expression is inside of
+// Any other variables are globals
+// The value left on the stack is converted to the expected type if it is a different number type
+pub fn expression_to_wat(
+    expr: &AstNode,
+    expected_type: &DataType,
+    locals: &[String],
+    line_number: u32,
+) -> Result<Vec<Instruction>, String> {
+    let (mut wasm, value_type) = typed_expression_to_wat(expr, locals, line_number)?;
+    if let Some(expected_type) = wat_number_type(expected_type) {
+        wasm.extend(convert_wat(value_type, expected_type));
+    }
+    Ok(wasm)
+}
+
+// Returns the instructions for an expression and the WASM type of the value they leave on the stack
+fn typed_expression_to_wat(
+    expr: &AstNode,
+    locals: &[String],
+    line_number: u32,
+) -> Result<(Vec<Instruction>, ValType), String> {
     match expr {
         AstNode::RuntimeExpression(nodes, datatype) => match datatype {
-            &DataType::Float => {
-                return float_expr_to_wat(nodes);
-            }
-            _ => {
-                red_ln!("Unsupported datatype found in expression sent to WAT parser");
+            // Ints in a Float expression are converted to Floats
+            DataType::Float | DataType::Int => {
+                let (mut wasm, value_type) = rpn_to_wat(nodes, locals, line_number)?;
+                let expression_type = wat_value_type(datatype).unwrap_or(value_type);
+                wasm.extend(convert_wat(value_type, expression_type));
+                Ok((wasm, expression_type))
             }
+            DataType::Bool => rpn_to_wat(nodes, locals, line_number),
+            _ => Err(wat_error(
+                line_number,
+                &format!("{:?} expressions can't be compiled to WASM", datatype),
+            )),
         },
 
         AstNode::Literal(token) => match token {
-            Token::FloatLiteral(value) => {
-                wat.push_str(&format!("\n(f64.const {})", value.to_string()));
-            }
-            Token::IntLiteral(value) => {
-                wat.push_str(&format!("\n(i64.const {})", value.to_string()));
-            }
-            _ => {
-                red_ln!("unknown literal found in expression");
+            Token::FloatLiteral(value) => Ok((vec![Instruction::F64Const(*value)], ValType::F64)),
+            Token::IntLiteral(value) => Ok((vec![Instruction::I64Const(*value)], ValType::I64)),
+            Token::BoolLiteral(value) => {
+                Ok((vec![Instruction::I32Const(*value as i32)], ValType::I32))
             }
+            _ => Err(wat_error(
+                line_number,
+                &format!("{:?} can't be compiled to WASM", token),
+            )),
         },
 
-        _ => {
-            red_ln!(
-                "Invalid AST node given to expression_to_wat (wat parser): {:?}",
-                expr
-            );
+        AstNode::VarReference(name, data_type) | AstNode::ConstReference(name, data_type) => {
+            match wat_value_type(data_type) {
+                Some(value_type) => Ok((vec![variable_to_wat(name, locals)], value_type)),
+                None => Err(wat_error(
+                    line_number,
+                    &format!("'{name}' is a {:?}, so it can't be used in WASM", data_type),
+                )),
+            }
         }
+
+        AstNode::FunctionCall(name, _, return_type) => match wat_value_type(return_type) {
+            Some(value_type) => Ok((function_call_to_wat(expr, locals, line_number)?, value_type)),
+            None => Err(wat_error(
+                line_number,
+                &format!(
+                    "'{name}' returns a {:?}, so it can't be called from WASM",
+                    return_type
+                ),
+            )),
+        },
+
+        _ => Err(wat_error(
+            line_number,
+            &format!("{:?} can't be compiled to WASM", expr),
+        )),
     }
+}
 
-    wat
+// Points at the line of the code that couldn't be compiled to WASM
+fn wat_error(line_number: u32, message: &str) -> String {
+    format!("Line {line_number}: {message}")
 }
 
-pub fn _new_wat_function() {}
+// Set is the instruction that stores the new value in the variable
+// Returns None if the operator or type of the variable can't be used in WASM
+pub fn assignment_to_wat(
+    target: &str,
+    operator: &Token,
+    value: &AstNode,
+    data_type: &DataType,
+    set: Instruction,
+    locals: &[String],
+    line_number: u32,
+) -> Result<Option<Vec<Instruction>>, String> {
+    let wat_type = match wat_value_type(data_type) {
+        Some(wat_type) => wat_type,
+        None => return Ok(None),
+    };
+    let is_float = wat_type == ValType::F64;
+    let instruction = match operator {
+        Token::Assign => None,
+        Token::AddAssign => arithmetic(&Token::Add, wat_type),
+        Token::SubtractAssign => arithmetic(&Token::Subtract, wat_type),
+        Token::MultiplyAssign => arithmetic(&Token::Multiply, wat_type),
+        Token::DivideAssign => arithmetic(&Token::Divide, wat_type),
+        Token::ModulusAssign if is_float => Some(Instruction::Call("f64_rem".to_string())),
+        Token::ModulusAssign => arithmetic(&Token::Modulus, wat_type),
+        _ => return Ok(None),
+    };
+    if instruction.is_none() && *operator != Token::Assign {
+        return Ok(None);
+    }
 
-fn float_expr_to_wat(nodes: &Vec<AstNode>) -> String {
-    let mut wat: String = String::new();
+    let mut instructions = Vec::new();
+    if instruction.is_some() {
+        instructions.push(variable_to_wat(target, locals));
+    }
+    instructions.extend(expression_to_wat(value, data_type, locals, line_number)?);
+    instructions.extend(instruction);
+    instructions.push(set);
+    Ok(Some(instructions))
+}
 
-    for node in nodes {
+// Functions that only take and return numbers are compiled to WASM
+// Returns None if anything in the function needs JS, so it can be created as a JS function instead
+// Globals are the number variables the function can use from outside of it,
+// and functions are the other functions that have already been compiled to WASM
+pub fn new_wat_function(
+    name: &str,
+    args: &[Reference],
+    body: &[AstNode],
+    return_type: &DataType,
+    globals: &[String],
+    functions: &[String],
+    line_number: u32,
+) -> Result<Option<Function>, String> {
+    let Some(result) = wat_number_type(return_type) else {
+        return Ok(None);
+    };
+
+    let mut params = Vec::new();
+    let mut locals: Vec<String> = Vec::new();
+    for arg in args {
+        // Default values are only supported by JS
+        let (None, Some(wat_type)) = (&arg.default_value, wat_number_type(&arg.data_type)) else {
+            return Ok(None);
+        };
+        params.push((format!("{BS_VAR_PREFIX}{}", arg.name), wat_type));
+        locals.push(arg.name.to_owned());
+    }
+
+    // Functions can call themselves
+    let mut functions = functions.to_vec();
+    functions.push(name.to_owned());
+
+    // The result must be on the stack at the end of the function
+    if !matches!(body.last(), Some(AstNode::Return(_))) {
+        return Ok(None);
+    }
+
+    let mut line_number = line_number;
+    let mut local_declarations = Vec::new();
+    let mut instructions = Vec::new();
+    for node in body {
         match node {
-            AstNode::Literal(token) => {
-                match token {
-                    Token::FloatLiteral(value) => {
-                        wat.push_str(&format!(" f64.const {}", value));
-                    }
-                    _ => {
-                        red_ln!("Compiler error: Wrong literal type found in expression sent to WAT parser");
+            AstNode::VarDeclaration(id, expr, _, data_type, _) => {
+                let Some(wat_type) = wat_number_type(data_type) else {
+                    return Ok(None);
+                };
+                if locals.contains(id) || !is_wat_expression(expr, &locals, globals, &functions) {
+                    return Ok(None);
+                }
+                instructions.extend(expression_to_wat(expr, data_type, &locals, line_number)?);
+                instructions.push(Instruction::LocalSet(format!("{BS_VAR_PREFIX}{id}")));
+                local_declarations.push((format!("{BS_VAR_PREFIX}{id}"), wat_type));
+                locals.push(id.to_owned());
+            }
+
+            AstNode::Assignment(target, operator, value, data_type) => {
+                if wat_number_type(data_type).is_none()
+                    || !is_wat_expression(value, &locals, globals, &functions)
+                {
+                    return Ok(None);
+                }
+                let set = match locals.contains(target) {
+                    true => Instruction::LocalSet(format!("{BS_VAR_PREFIX}{target}")),
+                    // Globals are changed with their setter, so the page is updated
+                    false if globals.contains(target) => {
+                        Instruction::Call(format!("set_{BS_VAR_PREFIX}{target}"))
                     }
+                    false => return Ok(None),
+                };
+                match assignment_to_wat(
+                    target,
+                    operator,
+                    value,
+                    data_type,
+                    set,
+                    &locals,
+                    line_number,
+                )? {
+                    Some(assignment) => instructions.extend(assignment),
+                    None => return Ok(None),
                 }
             }
 
-            AstNode::VarReference(name, _) | AstNode::ConstReference(name, _) => {
-                wat.push_str(&format!(" global.get ${BS_VAR_PREFIX}{name}"));
+            AstNode::Return(expr) => {
+                if !is_wat_expression(expr, &locals, globals, &functions) {
+                    return Ok(None);
+                }
+                instructions.extend(expression_to_wat(expr, return_type, &locals, line_number)?);
+                instructions.push(Instruction::Return);
+            }
+
+            AstNode::LineNumber(line) => {
+                line_number = *line;
+                instructions.push(Instruction::SourceLine(line_number));
+            }
+
+            AstNode::Comment(_) | AstNode::FoldedCall(_) => {}
+
+            _ => return Ok(None),
+        }
+    }
+
+    Ok(Some(Function {
+        name: format!("{BS_VAR_PREFIX}{name}"),
+        export: Some(format!("{BS_VAR_PREFIX}{name}")),
+        params,
+        result: Some(result),
+        locals: local_declarations,
+        body: instructions,
+    }))
+}
+
+pub fn wat_number_type(data_type: &DataType) -> Option<ValType> {
+    match data_type {
+        DataType::Float => Some(ValType::F64),
+        DataType::Int => Some(ValType::I64),
+        _ => None,
+    }
+}
+
+// Bools are only stored in WASM when there is no JS to hold them (0 for false, 1 for true)
+pub fn wat_value_type(data_type: &DataType) -> Option<ValType> {
+    match data_type {
+        DataType::Bool => Some(ValType::I32),
+        _ => wat_number_type(data_type),
+    }
+}
+
+fn zero(val_type: ValType) -> Instruction {
+    match val_type {
+        ValType::I32 => Instruction::I32Const(0),
+        ValType::I64 => Instruction::I64Const(0),
+        ValType::F64 => Instruction::F64Const(0.0),
+    }
+}
+
+fn variable_to_wat(name: &str, locals: &[String]) -> Instruction {
+    match locals.iter().any(|local| local == name) {
+        true => Instruction::LocalGet(format!("{BS_VAR_PREFIX}{name}")),
+        false => Instruction::GlobalGet(format!("{BS_VAR_PREFIX}{name}")),
+    }
+}
+
+// Whether an expression can be compiled to WAT from inside of a function
+pub fn is_wat_expression(
+    expr: &AstNode,
+    locals: &[String],
+    globals: &[String],
+    functions: &[String],
+) -> bool {
+    match expr {
+        AstNode::Literal(Token::FloatLiteral(_) | Token::IntLiteral(_) | Token::BoolLiteral(_))
+        | AstNode::Empty => true,
+        AstNode::VarReference(name, DataType::Float | DataType::Int | DataType::Bool)
+        | AstNode::ConstReference(name, DataType::Float | DataType::Int | DataType::Bool) => {
+            locals.contains(name) || globals.contains(name)
+        }
+        AstNode::FunctionCall(name, args, DataType::Float | DataType::Int) => {
+            (math_function_from_call(name).is_some() || functions.contains(name))
+                && is_wat_expression(args, locals, globals, functions)
+        }
+        AstNode::Tuple(items, _) => items
+            .iter()
+            .all(|item| is_wat_expression(item, locals, globals, functions)),
+        AstNode::RuntimeExpression(nodes, DataType::Float | DataType::Int | DataType::Bool) => {
+            nodes.iter().all(|node| match node {
+                AstNode::BinaryOperator(op, _) | AstNode::LogicalOperator(op, _) => matches!(
+                    op,
+                    Token::Add
+                        | Token::Subtract
+                        | Token::Multiply
+                        | Token::Divide
+                        | Token::Modulus
+                        | Token::Equal
+                        | Token::LessThan
+                        | Token::LessThanOrEqual
+                        | Token::GreaterThan
+                        | Token::GreaterThanOrEqual
+                        | Token::And
+                        | Token::Or
+                ),
+                AstNode::UnaryOperator(Token::Negative, _) => true,
+                _ => is_wat_expression(node, locals, globals, functions),
+            })
+        }
+        _ => false,
+    }
+}
+
+// Math module functions are part of the WASM prelude,
+// any other function has been compiled to WASM from Beanstalk
+fn function_call_to_wat(
+    call: &AstNode,
+    locals: &[String],
+    line_number: u32,
+) -> Result<Vec<Instruction>, String> {
+    let (name, args) = match call {
+        AstNode::FunctionCall(name, args, _) => (name, args),
+        _ => return Err(wat_error(line_number, "Expected a function call")),
+    };
+
+    let args = match &**args {
+        AstNode::Empty => Vec::new(),
+        AstNode::Tuple(values, _) => values.iter().collect(),
+        arg => vec![arg],
+    };
+
+    match math_function_from_call(name) {
+        // Ints can be given to float maths functions
+        Some(function) => {
+            let arg_type = if function.is_int {
+                DataType::Int
+            } else {
+                DataType::Float
+            };
+            let mut wasm = Vec::new();
+            for arg in args {
+                wasm.extend(expression_to_wat(arg, &arg_type, locals, line_number)?);
             }
+            Ok(function.call_to_wasm(wasm))
+        }
+        None => {
+            let mut wasm = Vec::new();
+            for arg in args {
+                wasm.extend(typed_expression_to_wat(arg, locals, line_number)?.0);
+            }
+            wasm.push(Instruction::Call(format!("{BS_VAR_PREFIX}{name}")));
+            Ok(wasm)
+        }
+    }
+}
+
+/*
+    Maths and logical expressions are in reverse polish notation,
+    so each value can be pushed onto the WASM stack in order.
+    The type of each value is tracked, so Ints mixed with Floats are converted to Floats,
+    and comparisons leave an i32 on the stack (0 for false, 1 for true).
+*/
+fn rpn_to_wat(
+    nodes: &[AstNode],
+    locals: &[String],
+    line_number: u32,
+) -> Result<(Vec<Instruction>, ValType), String> {
+    let mut stack: Vec<(Vec<Instruction>, ValType)> = Vec::new();
+    let missing_value = || {
+        wat_error(
+            line_number,
+            "Not enough values on the stack for an operator when parsing an expression into WAT",
+        )
+    };
 
-            AstNode::BinaryOperator(op, _) => {
-                let wat_op = match op {
-                    Token::Add => " f64.add",
-                    Token::Subtract => " f64.sub",
-                    Token::Multiply => " f64.mul",
-                    Token::Divide => " f64.div",
-                    _ => {
-                        red_ln!("Unsupported operator found in operator stack when parsing an expression into WAT");
-                        return String::new();
+    for node in nodes {
+        match node {
+            AstNode::UnaryOperator(Token::Negative, _) => {
+                let (value, value_type) = stack.pop().ok_or_else(missing_value)?;
+                let negated = match value_type {
+                    ValType::F64 => [value, vec![Instruction::Numeric(NumericOp::F64Neg)]].concat(),
+                    ValType::I64 => [
+                        vec![Instruction::I64Const(0)],
+                        value,
+                        vec![Instruction::Numeric(NumericOp::I64Sub)],
+                    ]
+                    .concat(),
+                    ValType::I32 => {
+                        return Err(wat_error(line_number, "Only numbers can be negated"));
                     }
                 };
+                stack.push((negated, value_type));
+            }
+
+            AstNode::BinaryOperator(op, _) | AstNode::LogicalOperator(op, _) => {
+                let (right, right_type) = stack.pop().ok_or_else(missing_value)?;
+                let (left, left_type) = stack.pop().ok_or_else(missing_value)?;
+
+                // Booleans can only be combined with 'and' and 'or'
+                if let Token::And | Token::Or = op {
+                    let instruction = if *op == Token::And {
+                        NumericOp::I32And
+                    } else {
+                        NumericOp::I32Or
+                    };
+                    stack.push((
+                        [left, right, vec![Instruction::Numeric(instruction)]].concat(),
+                        ValType::I32,
+                    ));
+                    continue;
+                }
+
+                let operand_type = match (left_type, right_type) {
+                    (ValType::F64, _) | (_, ValType::F64) => ValType::F64,
+                    _ => left_type,
+                };
+                let mut operands = left;
+                operands.extend(convert_wat(left_type, operand_type));
+                operands.extend(right);
+                operands.extend(convert_wat(right_type, operand_type));
+
+                let instruction = match arithmetic(op, operand_type) {
+                    Some(instruction) => (instruction, operand_type),
+                    None => match comparison(op, operand_type) {
+                        Some(op) => (Instruction::Numeric(op), ValType::I32),
+
+                        // WASM has no float remainder instruction
+                        None if *op == Token::Modulus && operand_type == ValType::F64 => {
+                            (Instruction::Call("f64_rem".to_string()), ValType::F64)
+                        }
+                        None => {
+                            return Err(wat_error(
+                                line_number,
+                                &format!("The operator {:?} can't be compiled to WASM", op),
+                            ));
+                        }
+                    },
+                };
+
+                operands.push(instruction.0);
+                stack.push((operands, instruction.1));
+            }
 
-                wat.push_str(wat_op);
+            AstNode::Literal(_)
+            | AstNode::VarReference(..)
+            | AstNode::ConstReference(..)
+            | AstNode::FunctionCall(..)
+            | AstNode::RuntimeExpression(..) => {
+                stack.push(typed_expression_to_wat(node, locals, line_number)?);
             }
 
             _ => {
-                red_ln!("unknown AST node found in expression when parsing float expression into WAT: {:?}", node);
+                return Err(wat_error(
+                    line_number,
+                    &format!("{:?} can't be used in an expression compiled to WASM", node),
+                ));
             }
         }
     }
 
-    wat
+    // Every value must have been used by an operator, leaving only the result
+    match (stack.pop(), stack.is_empty()) {
+        (Some(result), true) => Ok(result),
+        _ => Err(wat_error(
+            line_number,
+            "An expression didn't leave exactly one value on the stack when parsed into WAT",
+        )),
+    }
+}
+
+// Float remainders are a call to f64_rem instead
+fn arithmetic(op: &Token, val_type: ValType) -> Option<Instruction> {
+    let op = match (op, val_type) {
+        (Token::Add, ValType::F64) => NumericOp::F64Add,
+        (Token::Add, ValType::I64) => NumericOp::I64Add,
+        (Token::Subtract, ValType::F64) => NumericOp::F64Sub,
+        (Token::Subtract, ValType::I64) => NumericOp::I64Sub,
+        (Token::Multiply, ValType::F64) => NumericOp::F64Mul,
+        (Token::Multiply, ValType::I64) => NumericOp::I64Mul,
+        (Token::Divide, ValType::F64) => NumericOp::F64Div,
+        (Token::Divide, ValType::I64) => NumericOp::I64DivS,
+        (Token::Modulus, ValType::I64) => NumericOp::I64RemS,
+        _ => return None,
+    };
+    Some(Instruction::Numeric(op))
+}
+
+fn comparison(op: &Token, val_type: ValType) -> Option<NumericOp> {
+    match (op, val_type) {
+        (Token::Equal, ValType::F64) => Some(NumericOp::F64Eq),
+        (Token::Equal, ValType::I64) => Some(NumericOp::I64Eq),
+        (Token::LessThan, ValType::F64) => Some(NumericOp::F64Lt),
+        (Token::LessThan, ValType::I64) => Some(NumericOp::I64LtS),
+        (Token::LessThanOrEqual, ValType::F64) => Some(NumericOp::F64Le),
+        (Token::LessThanOrEqual, ValType::I64) => Some(NumericOp::I64LeS),
+        (Token::GreaterThan, ValType::F64) => Some(NumericOp::F64Gt),
+        (Token::GreaterThan, ValType::I64) => Some(NumericOp::I64GtS),
+        (Token::GreaterThanOrEqual, ValType::F64) => Some(NumericOp::F64Ge),
+        (Token::GreaterThanOrEqual, ValType::I64) => Some(NumericOp::I64GeS),
+        _ => None,
+    }
+}
+
+// Converts the number on top of the stack from one WASM type to another
+fn convert_wat(from: ValType, to: ValType) -> Option<Instruction> {
+    let op = match (from, to) {
+        (ValType::I64, ValType::F64) => NumericOp::F64ConvertI64S,
+        (ValType::I32, ValType::F64) => NumericOp::F64ConvertI32S,
+        (ValType::I32, ValType::I64) => NumericOp::I64ExtendI32S,
+        (ValType::F64, ValType::I64) => NumericOp::I64TruncSatF64S,
+        _ => return None,
+    };
+    Some(Instruction::Numeric(op))
+}
+
+// Bump allocator, memory is never freed
+// Returns the address of a new block of memory of the given size, aligned to 4 bytes
+// Memory is grown if the new block doesn't fit
+fn alloc_function() -> Function {
+    use Instruction::*;
+    let pointer = || GlobalGet("heap_pointer".to_string());
+
+    Function {
+        name: "alloc".to_string(),
+        export: Some("alloc".to_string()),
+        params: vec![("size".to_string(), ValType::I32)],
+        result: Some(ValType::I32),
+        locals: vec![("ptr".to_string(), ValType::I32)],
+        body: vec![
+            pointer(),
+            LocalSet("ptr".to_string()),
+            // heap_pointer = (ptr + size + 3) & -4
+            pointer(),
+            LocalGet("size".to_string()),
+            Numeric(NumericOp::I32Add),
+            I32Const(3),
+            Numeric(NumericOp::I32Add),
+            I32Const(-4),
+            Numeric(NumericOp::I32And),
+            GlobalSet("heap_pointer".to_string()),
+            // Grow memory by enough pages to fit the new end of the heap
+            Block("done".to_string()),
+            pointer(),
+            MemorySize,
+            I32Const(16),
+            Numeric(NumericOp::I32Shl),
+            Numeric(NumericOp::I32LeU),
+            BrIf("done".to_string()),
+            pointer(),
+            MemorySize,
+            I32Const(16),
+            Numeric(NumericOp::I32Shl),
+            Numeric(NumericOp::I32Sub),
+            I32Const(65535),
+            Numeric(NumericOp::I32Add),
+            I32Const(16),
+            Numeric(NumericOp::I32ShrU),
+            MemoryGrow,
+            Drop,
+            End,
+            LocalGet("ptr".to_string()),
+        ],
+    }
+}
+
+// Helper function that expressions compiled to WASM can call
+// Float remainders are truncated, so the result has the same sign as the dividend like in JS
+fn f64_rem_function() -> Function {
+    let a = || Instruction::LocalGet("a".to_string());
+    let b = || Instruction::LocalGet("b".to_string());
+    Function {
+        name: "f64_rem".to_string(),
+        export: None,
+        params: vec![
+            ("a".to_string(), ValType::F64),
+            ("b".to_string(), ValType::F64),
+        ],
+        result: Some(ValType::F64),
+        locals: Vec::new(),
+        body: vec![
+            a(),
+            a(),
+            b(),
+            Instruction::Numeric(NumericOp::F64Div),
+            Instruction::Numeric(NumericOp::F64Trunc),
+            b(),
+            Instruction::Numeric(NumericOp::F64Mul),
+            Instruction::Numeric(NumericOp::F64Sub),
+        ],
+    }
 }
 
 // if operators_stack.len() > 0 && output_stack.len() > 0 {